@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{Datelike, TimeZone, Timelike, Utc};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::{Error, Result};
+
+/// Write a zip archive with a fixed timestamp and permissions, so that the same
+/// entries always result in the same archive
+pub fn write_zip(
+    path: &Path,
+    entries: &BTreeMap<String, Vec<u8>>,
+    source_date_epoch: Option<i64>,
+) -> Result<()> {
+    let file = File::create(path)
+        .map_err(|error| Error::new(format!("Could not create {}", path.display())).from(error))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(zip_timestamp(source_date_epoch))
+        .unix_permissions(0o644);
+
+    for (name, content) in entries {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(content)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// The time of the source date epoch of a reproducible build, otherwise the earliest time a zip entry can have
+pub fn zip_timestamp(source_date_epoch: Option<i64>) -> DateTime {
+    source_date_epoch
+        .and_then(|epoch| Utc.timestamp_opt(epoch, 0).single())
+        .and_then(|time| {
+            DateTime::from_date_and_time(
+                time.year() as u16,
+                time.month() as u8,
+                time.day() as u8,
+                time.hour() as u8,
+                time.minute() as u8,
+                time.second() as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
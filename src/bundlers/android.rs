@@ -1,84 +1,160 @@
-use crate::bundlers::Bundler;
-use crate::{BundleOptions, Target};
+use crate::bundlers::android_app_bundle::AndroidAppBundle;
+use crate::bundlers::{
+    copy_file, create_directory, recreate_directory, validate_resources, Bundler,
+};
+use crate::events::Event;
+use crate::requirements::{common_requirements, Requirement};
+use crate::{
+    events, AndroidPackageFormat, BundleOptions, Context, Error, Executable, Result, Target,
+};
 use ndk_build::apk::{ApkConfig, StripConfig};
 use ndk_build::cargo::VersionCode;
 use ndk_build::manifest::{
-    Activity, AndroidManifest, Application, IntentFilter, MetaData, Permission,
+    Activity, AndroidManifest, Application, IntentFilter, IntentFilterData, MetaData, Permission,
 };
 use ndk_build::ndk::Ndk;
 use ndk_build::target::Target as AndroidTarget;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// A directory next to the app directory with the Android resources, like the launcher icon
+const RESOURCES_DIRECTORY: &str = "res";
 
 #[derive(Debug, Clone)]
 pub struct AndroidBundler {}
 
+/// Native libraries compiled for one Android ABI
+#[derive(Debug, Clone)]
+pub struct AndroidLibraries {
+    pub target: AndroidTarget,
+    pub search_path: PathBuf,
+    pub libraries: Vec<PathBuf>,
+}
+
 impl AndroidBundler {
     pub fn new() -> Self {
         Self {}
     }
-}
-
-impl Bundler for AndroidBundler {
-    fn bundle(&self, options: &BundleOptions) {
-        let bundle_location = options.bundle_location();
-        let app_name = options.app_name();
 
-        let resources_dir = bundle_location.join("res");
-        if resources_dir.exists() {
-            std::fs::remove_dir_all(resources_dir.as_path()).unwrap();
+    pub fn android_target(target: &Target) -> Option<AndroidTarget> {
+        match target {
+            Target::AArch64LinuxAndroid => Some(AndroidTarget::Arm64V8a),
+            Target::ArmV7LinuxAndroidEabi => Some(AndroidTarget::ArmV7a),
+            Target::X8664LinuxAndroid => Some(AndroidTarget::X86_64),
+            _ => None,
         }
-        std::fs::create_dir_all(resources_dir.as_path()).unwrap();
+    }
+
+    /// Prepare the `res` directory with the icons of the app and return
+    /// the resource name of the launcher icon, if there is one
+    fn prepare_resources(
+        &self,
+        options: &BundleOptions,
+        resources_dir: &Path,
+    ) -> Result<Option<String>> {
+        recreate_directory(resources_dir)?;
 
         for each in options.icons() {
             let mut copy_options = fs_extra::dir::CopyOptions::default();
             copy_options.content_only = true;
-            fs_extra::dir::copy(each, resources_dir.as_path(), &copy_options).unwrap();
+            fs_extra::dir::copy(each, resources_dir, &copy_options).context(|| {
+                Error::bundling(format!(
+                    "Could not copy icons {} to {}",
+                    each.display(),
+                    resources_dir.display()
+                ))
+            })?;
         }
-        let icon = if options.icons().is_empty() {
-            None
+        if options.icons().is_empty() {
+            Ok(None)
         } else {
-            Some("@mipmap/ic_launcher".to_string())
-        };
+            Ok(Some("@mipmap/ic_launcher".to_string()))
+        }
+    }
 
-        let android_target = match options.target() {
-            Target::AArch64LinuxAndroid => AndroidTarget::Arm64V8a,
-            _ => {
-                panic!(
-                    "Unsupported android target: {}",
-                    options.target().to_string()
-                )
+    /// Place the image and resources into the assets directory
+    fn prepare_assets(&self, options: &BundleOptions) -> Result<()> {
+        let mut assets = self.start_bundle(options)?;
+        self.bundle_resources(options, &mut assets)?;
+        assets.finish()
+    }
+
+    /// The assets directory if there is anything to package
+    fn assets(&self, options: &BundleOptions) -> Option<PathBuf> {
+        Some(self.bundled_resources_directory(options)).filter(|assets_dir| {
+            assets_dir
+                .read_dir()
+                .map_or(false, |mut entries| entries.next().is_some())
+        })
+    }
+
+    pub fn android_manifest(
+        &self,
+        options: &BundleOptions,
+        icon: Option<String>,
+    ) -> Result<AndroidManifest> {
+        let app_name = options.app_name();
+        let android_options = options.android();
+
+        let mut intent_filter = vec![IntentFilter {
+            actions: vec!["android.intent.action.MAIN".to_string()],
+            categories: vec!["android.intent.category.LAUNCHER".to_string()],
+            data: vec![],
+        }];
+        intent_filter.extend(android_options.intent_filters().iter().map(|filter| {
+            let mut data = vec![];
+            data.extend(filter.schemes.iter().map(|scheme| IntentFilterData {
+                scheme: Some(scheme.clone()),
+                ..Default::default()
+            }));
+            data.extend(filter.hosts.iter().map(|host| IntentFilterData {
+                host: Some(host.clone()),
+                ..Default::default()
+            }));
+            data.extend(filter.mime_types.iter().map(|mime_type| IntentFilterData {
+                mime_type: Some(mime_type.clone()),
+                ..Default::default()
+            }));
+            IntentFilter {
+                actions: filter.actions.clone(),
+                categories: filter.categories.clone(),
+                data,
             }
-        };
+        }));
 
         let android_activity = Activity {
             config_changes: Some("orientation|keyboardHidden|screenSize".to_string()),
             label: Some(app_name.to_string()),
-            launch_mode: None,
+            launch_mode: android_options.launch_mode().map(|mode| mode.to_string()),
             name: "android.app.NativeActivity".to_string(),
-            orientation: None,
+            orientation: android_options
+                .orientation()
+                .map(|orientation| orientation.to_string()),
             exported: None,
             resizeable_activity: None,
             always_retain_task_state: None,
             meta_data: vec![MetaData {
                 name: "android.app.lib_name".to_string(),
-                value: "vm_client_android".to_string(),
-            }],
-            intent_filter: vec![IntentFilter {
-                actions: vec!["android.intent.action.MAIN".to_string()],
-                categories: vec!["android.intent.category.LAUNCHER".to_string()],
-                data: vec![],
+                value: options.executable_name().to_string(),
             }],
+            intent_filter,
         };
 
         let android_application = Application {
-            debuggable: Some(true),
-            theme: Some("@android:style/Theme.DeviceDefault.NoActionBar.Fullscreen".to_string()),
+            debuggable: Some(android_options.debuggable(options.release())),
+            theme: Some(android_options.theme().to_string()),
             has_code: false,
             icon,
             label: app_name.to_string(),
             extract_native_libs: None,
             uses_cleartext_traffic: None,
-            meta_data: vec![],
+            meta_data: android_options
+                .meta_data()
+                .iter()
+                .map(|(name, value)| MetaData {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
             activity: android_activity,
         };
 
@@ -88,29 +164,41 @@ impl Bundler for AndroidBundler {
         manifest.version_name = Some(options.version().to_string());
         manifest.version_code = Some(
             VersionCode::from_semver(options.version().to_string().as_str())
-                .unwrap()
+                .context(|| {
+                    Error::invalid_options(format!(
+                        "Version {} can not be converted to an Android version code",
+                        options.version()
+                    ))
+                })?
                 .to_code(1),
         );
-        manifest.sdk.min_sdk_version = Some(30);
-        manifest.sdk.target_sdk_version = Some(30);
-        manifest.sdk.max_sdk_version = Some(33);
-        manifest.uses_permission = vec![
-            Permission {
-                name: "android.permission.INTERNET".to_string(),
-                max_sdk_version: None,
-            },
-            Permission {
-                name: "android.permission.ACCESS_NETWORK_STATE".to_string(),
+        manifest.sdk.min_sdk_version = Some(android_options.min_sdk_version());
+        manifest.sdk.target_sdk_version = Some(android_options.target_sdk_version());
+        manifest.sdk.max_sdk_version = Some(android_options.max_sdk_version());
+        manifest.uses_permission = android_options
+            .permissions()
+            .into_iter()
+            .map(|name| Permission {
+                name,
                 max_sdk_version: None,
-            },
-        ];
+            })
+            .collect();
+        Ok(manifest)
+    }
 
-        let ndk = Ndk::from_env().unwrap();
+    fn create_apk(
+        &self,
+        options: &BundleOptions,
+        manifest: AndroidManifest,
+        resources_dir: PathBuf,
+        assets: Option<PathBuf>,
+    ) -> Result<()> {
+        let ndk = ndk()?;
         let config = ApkConfig {
             ndk: ndk.clone(),
-            build_dir: bundle_location.clone(),
-            apk_name: app_name.to_string(),
-            assets: None,
+            build_dir: options.bundle_location(),
+            apk_name: options.app_name().to_string(),
+            assets,
             resources: Some(resources_dir),
             manifest,
             disable_aapt_compression: !options.release(),
@@ -118,22 +206,236 @@ impl Bundler for AndroidBundler {
             reverse_port_forward: Default::default(),
         };
 
-        let mut apk = config.create_apk().expect("Create APK");
-        let lib_search_path = self.compiled_libraries_directory(options);
+        let mut apk = config
+            .create_apk()
+            .context(|| Error::bundling("Failed to create an APK"))?;
 
-        self.compiled_libraries(options)
-            .iter()
-            .for_each(|compiled_library_path| {
+        for abi_libraries in self.android_libraries(options)? {
+            for library_path in &abi_libraries.libraries {
                 apk.add_lib_recursively(
-                    &compiled_library_path,
-                    android_target,
-                    &[lib_search_path.as_path()],
+                    library_path,
+                    abi_libraries.target,
+                    &[abi_libraries.search_path.as_path()],
                 )
-                .expect("Add runtime lib")
-            });
+                .context(|| {
+                    Error::bundling(format!(
+                        "Failed to add {} to the APK",
+                        library_path.display()
+                    ))
+                })?;
+            }
+        }
 
         apk.add_pending_libs_and_align()
-            .expect("Add pending libs and align");
+            .context(|| Error::bundling("Failed to align the APK"))?;
+
+        events::emit(Event::archive_created(
+            options
+                .bundle_location()
+                .join(format!("{}.apk", options.app_name())),
+        ));
+        Ok(())
+    }
+
+    fn create_app_bundle(
+        &self,
+        options: &BundleOptions,
+        manifest: AndroidManifest,
+        resources_dir: Option<PathBuf>,
+        assets: Option<PathBuf>,
+    ) -> Result<()> {
+        let ndk = ndk()?;
+        let android_jar = ndk
+            .android_jar(options.android().target_sdk_version())
+            .context(|| {
+                Error::missing_tool(format!(
+                    "Could not find android.jar for the target sdk version {}",
+                    options.android().target_sdk_version()
+                ))
+                .hint("Install the Android SDK platform of the target sdk version")
+            })?;
+        let aapt2 = options.android().aapt2().context(|| {
+            Error::missing_tool("aapt2 is required to create an Android App Bundle").hint(
+                "Add the Android SDK build-tools directory to the PATH or pass --android-aapt2",
+            )
+        })?;
+
+        let app_bundle = AndroidAppBundle::new(
+            aapt2,
+            android_jar,
+            options.bundle_location(),
+            options.app_name(),
+            options.source_date_epoch(),
+        );
+
+        let app_bundle = app_bundle
+            .create(
+                &manifest,
+                resources_dir.as_deref(),
+                assets.as_deref(),
+                &self.android_libraries(options)?,
+            )
+            .context(|| Error::bundling("Failed to create an Android App Bundle"))?;
+
+        events::emit(Event::archive_created(app_bundle));
+        Ok(())
+    }
+
+    /// Collect the native libraries of every compiled ABI. The main library is renamed
+    /// after the executable name, so that it matches the `android.app.lib_name` of the activity
+    pub fn android_libraries(&self, options: &BundleOptions) -> Result<Vec<AndroidLibraries>> {
+        options
+            .target_variants()?
+            .iter()
+            .map(|target_options| {
+                let android_target =
+                    Self::android_target(target_options.target()).context(|| {
+                        Error::invalid_options(format!(
+                            "Unsupported android target: {}",
+                            target_options.target().to_string()
+                        ))
+                    })?;
+
+                let executable_directory = self
+                    .bundled_executable_directory(options)
+                    .join(android_target.android_abi());
+                if !executable_directory.exists() {
+                    create_directory(&executable_directory)?;
+                }
+
+                let android_executables = target_options
+                    .executables()
+                    .iter()
+                    .filter(|executable| **executable == Executable::Android)
+                    .collect::<Vec<&Executable>>();
+
+                let mut libraries = vec![];
+                for executable in &android_executables {
+                    let compiled_executable_path =
+                        target_options.compiled_executable_path(executable);
+                    let bundled_executable_path = executable_directory
+                        .join(target_options.bundled_executable_name(executable));
+                    copy_file(&compiled_executable_path, &bundled_executable_path)?;
+                    libraries.push(bundled_executable_path);
+                }
+
+                let compiled_executables = android_executables
+                    .iter()
+                    .map(|executable| target_options.compiled_executable_path(executable))
+                    .collect::<Vec<PathBuf>>();
+
+                libraries.extend(
+                    self.compiled_libraries(target_options)?
+                        .into_iter()
+                        .filter(|library| !compiled_executables.contains(library)),
+                );
+
+                Ok(AndroidLibraries {
+                    target: android_target,
+                    search_path: self.compiled_libraries_directory(target_options),
+                    libraries,
+                })
+            })
+            .collect()
+    }
+}
+
+fn ndk() -> Result<Ndk> {
+    Ndk::from_env().context(|| {
+        Error::missing_tool("Could not find the Android NDK")
+            .hint("Install the Android NDK and set ANDROID_NDK_ROOT to its location")
+    })
+}
+
+impl Bundler for AndroidBundler {
+    fn requirements(&self, target: &Target) -> Vec<Requirement> {
+        let mut requirements = common_requirements(target);
+        requirements.push(
+            Requirement::environment_variable(
+                &[
+                    "ANDROID_NDK_ROOT",
+                    "ANDROID_NDK_PATH",
+                    "ANDROID_NDK_HOME",
+                    "NDK_HOME",
+                ],
+                "to locate the Android NDK",
+            )
+            .hint("Install the Android NDK and set ANDROID_NDK_ROOT to its location"),
+        );
+        requirements.push(
+            Requirement::environment_variable(
+                &["ANDROID_HOME", "ANDROID_SDK_ROOT"],
+                "to locate the Android SDK build tools and platforms",
+            )
+            .hint("Install the Android SDK and set ANDROID_HOME to its location"),
+        );
+        requirements.push(
+            Requirement::tool("cargo-apk", "to compile the Android executable")
+                .hint("Run `cargo install cargo-apk`"),
+        );
+        requirements.push(
+            Requirement::tool("aapt2", "to create an Android App Bundle")
+                .hint(
+                    "Add the Android SDK build-tools directory to the PATH or pass --android-aapt2",
+                )
+                .optional(),
+        );
+        requirements
+    }
+
+    fn validate(&self, options: &BundleOptions) -> Result<()> {
+        options.android().validate()?;
+        validate_resources(options)?;
+
+        for target_options in options.target_variants()? {
+            if Self::android_target(target_options.target()).is_none() {
+                return Error::invalid_options(format!(
+                    "Unsupported android target: {}",
+                    target_options.target().to_string()
+                ))
+                .into();
+            }
+        }
+        Ok(())
+    }
+
+    /// The assets are placed first, so that the SBOM and the integrity manifest are written
+    /// into them before they are packaged
+    fn bundle(&self, options: &BundleOptions) -> Result<()> {
+        self.prepare_assets(options)
+    }
+
+    fn package(&self, options: &BundleOptions) -> Result<()> {
+        let bundle_location = options.bundle_location();
+
+        let resources_dir = bundle_location.join(RESOURCES_DIRECTORY);
+        let icon = self.prepare_resources(options, &resources_dir)?;
+        let icon_exists = icon.is_some();
+        let manifest = self.android_manifest(options, icon)?;
+        let assets = self.assets(options);
+
+        match options.android().package_format() {
+            AndroidPackageFormat::Apk => self.create_apk(options, manifest, resources_dir, assets),
+            AndroidPackageFormat::Aab => {
+                let resources = icon_exists.then_some(resources_dir);
+                self.create_app_bundle(options, manifest, resources, assets)
+            }
+        }
+    }
+
+    fn bundled_app_directory(&self, options: &BundleOptions) -> PathBuf {
+        options.bundle_location().join(options.app_name())
+    }
+
+    fn bundled_archives(&self, options: &BundleOptions) -> Vec<PathBuf> {
+        let bundle_location = options.bundle_location();
+        vec![
+            bundle_location.join(format!("{}.apk", options.app_name())),
+            bundle_location.join(format!("{}-unaligned.apk", options.app_name())),
+            bundle_location.join(format!("{}.aab", options.app_name())),
+            bundle_location.join(AndroidAppBundle::WORK_DIRECTORY),
+            bundle_location.join(RESOURCES_DIRECTORY),
+        ]
     }
 
     fn bundled_executable_directory(&self, options: &BundleOptions) -> PathBuf {
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ndk_build::manifest::AndroidManifest;
+use zip::ZipArchive;
+
+use crate::archives::write_zip;
+use crate::bundlers::android::AndroidLibraries;
+use crate::checksums::{files_in_directory, relative_path};
+use crate::{Error, Result};
+
+/// A version of the bundletool the produced bundle config is compatible with
+const BUNDLETOOL_VERSION: &str = "1.15.6";
+
+/// Creates an Android App Bundle (.aab) for uploading to the Play Store.
+/// The manifest and resources are converted to the protobuf format by aapt2,
+/// and are packed with assets and native libraries into the `base` module.
+#[derive(Debug, Clone)]
+pub struct AndroidAppBundle {
+    aapt2: PathBuf,
+    android_jar: PathBuf,
+    build_dir: PathBuf,
+    name: String,
+    source_date_epoch: Option<i64>,
+}
+
+impl AndroidAppBundle {
+    /// A directory within the build directory with the intermediate files of the bundle
+    pub const WORK_DIRECTORY: &'static str = "aab";
+
+    pub fn new(
+        aapt2: impl Into<PathBuf>,
+        android_jar: impl Into<PathBuf>,
+        build_dir: impl Into<PathBuf>,
+        name: impl Into<String>,
+        source_date_epoch: Option<i64>,
+    ) -> Self {
+        Self {
+            aapt2: aapt2.into(),
+            android_jar: android_jar.into(),
+            build_dir: build_dir.into(),
+            name: name.into(),
+            source_date_epoch,
+        }
+    }
+
+    pub fn create(
+        &self,
+        manifest: &AndroidManifest,
+        resources: Option<&Path>,
+        assets: Option<&Path>,
+        libraries: &[AndroidLibraries],
+    ) -> Result<PathBuf> {
+        let work_dir = self.build_dir.join(Self::WORK_DIRECTORY);
+        if work_dir.exists() {
+            fs::remove_dir_all(&work_dir)?;
+        }
+        fs::create_dir_all(&work_dir)?;
+
+        manifest.write_to(&work_dir)?;
+
+        let linked = work_dir.join("linked.zip");
+        let mut link = Command::new(&self.aapt2);
+        link.arg("link")
+            .arg("--proto-format")
+            .arg("--auto-add-overlay")
+            .arg("-o")
+            .arg(&linked)
+            .arg("-I")
+            .arg(&self.android_jar)
+            .arg("--manifest")
+            .arg(work_dir.join("AndroidManifest.xml"));
+
+        if let Some(resources) = resources {
+            let compiled_resources = work_dir.join("compiled-resources.zip");
+            let mut compile = Command::new(&self.aapt2);
+            compile
+                .arg("compile")
+                .arg("--dir")
+                .arg(resources)
+                .arg("-o")
+                .arg(&compiled_resources);
+            run(&mut compile)?;
+            link.arg(&compiled_resources);
+        }
+        run(&mut link)?;
+
+        // BTreeMap keeps the entries sorted which makes the archive deterministic
+        let mut entries: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+
+        let mut linked_archive = ZipArchive::new(File::open(&linked)?)?;
+        for index in 0..linked_archive.len() {
+            let mut entry = linked_archive.by_index(index)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let mut content = vec![];
+            entry.read_to_end(&mut content)?;
+            let path = match entry.name() {
+                "AndroidManifest.xml" => "base/manifest/AndroidManifest.xml".to_string(),
+                name => format!("base/{}", name),
+            };
+            entries.insert(path, content);
+        }
+
+        if let Some(assets) = assets {
+            for file in files_in_directory(assets)? {
+                entries.insert(
+                    format!("base/assets/{}", relative_path(assets, &file)),
+                    fs::read(&file)?,
+                );
+            }
+        }
+
+        for abi_libraries in libraries {
+            for library in &abi_libraries.libraries {
+                let file_name = library
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| {
+                        Error::new(format!("Invalid library name {}", library.display()))
+                    })?;
+                entries.insert(
+                    format!(
+                        "base/lib/{}/{}",
+                        abi_libraries.target.android_abi(),
+                        file_name
+                    ),
+                    fs::read(library)?,
+                );
+            }
+        }
+
+        entries.insert("BundleConfig.pb".to_string(), bundle_config());
+
+        let bundle = self.build_dir.join(format!("{}.aab", &self.name));
+        write_zip(&bundle, &entries, self.source_date_epoch)?;
+        Ok(bundle)
+    }
+}
+
+/// A protobuf encoded `BundleConfig` message that only specifies the bundletool version:
+/// `BundleConfig { bundletool: Bundletool { version } }`
+fn bundle_config() -> Vec<u8> {
+    let mut bundletool = vec![0x12, BUNDLETOOL_VERSION.len() as u8];
+    bundletool.extend_from_slice(BUNDLETOOL_VERSION.as_bytes());
+
+    let mut config = vec![0x0a, bundletool.len() as u8];
+    config.extend(bundletool);
+    config
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let status = command
+        .status()
+        .map_err(|error| Error::new(format!("Failed to start {:?}", command)).from(error))?;
+    if !status.success() {
+        return Error::new(format!("{:?} did not finish successfully", command)).into();
+    }
+    Ok(())
+}
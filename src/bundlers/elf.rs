@@ -0,0 +1,319 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::checksums::to_hex;
+use crate::{Context, Error, Result};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const SECTION_TYPE_NOTE: u32 = 7;
+const NOTE_TYPE_GNU_BUILD_ID: u32 = 3;
+const NOTE_NAME_GNU: &[u8] = b"GNU\0";
+
+/// A section of an ELF binary as described by its section header
+#[derive(Debug, Clone)]
+pub struct ElfSection {
+    pub name: String,
+    pub kind: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Reads the section headers of an ELF executable or shared library without loading the whole file,
+/// which may be hundreds of megabytes with the debug info
+#[derive(Debug)]
+pub struct ElfFile {
+    file: File,
+    /// The size of the file, nothing is read past it
+    length: u64,
+    is_64: bool,
+    is_little_endian: bool,
+    sections: Vec<ElfSection>,
+}
+
+impl ElfFile {
+    /// Open a binary, or return None if it is not an ELF file
+    pub fn open(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let mut file =
+            File::open(path).context(|| Error::io(format!("Could not open {}", path.display())))?;
+        let length = file
+            .metadata()
+            .context(|| Error::io(format!("Could not read the metadata of {}", path.display())))?
+            .len();
+
+        let mut identification = [0u8; 16];
+        if file.read_exact(&mut identification).is_err() || identification[..4] != ELF_MAGIC {
+            return Ok(None);
+        }
+
+        let mut elf = Self {
+            file,
+            length,
+            is_64: identification[4] == ELF_CLASS_64,
+            is_little_endian: identification[5] == ELF_DATA_LITTLE_ENDIAN,
+            sections: vec![],
+        };
+        elf.sections = elf.read_sections().context(|| {
+            Error::bundling(format!("Could not read the sections of {}", path.display()))
+        })?;
+        Ok(Some(elf))
+    }
+
+    /// DWARF sections, possibly compressed, that are left in the binary by the compiler
+    pub fn has_debug_info(&self) -> bool {
+        self.sections.iter().any(|section| {
+            section.name.starts_with(".debug_") || section.name.starts_with(".zdebug_")
+        })
+    }
+
+    /// A lowercase hex encoded GNU build-id from the `.note.gnu.build-id` note
+    pub fn build_id(&mut self) -> Result<Option<String>> {
+        let notes = self
+            .sections
+            .iter()
+            .filter(|section| section.kind == SECTION_TYPE_NOTE)
+            .cloned()
+            .collect::<Vec<ElfSection>>();
+
+        for section in notes {
+            let data = self.read(section.offset, section.size)?;
+            let mut offset = 0;
+            while offset + 12 <= data.len() {
+                let name_size = self.u32_in(&data, offset) as usize;
+                let description_size = self.u32_in(&data, offset + 4) as usize;
+                let kind = self.u32_in(&data, offset + 8);
+                let name_start = offset + 12;
+                let description_start = name_start + aligned(name_size);
+                let description_end = description_start + description_size;
+                if description_end > data.len() {
+                    break;
+                }
+                if kind == NOTE_TYPE_GNU_BUILD_ID
+                    && &data[name_start..name_start + name_size] == NOTE_NAME_GNU
+                {
+                    return Ok(Some(to_hex(&data[description_start..description_end])));
+                }
+                offset = description_start + aligned(description_size);
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_sections(&mut self) -> Result<Vec<ElfSection>> {
+        let (offset_position, entry_size_position) = if self.is_64 {
+            (0x28, 0x3a)
+        } else {
+            (0x20, 0x2e)
+        };
+        let header = self.read(0, if self.is_64 { 0x40 } else { 0x34 })?;
+        let headers_offset = self.word_in(&header, offset_position);
+        let entry_size = self.u16_in(&header, entry_size_position) as u64;
+        let count = self.u16_in(&header, entry_size_position + 2) as u64;
+        let names_index = self.u16_in(&header, entry_size_position + 4) as u64;
+        let minimum_entry_size = if self.is_64 { 0x40 } else { 0x28 };
+        if headers_offset == 0 || count == 0 || entry_size < minimum_entry_size {
+            return Ok(vec![]);
+        }
+
+        let headers = self.read(headers_offset, entry_size * count)?;
+        let mut sections = vec![];
+        let mut name_offsets = vec![];
+        for index in 0..count as usize {
+            let entry = index * entry_size as usize;
+            name_offsets.push(self.u32_in(&headers, entry) as usize);
+            let (offset, size) = if self.is_64 {
+                (
+                    self.word_in(&headers, entry + 0x18),
+                    self.word_in(&headers, entry + 0x20),
+                )
+            } else {
+                (
+                    self.word_in(&headers, entry + 0x10),
+                    self.word_in(&headers, entry + 0x14),
+                )
+            };
+            sections.push(ElfSection {
+                name: String::new(),
+                kind: self.u32_in(&headers, entry + 4),
+                offset,
+                size,
+            });
+        }
+
+        if let Some(names_section) = sections.get(names_index as usize).cloned() {
+            let names = self.read(names_section.offset, names_section.size)?;
+            for (section, name_offset) in sections.iter_mut().zip(name_offsets) {
+                if let Some(name) = names.get(name_offset..) {
+                    let end = name
+                        .iter()
+                        .position(|byte| *byte == 0)
+                        .unwrap_or(name.len());
+                    section.name = String::from_utf8_lossy(&name[..end]).to_string();
+                }
+            }
+        }
+        Ok(sections)
+    }
+
+    /// Read a part of the file, failing instead of allocating a buffer
+    /// when a corrupted header points past the end of the file
+    fn read(&mut self, offset: u64, size: u64) -> Result<Vec<u8>> {
+        if offset
+            .checked_add(size)
+            .map_or(true, |end| end > self.length)
+        {
+            return Error::bundling(format!(
+                "{} bytes at {} are past the end of the file of {} bytes",
+                size, offset, self.length
+            ))
+            .into();
+        }
+        let mut data = vec![0u8; size as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn u16_in(&self, data: &[u8], offset: usize) -> u16 {
+        let bytes = [data[offset], data[offset + 1]];
+        if self.is_little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32_in(&self, data: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&data[offset..offset + 4]);
+        if self.is_little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    /// An address or an offset, which is 8 bytes long in 64-bit files and 4 bytes long in 32-bit ones
+    fn word_in(&self, data: &[u8], offset: usize) -> u64 {
+        if !self.is_64 {
+            return self.u32_in(data, offset) as u64;
+        }
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&data[offset..offset + 8]);
+        if self.is_little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        }
+    }
+}
+
+/// Names and descriptions of notes are padded to 4 bytes
+fn aligned(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const SECTION_TYPE_STRING_TABLE: u32 = 3;
+    const SECTION_TYPE_PROGRAM_BITS: u32 = 1;
+
+    /// A 64-bit little endian ELF file with only the sections and without any code
+    fn elf_file(build_id: Option<&[u8]>, debug_info: bool) -> Vec<u8> {
+        let names = b"\0.shstrtab\0.note.gnu.build-id\0.debug_info\0";
+        let mut data = vec![0u8; 0x40];
+        data[..4].copy_from_slice(&ELF_MAGIC);
+        data[4] = ELF_CLASS_64;
+        data[5] = ELF_DATA_LITTLE_ENDIAN;
+
+        // (name offset, type, offset, size) of each section after the null one
+        let mut sections = vec![(1u32, SECTION_TYPE_STRING_TABLE, data.len(), names.len())];
+        data.extend_from_slice(names);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+
+        if let Some(build_id) = build_id {
+            let note_offset = data.len();
+            data.extend_from_slice(&4u32.to_le_bytes());
+            data.extend_from_slice(&(build_id.len() as u32).to_le_bytes());
+            data.extend_from_slice(&NOTE_TYPE_GNU_BUILD_ID.to_le_bytes());
+            data.extend_from_slice(NOTE_NAME_GNU);
+            data.extend_from_slice(build_id);
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            sections.push((11, SECTION_TYPE_NOTE, note_offset, data.len() - note_offset));
+        }
+
+        if debug_info {
+            sections.push((30, SECTION_TYPE_PROGRAM_BITS, data.len(), 4));
+            data.extend_from_slice(&[0u8; 4]);
+        }
+
+        let headers_offset = data.len();
+        data.extend_from_slice(&[0u8; 0x40]);
+        for (name, kind, offset, size) in &sections {
+            let mut header = [0u8; 0x40];
+            header[..4].copy_from_slice(&name.to_le_bytes());
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[0x18..0x20].copy_from_slice(&(*offset as u64).to_le_bytes());
+            header[0x20..0x28].copy_from_slice(&(*size as u64).to_le_bytes());
+            data.extend_from_slice(&header);
+        }
+
+        data[0x28..0x30].copy_from_slice(&(headers_offset as u64).to_le_bytes());
+        data[0x3a..0x3c].copy_from_slice(&0x40u16.to_le_bytes());
+        data[0x3c..0x3e].copy_from_slice(&(sections.len() as u16 + 1).to_le_bytes());
+        data[0x3e..0x40].copy_from_slice(&1u16.to_le_bytes());
+        data
+    }
+
+    fn test_file(name: &str, content: &[u8]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("gtoolkit-vm-builder-elf-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join(name);
+        std::fs::write(&file, content).unwrap();
+        file
+    }
+
+    #[test]
+    fn build_id_and_debug_info() {
+        let file = test_file(
+            "with-build-id",
+            &elf_file(Some(&[0x7f, 0x9c, 0xf1, 0xd0, 0xfb]), true),
+        );
+        let mut elf = ElfFile::open(&file).unwrap().unwrap();
+        assert!(elf.has_debug_info());
+        assert_eq!(elf.build_id().unwrap(), Some("7f9cf1d0fb".to_string()));
+    }
+
+    #[test]
+    fn without_build_id_and_debug_info() {
+        let file = test_file("without-build-id", &elf_file(None, false));
+        let mut elf = ElfFile::open(&file).unwrap().unwrap();
+        assert!(!elf.has_debug_info());
+        assert_eq!(elf.build_id().unwrap(), None);
+    }
+
+    #[test]
+    fn not_an_elf_file() {
+        let file = test_file("not-elf", b"#!/bin/sh\necho hello\n");
+        assert!(ElfFile::open(&file).unwrap().is_none());
+    }
+
+    #[test]
+    fn section_headers_past_the_end() {
+        let mut data = elf_file(None, false);
+        data[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        let file = test_file("truncated", &data);
+        assert!(ElfFile::open(&file).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::bundlers::{create_directory, recreate_directory};
+use crate::checksums::{files_in_directory, relative_path, sha256_of_file, to_hex};
+use crate::events::Event;
+use crate::{events, reproducible, BundleOptions, Context, Error, Result};
+
+/// A directory within the compilation location with what was placed into each bundled directory
+const BUNDLE_STATE_DIRECTORY: &str = "bundle-state";
+
+/// Size, modification time and SHA-256 of a file when it was last seen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FileState {
+    size: u64,
+    /// Nanoseconds since the unix epoch
+    modified: u64,
+    /// Only known when the content had to be compared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/// A file copied into the bundle, with its source as it was copied
+/// and the bundled file as it was left after post-processing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundledFile {
+    source: PathBuf,
+    source_state: FileState,
+    bundled_state: FileState,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BundleState {
+    directory: PathBuf,
+    /// Post-processing of the bundled binaries depends on whether the debug symbols are included
+    #[serde(default)]
+    include_debug_symbols: bool,
+    /// Bundled files by their path relative to the directory
+    files: BTreeMap<String, BundledFile>,
+}
+
+/// Places files into a bundled directory, copying only the ones that changed since the previous bundling
+/// and removing the files that are no longer part of it when finished.
+/// With `--fresh` the directory is removed and everything is copied again
+#[derive(Debug)]
+pub struct IncrementalBundle {
+    directory: PathBuf,
+    state_file: PathBuf,
+    previous: BundleState,
+    current: BundleState,
+    /// All files placed during this bundling, either copied or written
+    placed: BTreeSet<PathBuf>,
+    changed: BTreeSet<PathBuf>,
+    /// Debug symbols placed into a separate directory instead of the bundled one
+    debug_symbols: Option<Box<IncrementalBundle>>,
+    /// Set when building reproducibly, the bundled files are normalized before their state is saved
+    source_date_epoch: Option<i64>,
+}
+
+impl IncrementalBundle {
+    pub fn start(directory: impl AsRef<Path>, options: &BundleOptions) -> Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        let state_file = options
+            .compilation_location()
+            .join(BUNDLE_STATE_DIRECTORY)
+            .join(state_file_name(&directory));
+
+        let previous = if options.fresh() {
+            recreate_directory(&directory)?;
+            BundleState::default()
+        } else {
+            create_directory(&directory)?;
+            fs::read_to_string(&state_file)
+                .ok()
+                .and_then(|content| serde_json::from_str::<BundleState>(&content).ok())
+                .filter(|state| {
+                    state.directory == directory
+                        && state.include_debug_symbols == options.include_debug_symbols()
+                })
+                .unwrap_or_default()
+        };
+
+        Ok(Self {
+            current: BundleState {
+                directory: directory.clone(),
+                include_debug_symbols: options.include_debug_symbols(),
+                files: Default::default(),
+            },
+            directory,
+            state_file,
+            previous,
+            placed: Default::default(),
+            changed: Default::default(),
+            debug_symbols: None,
+            source_date_epoch: options
+                .source_date_epoch()
+                .filter(|_| options.reproducible()),
+        })
+    }
+
+    /// Place the debug symbols into a separate directory that mirrors the bundled one, to be archived on their own
+    pub fn separate_debug_symbols(
+        &mut self,
+        directory: impl AsRef<Path>,
+        options: &BundleOptions,
+    ) -> Result<()> {
+        self.debug_symbols = Some(Box::new(Self::start(directory, options)?));
+        Ok(())
+    }
+
+    /// Where the debug symbols go, the bundled directory itself unless they are separated
+    pub fn debug_symbols(&mut self) -> &mut IncrementalBundle {
+        match self.debug_symbols {
+            Some(ref mut debug_symbols) => debug_symbols,
+            None => self,
+        }
+    }
+
+    /// A path of the debug symbols for a path within the bundled directory
+    pub fn debug_symbols_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        match &self.debug_symbols {
+            Some(debug_symbols) => debug_symbols
+                .directory
+                .join(path.strip_prefix(&self.directory).unwrap_or(path)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Copy a file into the bundle unless it is the same as the one copied the previous time
+    /// and the bundled copy was not touched since. Returns true if the file was copied
+    pub fn copy_file(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<bool> {
+        let from = from.as_ref();
+        let to = to.as_ref();
+        let path = relative_path(&self.directory, to);
+
+        let previous = self
+            .previous
+            .files
+            .get(&path)
+            .filter(|previous| previous.source == from);
+        let previous_source_size = previous.map(|previous| previous.source_state.size);
+        if let Some(previous) = previous {
+            if let (Some(source_state), Some(bundled_state)) = (
+                previous.source_state.current(from)?,
+                previous.bundled_state.current(to)?,
+            ) {
+                events::emit(Event::FileUpToDate {
+                    path: to.to_path_buf(),
+                });
+                self.current.files.insert(
+                    path,
+                    BundledFile {
+                        source: from.to_path_buf(),
+                        source_state,
+                        bundled_state,
+                    },
+                );
+                self.placed.insert(to.to_path_buf());
+                return Ok(false);
+            }
+        }
+
+        if let Some(parent) = to.parent() {
+            create_directory(parent)?;
+        }
+        // a bundled file may have been made read-only by the previous post-processing
+        if to.exists() {
+            fs::remove_file(to)
+                .context(|| Error::io(format!("Could not remove {}", to.display())))?;
+        }
+        fs::copy(from, to).context(|| {
+            Error::bundling(format!(
+                "Could not copy {} to {}",
+                from.display(),
+                to.display()
+            ))
+        })?;
+        events::emit(Event::file_copied(from, to));
+
+        let mut source_state = FileState::of(from)?;
+        // only the modification time may have changed, for example when the source was rebuilt,
+        // hash it so that next time an identical rebuild is not copied again
+        if previous_source_size == Some(source_state.size) {
+            source_state = source_state.with_sha256(from)?;
+        }
+        let bundled_state = FileState::of(to)?;
+        self.current.files.insert(
+            path,
+            BundledFile {
+                source: from.to_path_buf(),
+                source_state,
+                bundled_state,
+            },
+        );
+        self.placed.insert(to.to_path_buf());
+        self.changed.insert(to.to_path_buf());
+        Ok(true)
+    }
+
+    /// Copy files and directories with their content into a directory of the bundle.
+    /// Returns all bundled files, whether they were copied or not
+    pub fn copy_items(
+        &mut self,
+        items: &[PathBuf],
+        directory: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>> {
+        self.copy_items_within(items, directory.as_ref(), &mut vec![])
+    }
+
+    /// Copy items knowing the directories that are being copied around them,
+    /// so that a linked directory that contains its own link is reported instead of copied endlessly
+    fn copy_items_within(
+        &mut self,
+        items: &[PathBuf],
+        directory: &Path,
+        copied_directories: &mut Vec<PathBuf>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut bundled_files = vec![];
+        for item in items {
+            let bundled_item = directory.join(
+                item.file_name()
+                    .context(|| Error::bundling(format!("Could not copy {}", item.display())))?,
+            );
+            if item.is_dir() {
+                let canonical_item = item
+                    .canonicalize()
+                    .context(|| Error::io(format!("Could not resolve {}", item.display())))?;
+                if copied_directories.contains(&canonical_item) {
+                    return Error::bundling(format!(
+                        "Could not copy {}, it links to {} which contains it",
+                        item.display(),
+                        canonical_item.display()
+                    ))
+                    .hint("Remove the symbolic link loop or replace the link with the files it should bundle")
+                    .into();
+                }
+                copied_directories.push(canonical_item);
+                for file in files_in_directory(item)? {
+                    let bundled_file = bundled_item.join(relative_path(item, &file));
+                    // the content of linked directories is copied
+                    if file.is_dir() {
+                        if let Some(parent) = bundled_file.parent() {
+                            bundled_files.extend(self.copy_items_within(
+                                &[file.clone()],
+                                parent,
+                                copied_directories,
+                            )?);
+                        }
+                        continue;
+                    }
+                    self.copy_file(&file, &bundled_file)?;
+                    bundled_files.push(bundled_file);
+                }
+                copied_directories.pop();
+            } else {
+                self.copy_file(item, &bundled_item)?;
+                bundled_files.push(bundled_item);
+            }
+        }
+        Ok(bundled_files)
+    }
+
+    /// Write a generated file into the bundle, keeping the existing one if the content is the same.
+    /// Returns true if the file was written
+    pub fn write_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        content: impl AsRef<[u8]>,
+    ) -> Result<bool> {
+        let path = path.as_ref();
+        let content = content.as_ref();
+        self.placed.insert(path.to_path_buf());
+
+        if fs::read(path).map_or(false, |existing| existing == content) {
+            return Ok(false);
+        }
+        if let Some(parent) = path.parent() {
+            create_directory(parent)?;
+        }
+        fs::write(path, content)
+            .context(|| Error::io(format!("Could not write {}", path.display())))?;
+        self.changed.insert(path.to_path_buf());
+        Ok(true)
+    }
+
+    /// Keep a file that was placed into the bundle by other means
+    pub fn keep(&mut self, path: impl AsRef<Path>) {
+        self.placed.insert(path.as_ref().to_path_buf());
+    }
+
+    /// Whether a bundled file was copied or written during this bundling
+    pub fn is_changed(&self, path: impl AsRef<Path>) -> bool {
+        self.changed.contains(path.as_ref())
+    }
+
+    /// Remember the state of a bundled file after it was post-processed, for example after its rpath was set,
+    /// so that it is not mistaken for a modified one the next time
+    pub fn record(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(bundled_file) = self
+            .current
+            .files
+            .get_mut(&relative_path(&self.directory, path))
+        {
+            bundled_file.bundled_state = FileState::of(path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the directories of a removed file up to the bundled directory as long as they are empty
+    fn remove_empty_parents(&self, file: &Path) -> Result<()> {
+        for directory in file
+            .ancestors()
+            .skip(1)
+            .take_while(|directory| *directory != self.directory)
+        {
+            if directory.read_dir()?.next().is_some() {
+                break;
+            }
+            fs::remove_dir(directory)
+                .context(|| Error::io(format!("Could not remove {}", directory.display())))?;
+        }
+        Ok(())
+    }
+
+    /// Remove the files that were not placed during this bundling together with
+    /// the directories they leave empty, and save the state for the next bundling.
+    /// When building reproducibly the files are normalized first, so that the saved state is the final one
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(debug_symbols) = self.debug_symbols.take() {
+            debug_symbols.finish()?;
+        }
+        for file in files_in_directory(&self.directory)? {
+            if !self.placed.contains(&file) {
+                fs::remove_file(&file)
+                    .context(|| Error::io(format!("Could not remove {}", file.display())))?;
+                self.remove_empty_parents(&file)?;
+                events::emit(Event::StaleFileRemoved { path: file });
+            }
+        }
+
+        if let Some(source_date_epoch) = self.source_date_epoch {
+            reproducible::normalize_to(&self.directory, source_date_epoch)?;
+            for (path, bundled_file) in self.current.files.iter_mut() {
+                bundled_file.bundled_state = FileState::of(&self.directory.join(path))?;
+            }
+        }
+
+        if let Some(parent) = self.state_file.parent() {
+            create_directory(parent)?;
+        }
+        fs::write(
+            &self.state_file,
+            serde_json::to_string_pretty(&self.current)?,
+        )
+        .context(|| Error::io(format!("Could not write {}", self.state_file.display())))?;
+        Ok(())
+    }
+}
+
+impl FileState {
+    fn of(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)
+            .context(|| Error::io(format!("Could not read the metadata of {}", path.display())))?;
+        Ok(Self {
+            size: metadata.len(),
+            modified: modified_nanos(&metadata),
+            sha256: None,
+        })
+    }
+
+    fn with_sha256(self, path: &Path) -> Result<Self> {
+        Ok(Self {
+            sha256: Some(sha256_of_file(path)?),
+            ..self
+        })
+    }
+
+    /// The state of the file if its content is unchanged. The content is only hashed
+    /// when the size is the same but the modification time is not,
+    /// and is considered changed if there is no previous hash to compare with
+    fn current(&self, path: &Path) -> Result<Option<Self>> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+        if metadata.len() != self.size {
+            return Ok(None);
+        }
+        let modified = modified_nanos(&metadata);
+        if modified == self.modified {
+            return Ok(Some(self.clone()));
+        }
+        match &self.sha256 {
+            Some(sha256) if *sha256 == sha256_of_file(path)? => Ok(Some(Self {
+                modified,
+                ..self.clone()
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// The state of each bundled directory is kept in its own file, named after the directory
+fn state_file_name(directory: &Path) -> String {
+    let hash = to_hex(Sha256::digest(directory.to_string_lossy().as_bytes()).as_slice());
+    format!(
+        "{}-{}.json",
+        directory.file_name().unwrap_or_default().to_string_lossy(),
+        &hash[..16]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn test_file(name: &str, content: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "gtoolkit-vm-builder-incremental-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let file = directory.join(name);
+        fs::write(&file, content).unwrap();
+        file
+    }
+
+    fn touch(file: &Path, seconds: u64) {
+        fs::File::options()
+            .write(true)
+            .open(file)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn current_of_unchanged_file() {
+        let file = test_file("unchanged", "content");
+        let state = FileState::of(&file).unwrap();
+        assert_eq!(state.current(&file).unwrap(), Some(state));
+    }
+
+    #[test]
+    fn current_of_missing_file() {
+        let file = test_file("missing", "content");
+        let state = FileState::of(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(state.current(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn current_of_resized_file() {
+        let file = test_file("resized", "content");
+        let state = FileState::of(&file).unwrap();
+        fs::write(&file, "other content").unwrap();
+        assert_eq!(state.current(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn current_of_touched_file_without_hash() {
+        let file = test_file("touched-without-hash", "content");
+        touch(&file, 1_000);
+        let state = FileState::of(&file).unwrap();
+        touch(&file, 2_000);
+        assert_eq!(state.current(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn current_of_touched_file_with_same_hash() {
+        let file = test_file("touched-same-hash", "content");
+        touch(&file, 1_000);
+        let state = FileState::of(&file).unwrap().with_sha256(&file).unwrap();
+        touch(&file, 2_000);
+        let current = state.current(&file).unwrap().unwrap();
+        assert_eq!(current.modified, 2_000_000_000_000);
+        assert_eq!(current.sha256, state.sha256);
+    }
+
+    #[test]
+    fn current_of_changed_file_with_same_size() {
+        let file = test_file("changed-same-size", "content");
+        touch(&file, 1_000);
+        let state = FileState::of(&file).unwrap().with_sha256(&file).unwrap();
+        fs::write(&file, "CONTENT").unwrap();
+        touch(&file, 2_000);
+        assert_eq!(state.current(&file).unwrap(), None);
+    }
+}
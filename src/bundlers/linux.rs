@@ -1,11 +1,18 @@
-use crate::bundlers::Bundler;
+use crate::bundlers::elf::ElfFile;
+use crate::bundlers::incremental::IncrementalBundle;
+use crate::bundlers::{create_directory, Bundler};
+use crate::events::Event;
 use crate::options::BundleOptions;
-use crate::{Executable, ExecutableOptions};
-use std::error::Error;
-use std::fs;
+use crate::requirements::{common_requirements, Requirement};
+use crate::{events, Context, Error, Executable, ExecutableOptions, Result, Target};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use user_error::UserFacingError;
+
+/// An environment variable with an objcopy-compatible tool to use instead of `objcopy`, for example `llvm-objcopy`
+pub const OBJCOPY_VARIABLE: &str = "OBJCOPY";
+
+/// A directory of the bundle with the debug info split from the executables and libraries
+const DEBUG_DIRECTORY: &str = "debug";
 
 #[derive(Debug, Clone)]
 pub struct LinuxBundler {}
@@ -19,37 +26,181 @@ impl LinuxBundler {
         "lib"
     }
 
-    fn set_rpath(&self, binary: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-        which::which("patchelf")?;
+    fn set_rpath(&self, binary: impl AsRef<Path>) -> Result<()> {
+        which::which("patchelf").context(|| {
+            Error::missing_tool("patchelf is needed to set the RUNPATH of binaries")
+                .hint("Install patchelf, for example `sudo apt install patchelf`")
+        })?;
 
         let binary = binary.as_ref();
-        if !Command::new("patchelf")
+        let rpath = format!("$ORIGIN/../{}/", self.library_dir_name());
+        let status = Command::new("patchelf")
             .arg("--set-rpath")
-            .arg(format!("$ORIGIN/../{}/", self.library_dir_name()))
+            .arg(&rpath)
             .arg(binary)
-            .status()?
-            .success()
-        {
-            return Err(Box::new(UserFacingError::new(format!(
-                "Failed to set RUNPATH of {}",
-                binary.display(),
-            ))));
+            .status()
+            .context(|| Error::missing_tool("Failed to start patchelf"))?;
+        if !status.success() {
+            return Error::bundling(format!("Failed to set RUNPATH of {}", binary.display(),))
+                .into();
         }
+        events::emit(Event::RpathSet {
+            binary: binary.to_path_buf(),
+            rpath,
+        });
         Ok(())
     }
+
+    fn objcopy_name() -> String {
+        std::env::var(OBJCOPY_VARIABLE).unwrap_or_else(|_| "objcopy".to_string())
+    }
+
+    fn objcopy() -> Result<PathBuf> {
+        let name = Self::objcopy_name();
+        which::which(&name).context(|| {
+            Error::missing_tool(format!("{} is needed to split the debug info of binaries", &name))
+                .hint(format!("Install binutils, for example `sudo apt install binutils`, or set {} to an objcopy-compatible tool such as llvm-objcopy", OBJCOPY_VARIABLE))
+        })
+    }
+
+    fn run_objcopy(binary: &Path, arguments: &[String]) -> Result<()> {
+        let status = Command::new(Self::objcopy()?)
+            .args(arguments)
+            .status()
+            .context(|| Error::missing_tool(format!("Failed to start {}", Self::objcopy_name())))?;
+        if !status.success() {
+            return Error::bundling(format!(
+                "Failed to split the debug info of {}",
+                binary.display()
+            ))
+            .into();
+        }
+        Ok(())
+    }
+
+    /// Where the debug info of a binary goes: `debug/.build-id/xx/yyyy.debug` after its GNU build-id,
+    /// the layout gdb and other debuggers look up, or next to the relative path of the binary when it has none
+    pub fn debug_file(app_dir: &Path, binary: &Path, build_id: Option<&str>) -> PathBuf {
+        let debug_dir = app_dir.join(DEBUG_DIRECTORY);
+        match build_id {
+            Some(build_id) if build_id.len() > 2 => debug_dir
+                .join(".build-id")
+                .join(&build_id[..2])
+                .join(format!("{}.debug", &build_id[2..])),
+            _ => {
+                let relative = binary.strip_prefix(app_dir).unwrap_or(binary);
+                let mut debug_file = debug_dir.join(relative).into_os_string();
+                debug_file.push(".debug");
+                PathBuf::from(debug_file)
+            }
+        }
+    }
+
+    /// Extract the debug info of a bundled binary into its file within the debug directory, strip the binary
+    /// and link it to the debug file with a `.gnu_debuglink` that carries the name and the CRC of the debug file.
+    /// Without debug symbols in the bundle, the binary is only stripped.
+    /// Binaries that were not copied again keep the debug file split the previous time
+    fn split_debug_info(
+        &self,
+        options: &BundleOptions,
+        app_dir: &Path,
+        binary: &Path,
+        bundle: &mut IncrementalBundle,
+    ) -> Result<()> {
+        let mut elf = match ElfFile::open(binary)? {
+            Some(elf) => elf,
+            None => return Ok(()),
+        };
+        let debug_file = bundle.debug_symbols_path(Self::debug_file(
+            app_dir,
+            binary,
+            elf.build_id()?.as_deref(),
+        ));
+
+        if !bundle.is_changed(binary) {
+            if options.include_debug_symbols() && debug_file.exists() {
+                bundle.debug_symbols().keep(&debug_file);
+            }
+            return Ok(());
+        }
+        if !elf.has_debug_info() {
+            return Ok(());
+        }
+        drop(elf);
+
+        if !options.include_debug_symbols() {
+            Self::run_objcopy(
+                binary,
+                &["--strip-debug".to_string(), binary.display().to_string()],
+            )?;
+            events::emit(Event::DebugInfoSplit {
+                binary: binary.to_path_buf(),
+                debug_file: None,
+            });
+            return bundle.record(binary);
+        }
+
+        if let Some(parent) = debug_file.parent() {
+            create_directory(parent)?;
+        }
+        // the debug link records the file name of the debug file as it is given,
+        // so the debug info is extracted into its final location before linking to it
+        Self::run_objcopy(
+            binary,
+            &[
+                "--only-keep-debug".to_string(),
+                binary.display().to_string(),
+                debug_file.display().to_string(),
+            ],
+        )?;
+        Self::run_objcopy(
+            binary,
+            &[
+                "--strip-debug".to_string(),
+                format!("--add-gnu-debuglink={}", debug_file.display()),
+                binary.display().to_string(),
+            ],
+        )?;
+        events::emit(Event::DebugInfoSplit {
+            binary: binary.to_path_buf(),
+            debug_file: Some(debug_file.clone()),
+        });
+        bundle.debug_symbols().keep(&debug_file);
+        bundle.record(binary)
+    }
 }
 
 impl Bundler for LinuxBundler {
+    fn requirements(&self, target: &Target) -> Vec<Requirement> {
+        let mut requirements = common_requirements(target);
+        requirements.push(
+            Requirement::tool(
+                "patchelf",
+                "to set the RUNPATH of executables and libraries",
+            )
+            .hint("Install patchelf, for example `sudo apt install patchelf`"),
+        );
+        requirements.push(
+            Requirement::tool(
+                Self::objcopy_name(),
+                "to split the debug info of executables and libraries that carry it",
+            )
+            .hint(format!("Install binutils, for example `sudo apt install binutils`, or set {} to an objcopy-compatible tool such as llvm-objcopy", OBJCOPY_VARIABLE))
+            .optional(),
+        );
+        requirements
+    }
+
     fn post_compile(
         &self,
         bundle_options: &BundleOptions,
         executable: &Executable,
         _executable_options: &ExecutableOptions,
-    ) {
+    ) -> Result<()> {
         self.set_rpath(bundle_options.compiled_executable_path(executable))
-            .expect("Failed to set rpath");
     }
-    fn bundle(&self, options: &BundleOptions) {
+
+    fn bundle(&self, options: &BundleOptions) -> Result<()> {
         let bundle_location = options.bundle_location();
         let app_name = options.app_name();
 
@@ -58,49 +209,39 @@ impl Bundler for LinuxBundler {
 
         let library_dir = app_dir.join(self.library_dir_name());
 
-        if app_dir.exists() {
-            fs::remove_dir_all(&app_dir).unwrap();
-        }
-        fs::create_dir_all(&app_dir).unwrap();
-        fs::create_dir(&binary_dir).unwrap();
-        fs::create_dir(&library_dir).unwrap();
+        let mut bundle = self.start_bundle(options)?;
+        create_directory(&binary_dir)?;
+        create_directory(&library_dir)?;
 
-        options.executables().iter().for_each(|executable| {
+        for executable in options.executables() {
             let compiled_executable_path = options.compiled_executable_path(executable);
             let bundled_executable_path =
                 binary_dir.join(options.bundled_executable_name(executable));
-            fs::copy(&compiled_executable_path, &bundled_executable_path)
-                .map_err(|error| {
-                    UserFacingError::new(format!(
-                        "Could not copy {} to {} due to {}",
-                        &compiled_executable_path.display(),
-                        &bundled_executable_path.display(),
-                        error
-                    ));
-                })
-                .expect("Failed to copy");
-        });
+            bundle.copy_file(&compiled_executable_path, &bundled_executable_path)?;
+            self.split_debug_info(options, &app_dir, &bundled_executable_path, &mut bundle)?;
+        }
 
-        self.compiled_libraries(options)
-            .iter()
-            .for_each(|compiled_library_path| {
-                let bundled_library_path =
-                    library_dir.join(compiled_library_path.file_name().unwrap());
-
-                match fs::copy(&compiled_library_path, &bundled_library_path) {
-                    Ok(_) => {
-                        self.set_rpath(&bundled_library_path).unwrap();
-                    }
-                    Err(error) => {
-                        panic!(
-                            "Could not copy {} to {} due to {}",
-                            &compiled_library_path.display(),
-                            &bundled_library_path.display(),
-                            error
-                        );
-                    }
-                };
-            });
+        for compiled_library_path in self.compiled_libraries(options)? {
+            let library_name = compiled_library_path.file_name().context(|| {
+                Error::bundling(format!(
+                    "Invalid library name {}",
+                    compiled_library_path.display()
+                ))
+            })?;
+            let bundled_library_path = library_dir.join(library_name);
+            if bundle.copy_file(&compiled_library_path, &bundled_library_path)? {
+                self.set_rpath(&bundled_library_path)?;
+                bundle.record(&bundled_library_path)?;
+            }
+            self.split_debug_info(options, &app_dir, &bundled_library_path, &mut bundle)?;
+        }
+
+        self.bundle_resources(options, &mut bundle)?;
+        bundle.finish()
+    }
+
+    fn bundled_app_directory(&self, options: &BundleOptions) -> PathBuf {
+        options.bundle_location().join(options.app_name())
     }
 
     fn bundled_executable_directory(&self, options: &BundleOptions) -> PathBuf {
@@ -1,13 +1,18 @@
-use crate::bundlers::Bundler;
+use crate::bundlers::{create_directory, Bundler};
+#[cfg(target_os = "macos")]
+use crate::events;
+#[cfg(target_os = "macos")]
+use crate::events::Event;
 use crate::options::BundleOptions;
-use crate::{Executable, Result};
-use std::fs;
-use std::fs::File;
+use crate::requirements::{common_requirements, Requirement};
+use crate::{Context, Error, Executable, Result, Target};
 use std::path::{Path, PathBuf};
 
 #[cfg(target_os = "macos")]
 use mach_object::{LoadCommand, OFile, LC_ID_DYLIB};
 #[cfg(target_os = "macos")]
+use std::fs::File;
+#[cfg(target_os = "macos")]
 use std::process::Command;
 
 use serde::{Deserialize, Serialize};
@@ -52,23 +57,29 @@ impl MacBundler {
         let mut cur = std::io::Cursor::new(payload);
         let file = OFile::parse(&mut cur)?;
 
-        println!("Processing {}...", filename.as_ref().display());
-
         match file {
             OFile::MachFile {
                 header: _,
                 ref commands,
             } => {
+                let rpath = format!("@executable_path/{}", path.as_ref());
                 if !Command::new("install_name_tool")
                     .arg("-add_rpath")
-                    .arg(format!("@executable_path/{}", path.as_ref()))
+                    .arg(&rpath)
                     .arg(&filename.as_ref())
                     .status()?
                     .success()
                 {
-                    panic!("Failed to add rpath to {}", filename.as_ref().display());
+                    return Error::bundling(format!(
+                        "Failed to add rpath to {}",
+                        filename.as_ref().display()
+                    ))
+                    .into();
                 }
-                println!("   Added rpath to {}", filename.as_ref().display());
+                events::emit(Event::RpathSet {
+                    binary: filename.as_ref().to_path_buf(),
+                    rpath,
+                });
 
                 let commands = commands
                     .iter()
@@ -88,9 +99,14 @@ impl MacBundler {
                                 let current_path = dylib.name.as_str();
                                 let file_name = Path::new(current_path)
                                     .file_name()
-                                    .unwrap()
-                                    .to_str()
-                                    .unwrap();
+                                    .and_then(|name| name.to_str())
+                                    .context(|| {
+                                        Error::bundling(format!(
+                                            "Invalid install name {} in {}",
+                                            current_path,
+                                            filename.as_ref().display()
+                                        ))
+                                    })?;
                                 let new_path =
                                     format!("@executable_path/{}/{}", path.as_ref(), &file_name);
 
@@ -102,17 +118,18 @@ impl MacBundler {
                                         .status()?
                                         .success()
                                     {
-                                        panic!(
+                                        return Error::bundling(format!(
                                             "Failed to change id to {} of {}",
                                             &new_path,
                                             filename.as_ref().display()
-                                        );
+                                        ))
+                                        .into();
                                     };
-                                    println!(
-                                        "   Changed id of {} to {}",
-                                        filename.as_ref().display(),
-                                        &new_path
-                                    );
+                                    events::emit(Event::InstallNameChanged {
+                                        binary: filename.as_ref().to_path_buf(),
+                                        from: None,
+                                        to: new_path,
+                                    });
                                 } else {
                                     if !Command::new("install_name_tool")
                                         .arg("-change")
@@ -122,19 +139,19 @@ impl MacBundler {
                                         .status()?
                                         .success()
                                     {
-                                        panic!(
+                                        return Error::bundling(format!(
                                             "Failed to change {} to {} in {}",
                                             current_path,
                                             &new_path,
                                             filename.as_ref().display()
-                                        );
+                                        ))
+                                        .into();
                                     };
-                                    println!(
-                                        "   Changed dependency of {} from {} to {}",
-                                        filename.as_ref().display(),
-                                        current_path,
-                                        &new_path
-                                    );
+                                    events::emit(Event::InstallNameChanged {
+                                        binary: filename.as_ref().to_path_buf(),
+                                        from: Some(current_path.to_string()),
+                                        to: new_path,
+                                    });
                                 }
                             }
                         }
@@ -155,14 +172,30 @@ impl MacBundler {
         Self::set_rpath_to(filename, "Plugins")
     }
 
-    fn debug_symbol_file(binary: &Path) -> PathBuf {
-        let debug_symbols_folder_name = binary.file_name().and_then(|name|name.to_str()).map(|name| format!("{}.dSYM", name)).unwrap();
-        binary.with_file_name(debug_symbols_folder_name)
+    pub fn debug_symbol_file(binary: &Path) -> Result<PathBuf> {
+        let debug_symbols_folder_name = binary
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| format!("{}.dSYM", name))
+            .context(|| Error::bundling(format!("Invalid binary name {}", binary.display())))?;
+        Ok(binary.with_file_name(debug_symbols_folder_name))
     }
 }
 
 impl Bundler for MacBundler {
-    fn bundle(&self, options: &BundleOptions) {
+    fn requirements(&self, target: &Target) -> Vec<Requirement> {
+        let mut requirements = common_requirements(target);
+        requirements.push(
+            Requirement::tool(
+                "install_name_tool",
+                "to set the rpath of executables and libraries",
+            )
+            .hint("Install the Xcode command line tools with `xcode-select --install`"),
+        );
+        requirements
+    }
+
+    fn bundle(&self, options: &BundleOptions) -> Result<()> {
         let bundle_location = options.bundle_location();
         let app_name = options.app_name();
 
@@ -172,86 +205,81 @@ impl Bundler for MacBundler {
         let macos_dir = contents_dir.join("MacOS");
         let plugins_dir = macos_dir.join("Plugins");
 
-        if app_dir.exists() {
-            fs::remove_dir_all(&app_dir).unwrap();
-        }
-        fs::create_dir_all(&app_dir).unwrap();
-        fs::create_dir(&contents_dir).unwrap();
-        fs::create_dir(&resources_dir).unwrap();
-        fs::create_dir(&macos_dir).unwrap();
-        fs::create_dir(&plugins_dir).unwrap();
+        let mut bundle = self.start_bundle(options)?;
+        create_directory(&plugins_dir)?;
 
-        options.executables().iter().for_each(|executable| {
+        for executable in options.executables() {
             let compiled_executable_path = options.compiled_executable_path(executable);
             let bundled_executable_path = self
                 .bundled_executable_directory(options)
                 .join(options.bundled_executable_name(executable));
-            match fs::copy(&compiled_executable_path, &bundled_executable_path) {
-                Ok(_) => {
-                    Self::set_rpath(&bundled_executable_path).expect(&format!(
-                        "Failed to set the rpath of {}",
-                        &bundled_executable_path.display()
-                    ));
-                }
-                Err(error) => {
-                    panic!(
-                        "Could not copy {} to {} due to {}",
-                        &compiled_executable_path.display(),
-                        &bundled_executable_path.display(),
-                        error
-                    );
-                }
-            };
-        });
-
-        fs_extra::copy_items(
-            &self.compiled_libraries(options),
-            &plugins_dir,
-            &fs_extra::dir::CopyOptions::new(),
-        )
-        .unwrap();
-        
-        if options.include_debug_symbols() {
-            for each_library in self.compiled_libraries(options) {
-                let debug_symbols = Self::debug_symbol_file(&each_library);
-                if debug_symbols.exists() {
-                    let options = fs_extra::dir::CopyOptions::default();
-                    fs_extra::dir::copy(debug_symbols, &plugins_dir, &options).unwrap();
-                }
+            if bundle.copy_file(&compiled_executable_path, &bundled_executable_path)? {
+                Self::set_rpath(&bundled_executable_path)?;
+                bundle.record(&bundled_executable_path)?;
             }
         }
 
-        for library_path in self.compiled_libraries_in(&plugins_dir, options) {
-            Self::set_rpath(&library_path).expect(&format!(
-                "Failed to set the rpath of {}",
-                &library_path.display()
-            ));
+        let compiled_libraries = self.compiled_libraries(options)?;
+        bundle.copy_items(&compiled_libraries, &plugins_dir)?;
+
+        if options.include_debug_symbols() {
+            let debug_symbols = compiled_libraries
+                .iter()
+                .map(|each_library| Self::debug_symbol_file(each_library))
+                .collect::<Result<Vec<PathBuf>>>()?
+                .into_iter()
+                .filter(|debug_symbols| debug_symbols.exists())
+                .collect::<Vec<PathBuf>>();
+            let symbols_dir = bundle.debug_symbols_path(&plugins_dir);
+            bundle
+                .debug_symbols()
+                .copy_items(&debug_symbols, symbols_dir)?;
+        }
+
+        // install_name_tool fails to add an rpath that is already there
+        for library_path in self.compiled_libraries_in(&plugins_dir, options)? {
+            if bundle.is_changed(&library_path) {
+                Self::set_rpath(&library_path)?;
+                bundle.record(&library_path)?;
+            }
         }
 
         let icon = if let Some(icon) = self.create_icns(options) {
             let resource_icon_name = resources_dir
                 .join(options.app_name())
                 .with_extension("icns");
-            fs::copy(icon, resource_icon_name.clone()).unwrap();
-            Some(resource_icon_name.clone())
+            bundle.copy_file(icon, &resource_icon_name)?;
+            Some(resource_icon_name)
         } else {
             None
         };
 
-        let info_plist_template = mustache::compile_str(INFO_PLIST).unwrap();
+        let info_plist_template = mustache::compile_str(INFO_PLIST)?;
         let info = Info {
             bundle_name: options.app_name().to_owned(),
             bundle_display_name: options.app_name().to_owned(),
             executable_name: options.bundled_executable_name(&Executable::App),
             bundle_identifier: options.identifier().to_owned(),
             bundle_version: options.version().to_string(),
-            bundle_icon: icon.as_ref().map_or("".to_string(), |icon| {
-                icon.file_name().unwrap().to_str().unwrap().to_string()
-            }),
+            bundle_icon: icon
+                .as_ref()
+                .and_then(|icon| icon.file_name())
+                .map_or("".to_string(), |name| name.to_string_lossy().to_string()),
         };
 
-        let mut file = File::create(contents_dir.join(Path::new("Info.plist"))).unwrap();
-        info_plist_template.render(&mut file, &info).unwrap();
+        let info_plist = contents_dir.join(Path::new("Info.plist"));
+        let mut content = vec![];
+        info_plist_template.render(&mut content, &info)?;
+        bundle.write_file(&info_plist, content)?;
+
+        self.bundle_resources(options, &mut bundle)?;
+        bundle.finish()
+    }
+
+    fn bundled_app_directory(&self, options: &BundleOptions) -> PathBuf {
+        options
+            .bundle_location()
+            .join(format!("{}.app", options.app_name()))
     }
 
     fn bundled_executable_directory(&self, options: &BundleOptions) -> PathBuf {
@@ -1,43 +1,77 @@
 use shared_library_builder::{Library, LibraryCompilationContext, LibraryTarget};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
+use crate::bundlers::incremental::IncrementalBundle;
+use crate::checksums;
+use crate::environment::{tool_environment, ScopedEnvironment};
+use crate::events::{Event, Phase};
+use crate::libraries::{library_repositories, PinnedVersion};
 use crate::options::BundleOptions;
-use crate::{Error, Platform, Result};
+use crate::requirements::{common_requirements, Requirement};
+use crate::{events, Context, Error, Platform, Result, Target};
 use crate::{Executable, ExecutableOptions};
 
 pub mod android;
+pub mod android_app_bundle;
+pub mod elf;
+pub mod incremental;
 pub mod linux;
 pub mod mac;
 pub mod windows;
 
+/// A name of the file with SHA-256 checksums of the image and resources placed in the bundle
+pub const RESOURCES_CHECKSUMS_FILE: &str = "resources.sha256";
+/// A name of the software bill of materials in the CycloneDX format
+pub const CYCLONEDX_FILE: &str = "sbom.cdx.json";
+/// A name of the software bill of materials in the SPDX format
+pub const SPDX_FILE: &str = "sbom.spdx.json";
+/// A name of the integrity manifest placed into the resources directory of the bundle
+pub const INTEGRITY_MANIFEST_FILE: &str = "integrity.manifest";
+
+/// A name of the file with a fingerprint of the sources of a library built from a local checkout
+const LOCAL_SOURCES_FINGERPRINT_FILE: &str = "local-sources.fingerprint";
+
 pub trait Bundler: Debug + Send + Sync {
-    fn pre_compile(&self, _options: &ExecutableOptions) {}
+    /// External tools and environment variables needed to compile and bundle for a target
+    fn requirements(&self, target: &Target) -> Vec<Requirement> {
+        common_requirements(target)
+    }
+
+    /// Check that the options can be bundled by this bundler before compiling anything
+    fn validate(&self, options: &BundleOptions) -> Result<()> {
+        validate_resources(options)
+    }
+
+    /// Check that the tools needed to bundle are available before compiling anything
+    fn validate_bundling(&self, _options: &BundleOptions) -> Result<()> {
+        Ok(())
+    }
+
+    fn pre_compile(&self, _options: &ExecutableOptions) -> Result<()> {
+        Ok(())
+    }
     fn post_compile(
         &self,
         _bundle_options: &BundleOptions,
         _executable: &Executable,
         _executable_options: &ExecutableOptions,
-    ) {
+    ) -> Result<()> {
+        Ok(())
     }
 
-    fn compile_binary(&self, options: &ExecutableOptions) {
-        std::env::set_var("CARGO_TARGET_DIR", options.target_dir());
-        if !options.target().is_current() {
-            std::env::set_var("CARGO_TARGET", options.target().to_string());
-        }
-
-        if let Some(vmmaker_vm) = options.vmmaker_vm() {
-            std::env::set_var("VM_CLIENT_VMMAKER", vmmaker_vm);
-        }
-
-        if let Some(vmmaker_image) = options.vmmaker_image() {
-            std::env::set_var("VM_CLIENT_VMMAKER_IMAGE", vmmaker_image);
-        }
-
-        std::env::set_var("VM_CLIENT_VERSION", options.version().to_string());
+    /// Environment variables passed to cargo when compiling a given executable.
+    /// Bundlers may extend it with platform specific variables
+    fn compile_environment(&self, options: &ExecutableOptions) -> Vec<(String, OsString)> {
+        options.cargo_environment()
+    }
 
+    fn compile_binary_command(&self, options: &ExecutableOptions) -> Command {
         let mut command = options.cargo_build_command();
+        command.envs(self.compile_environment(options));
 
         if !options.target().is_current() {
             command.arg("--target").arg(options.target().to_string());
@@ -62,16 +96,116 @@ pub trait Bundler: Debug + Send + Sync {
             command.args(options.features());
         }
 
-        if !command.status().unwrap().success() {
-            panic!("Failed to compile a vm-client")
+        command
+    }
+
+    fn bundle(&self, options: &BundleOptions) -> Result<()>;
+
+    /// Package the app directory into archives once everything was written into it,
+    /// for example the SBOM and the integrity manifest. Android assembles its APK or app bundle here
+    fn package(&self, _options: &BundleOptions) -> Result<()> {
+        Ok(())
+    }
+
+    /// Start placing files into the app directory, with the debug symbols
+    /// in a separate directory when they are archived on their own
+    fn start_bundle(&self, options: &BundleOptions) -> Result<IncrementalBundle> {
+        let mut bundle = IncrementalBundle::start(self.bundled_app_directory(options), options)?;
+        if self.separates_debug_symbols(options) {
+            bundle.separate_debug_symbols(options.symbols_directory(), options)?;
         }
+        // written after bundling, so they are not removed as stale
+        let resources_directory = self.bundled_resources_directory(options);
+        for name in [CYCLONEDX_FILE, SPDX_FILE, INTEGRITY_MANIFEST_FILE] {
+            bundle.keep(resources_directory.join(name));
+        }
+        Ok(bundle)
+    }
+
+    /// Whether the debug symbols are placed into the symbols directory instead of the app directory
+    fn separates_debug_symbols(&self, options: &BundleOptions) -> bool {
+        options.symbols_archive()
     }
 
-    fn bundle(&self, options: &BundleOptions);
+    /// The top-level directory of the bundled app that contains all of its files
+    fn bundled_app_directory(&self, options: &BundleOptions) -> PathBuf;
 
+    /// Archives and intermediate files written next to the app directory when bundling
+    fn bundled_archives(&self, _options: &BundleOptions) -> Vec<PathBuf> {
+        vec![]
+    }
     fn bundled_executable_directory(&self, options: &BundleOptions) -> PathBuf;
     fn bundled_resources_directory(&self, options: &BundleOptions) -> PathBuf;
 
+    /// Copy the image with its .changes and .sources files, extra resources, the build info
+    /// and the collected licenses into the resources directory of the bundle and write a checksum file of them.
+    /// Returns true if anything was placed in the resources directory.
+    fn bundle_resources(
+        &self,
+        options: &BundleOptions,
+        bundle: &mut IncrementalBundle,
+    ) -> Result<bool> {
+        let image_files = options.image_files()?;
+        let build_info = Some(options.build_info_path()).filter(|file| file.is_file());
+        let licenses = Some(options.licenses_directory()).filter(|directory| directory.is_dir());
+        if image_files.is_empty()
+            && options.resources().is_empty()
+            && build_info.is_none()
+            && licenses.is_none()
+        {
+            return Ok(false);
+        }
+
+        events::phase(Phase::BundleResources, options.app_name(), || {
+            let resources_directory = self.bundled_resources_directory(options);
+            if !resources_directory.exists() {
+                std::fs::create_dir_all(&resources_directory).map_err(|error| {
+                    Error::new(format!(
+                        "Could not create {}",
+                        resources_directory.display()
+                    ))
+                    .from(error)
+                })?;
+            }
+
+            // everything is placed by its name
+            let mut placed_files = vec![];
+            for file in image_files
+                .iter()
+                .chain(options.resources().iter().filter(|each| each.is_file()))
+                .chain(build_info.iter())
+            {
+                let file_name = file.file_name().context(|| {
+                    Error::invalid_options(format!("Invalid resource {}", file.display()))
+                })?;
+                let bundled_file = resources_directory.join(file_name);
+                bundle.copy_file(file, &bundled_file)?;
+                placed_files.push(bundled_file);
+            }
+
+            for directory in options
+                .resources()
+                .iter()
+                .filter(|each| !each.is_file())
+                .chain(licenses.iter())
+            {
+                if !directory.is_dir() {
+                    return Error::new(format!("Resource {} does not exist", directory.display()))
+                        .into();
+                }
+                placed_files.extend(
+                    bundle.copy_items(std::slice::from_ref(directory), &resources_directory)?,
+                );
+            }
+
+            let checksums_file = resources_directory.join(RESOURCES_CHECKSUMS_FILE);
+            checksums::write_checksums_file(&checksums_file, &placed_files)?;
+            bundle.keep(checksums_file);
+
+            Ok(true)
+        })
+    }
+
     fn compile_third_party_libraries(&self, options: &BundleOptions) -> Result<()> {
         self.ensure_compiled_libraries_directory(options)?;
 
@@ -101,36 +235,154 @@ pub trait Bundler: Debug + Send + Sync {
         &self,
         library: &Box<dyn Library>,
         options: &BundleOptions,
-    ) -> LibraryCompilationContext {
+    ) -> Result<LibraryCompilationContext> {
+        for directory in [
+            options.third_party_libraries_sources_directory(),
+            options.third_party_libraries_build_directory(),
+        ] {
+            let directory = directory.join(library.name());
+            if !directory.exists() {
+                std::fs::create_dir_all(&directory)
+                    .context(|| Error::io(format!("Failed to create {}", directory.display())))?;
+            }
+        }
+        self.library_compilation_context(library, options)
+    }
+
+    /// The compilation context of a library without creating its directories,
+    /// to tell where its sources and compiled library are
+    fn library_compilation_context(
+        &self,
+        library: &Box<dyn Library>,
+        options: &BundleOptions,
+    ) -> Result<LibraryCompilationContext> {
         let sources_directory = options
             .third_party_libraries_sources_directory()
             .join(library.name());
-        if !sources_directory.exists() {
-            std::fs::create_dir_all(&sources_directory)
-                .unwrap_or_else(|_| panic!("Failed to create {}", &sources_directory.display()));
-        }
         let build_directory = options
             .third_party_libraries_build_directory()
             .join(library.name());
-        if !build_directory.exists() {
-            std::fs::create_dir_all(&build_directory)
-                .unwrap_or_else(|_| panic!("Failed to create {}", &build_directory.display()));
-        }
 
-        LibraryCompilationContext::new(
+        let library_target = LibraryTarget::try_from(options.target().to_string().as_str())
+            .context(|| {
+                Error::invalid_options(format!(
+                    "Libraries can not be compiled for {}",
+                    options.target().to_string()
+                ))
+            })?;
+
+        Ok(LibraryCompilationContext::new(
             sources_directory,
             build_directory,
-            LibraryTarget::try_from(options.target().to_string().as_str()).unwrap(),
+            library_target,
             !options.release(),
-        )
+        ))
     }
 
     fn compile_library(&self, library: &Box<dyn Library>, options: &BundleOptions) -> Result<()> {
-        let context = self.new_library_compilation_context(library, options);
-        let _ = library.compile(&context)?;
+        let context = self.new_library_compilation_context(library, options)?;
+
+        // a library that was not rebuilt keeps its modification time
+        let exported_library = library.exported_library_path(&context);
+        let local_sources = options
+            .local_library_path(library.name())
+            .map(|path| self.invalidate_local_library(library, path, &exported_library, options))
+            .transpose()?;
+        let previously_modified = modification_time(&exported_library);
+
+        // offline, an already compiled library is used as is, even if its sources changed upstream
+        let use_cached = options.offline()
+            && !options.is_built_from_local_path(library.name())
+            && exported_library.exists();
+
+        let pinned_sources = options
+            .pinned_library(library.name())
+            .filter(|pinned| pinned.commit.is_some() || pinned.sources.is_some());
+        let sources_directory = options
+            .third_party_libraries_sources_directory()
+            .join(library.name());
+
+        events::phase(Phase::CompileLibrary, library.name(), || {
+            if use_cached {
+                // the sources of a cached library are still checked when they are around,
+                // so that a replaced checkout is noticed before it is compiled again online
+                if let Some(pinned) = pinned_sources.filter(|_| has_entries(&sources_directory)) {
+                    verify_sources(library.name(), &sources_directory, pinned)?;
+                }
+                return Ok(());
+            }
+            let _environment = ScopedEnvironment::set(tool_environment(options));
+            // the sources are fetched and verified on their own before anything of them is built or run
+            if let Some(pinned) = pinned_sources {
+                library.ensure_sources(&context).context(|| {
+                    Error::library(format!(
+                        "Failed to check out the sources of {}",
+                        library.name()
+                    ))
+                })?;
+                verify_sources(library.name(), &sources_directory, pinned)?;
+            }
+            let _ = library.compile(&context).context(|| {
+                Error::library(format!("Failed to compile {}", library.name()))
+                    .hint("Run `doctor --libraries ...` to check the tools needed by the library")
+            })?;
+            Ok(())
+        })?;
+
+        verify_checksum(library.name(), &exported_library, options)?;
+
+        if let Some((fingerprint_file, fingerprint)) = local_sources {
+            std::fs::write(&fingerprint_file, fingerprint)
+                .context(|| Error::io(format!("Could not write {}", fingerprint_file.display())))?;
+        }
+
+        if previously_modified.is_some()
+            && previously_modified == modification_time(&exported_library)
+        {
+            events::emit(Event::LibraryCacheHit {
+                library: library.name().to_string(),
+                path: exported_library,
+            });
+        } else {
+            events::emit(Event::ArtifactProduced {
+                phase: Phase::CompileLibrary,
+                subject: library.name().to_string(),
+                path: exported_library,
+            });
+        }
         Ok(())
     }
 
+    /// Libraries built from local checkouts are rebuilt when their sources change.
+    /// Returns the file to record the fingerprint of the sources in after a successful build
+    fn invalidate_local_library(
+        &self,
+        library: &Box<dyn Library>,
+        sources: &Path,
+        exported_library: &Path,
+        options: &BundleOptions,
+    ) -> Result<(PathBuf, String)> {
+        let fingerprint = checksums::sources_fingerprint(sources).context(|| {
+            Error::library(format!(
+                "Could not read the sources of {} at {}",
+                library.name(),
+                sources.display()
+            ))
+        })?;
+        let fingerprint_file = options
+            .third_party_libraries_build_directory()
+            .join(library.name())
+            .join(LOCAL_SOURCES_FINGERPRINT_FILE);
+
+        let previous_fingerprint = std::fs::read_to_string(&fingerprint_file).ok();
+        if previous_fingerprint.as_ref() != Some(&fingerprint) && exported_library.exists() {
+            std::fs::remove_file(exported_library).context(|| {
+                Error::io(format!("Could not remove {}", exported_library.display()))
+            })?;
+        }
+        Ok((fingerprint_file, fingerprint))
+    }
+
     fn bundle_location(&self, configuration: &BundleOptions) -> PathBuf {
         configuration.bundle_location()
     }
@@ -147,43 +399,249 @@ pub trait Bundler: Debug + Send + Sync {
         self.compilation_location(configuration)
     }
 
-    fn compiled_libraries(&self, options: &BundleOptions) -> Vec<PathBuf> {
-        let mut all_compiled_libraries = self.compiled_vm_libraries(options);
-        all_compiled_libraries.extend(self.exported_third_party_libraries(options));
-        all_compiled_libraries
+    fn compiled_libraries(&self, options: &BundleOptions) -> Result<Vec<PathBuf>> {
+        let mut all_compiled_libraries = self.compiled_vm_libraries(options)?;
+        all_compiled_libraries.extend(self.exported_third_party_libraries(options)?);
+        Ok(all_compiled_libraries)
+    }
+
+    /// File names of the executables and libraries placed into the bundle
+    fn bundled_binary_names(&self, options: &BundleOptions) -> Result<BTreeSet<OsString>> {
+        let mut names = options
+            .executables()
+            .iter()
+            .map(|executable| OsString::from(options.bundled_executable_name(executable)))
+            .collect::<BTreeSet<OsString>>();
+        for library in self.compiled_libraries(options)? {
+            if let Some(name) = library.file_name() {
+                names.insert(name.to_os_string());
+            }
+        }
+        Ok(names)
     }
 
-    fn compiled_vm_libraries(&self, options: &BundleOptions) -> Vec<PathBuf> {
+    fn compiled_vm_libraries(&self, options: &BundleOptions) -> Result<Vec<PathBuf>> {
         self.compiled_libraries_in(&self.compiled_libraries_directory(options), options)
     }
 
-    fn exported_third_party_libraries(&self, options: &BundleOptions) -> Vec<PathBuf> {
+    fn exported_third_party_libraries(&self, options: &BundleOptions) -> Result<Vec<PathBuf>> {
         options
             .libraries()
             .iter()
             .map(|library| {
-                let context = self.new_library_compilation_context(library, options);
-                library.exported_library_path(&context)
+                let context = self.new_library_compilation_context(library, options)?;
+                let exported_library = library.exported_library_path(&context);
+                verify_checksum(library.name(), &exported_library, options)?;
+                Ok(exported_library)
             })
             .collect()
     }
 
-    fn compiled_libraries_in(&self, directory: &Path, options: &BundleOptions) -> Vec<PathBuf> {
-        directory
-            .read_dir()
-            .unwrap()
-            .map(|each| each.unwrap().path())
-            .filter(|each| {
-                let extension = each.extension().and_then(|ext| ext.to_str());
-                match options.platform() {
-                    Platform::Mac => extension == Some("dylib"),
-                    Platform::Windows => extension == Some("dll"),
-                    Platform::Linux => extension == Some("so"),
-                    Platform::Android => extension == Some("so"),
-                }
-            })
-            .collect()
+    fn compiled_libraries_in(
+        &self,
+        directory: &Path,
+        options: &BundleOptions,
+    ) -> Result<Vec<PathBuf>> {
+        let entries = directory.read_dir().context(|| {
+            Error::bundling(format!(
+                "Could not list the compiled libraries in {}",
+                directory.display()
+            ))
+            .hint("Compile the executables and libraries before bundling them")
+        })?;
+
+        let mut libraries = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|ext| ext.to_str());
+            let is_library = match options.platform() {
+                Platform::Mac => extension == Some("dylib"),
+                Platform::Windows => extension == Some("dll"),
+                Platform::Linux => extension == Some("so"),
+                Platform::Android => extension == Some("so"),
+            };
+            if is_library {
+                libraries.push(path);
+            }
+        }
+        libraries.sort();
+        Ok(libraries)
     }
 
     fn clone_bundler(&self) -> Box<dyn Bundler>;
 }
+
+/// Copy a compiled file into the bundle
+pub fn copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    let from = from.as_ref();
+    let to = to.as_ref();
+    std::fs::copy(from, to).context(|| {
+        Error::bundling(format!(
+            "Could not copy {} to {}",
+            from.display(),
+            to.display()
+        ))
+    })?;
+    events::emit(Event::file_copied(from, to));
+    Ok(())
+}
+
+/// Remove the directory if it exists and create it again empty
+pub fn recreate_directory(directory: impl AsRef<Path>) -> Result<()> {
+    let directory = directory.as_ref();
+    if directory.exists() {
+        std::fs::remove_dir_all(directory)
+            .context(|| Error::io(format!("Could not remove {}", directory.display())))?;
+    }
+    create_directory(directory)
+}
+
+/// The image and the resources must exist and must not overwrite each other or the files the builder places
+/// into the resources directory, since all of them are placed into it by their names
+pub fn validate_resources(options: &BundleOptions) -> Result<()> {
+    let mut placed_names = BTreeMap::<OsString, PathBuf>::new();
+    for generated in [
+        options.build_info_path(),
+        options.licenses_directory(),
+        PathBuf::from(RESOURCES_CHECKSUMS_FILE),
+        PathBuf::from(CYCLONEDX_FILE),
+        PathBuf::from(SPDX_FILE),
+        PathBuf::from(INTEGRITY_MANIFEST_FILE),
+    ] {
+        if let Some(name) = generated.file_name() {
+            placed_names.insert(name.to_os_string(), generated.clone());
+        }
+    }
+
+    for resource in options.image_files()?.iter().chain(options.resources()) {
+        if !resource.exists() {
+            return Error::invalid_options(format!(
+                "Resource {} does not exist",
+                resource.display()
+            ))
+            .into();
+        }
+        let name = resource.file_name().context(|| {
+            Error::invalid_options(format!("Invalid resource {}", resource.display()))
+        })?;
+        if let Some(other) = placed_names.insert(name.to_os_string(), resource.clone()) {
+            return Error::invalid_options(format!(
+                "{} and {} would both be placed as {} in the resources of the bundle",
+                other.display(),
+                resource.display(),
+                name.to_string_lossy()
+            ))
+            .hint("Rename one of them or place them into a directory passed with --resources")
+            .into();
+        }
+    }
+    Ok(())
+}
+
+pub fn create_directory(directory: impl AsRef<Path>) -> Result<()> {
+    let directory = directory.as_ref();
+    std::fs::create_dir_all(directory)
+        .context(|| Error::io(format!("Could not create {}", directory.display())))
+}
+
+/// Make sure that the fetched sources of a library are the ones pinned in the versions file before compiling them
+fn verify_sources(
+    library_name: &str,
+    sources_directory: &Path,
+    pinned: &PinnedVersion,
+) -> Result<()> {
+    if let Some(commit) = &pinned.commit {
+        verify_commit(library_name, sources_directory, commit)?;
+    }
+
+    if let Some(expected) = &pinned.sources {
+        let actual = checksums::sources_sha256(sources_directory)?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Error::library(format!(
+                "Checksum mismatch of the sources of {}: {} has SHA-256 {}, but {} is pinned",
+                library_name,
+                sources_directory.display(),
+                actual,
+                expected
+            ))
+            .hint("The release may have been re-tagged or its archive replaced. Verify the sources and update the versions file")
+            .into();
+        }
+    }
+    Ok(())
+}
+
+/// Make sure that the sources of a library are checked out at the commit pinned in the versions file
+fn verify_commit(library_name: &str, sources_directory: &Path, commit: &str) -> Result<()> {
+    for repository in library_repositories(sources_directory) {
+        let output = Command::new("git")
+            .current_dir(&repository)
+            .args(["rev-parse", "HEAD"])
+            .output();
+        if let Ok(output) = output {
+            if output.status.success() {
+                let checked_out = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if checked_out.starts_with(&commit.to_lowercase()) {
+                    return Ok(());
+                }
+                return Error::library(format!(
+                    "The sources of {} in {} are checked out at {} instead of the pinned commit {}",
+                    library_name,
+                    repository.display(),
+                    checked_out,
+                    commit
+                ))
+                .hint("The release may have been re-tagged. Verify the new commit and update the versions file, or remove the sources to check them out again")
+                .into();
+            }
+        }
+    }
+
+    Error::library(format!(
+        "Could not find the git checkout of {} in {} to verify the pinned commit {}",
+        library_name,
+        sources_directory.display(),
+        commit
+    ))
+    .hint("Libraries that are not fetched with git can be pinned by their `sources` checksum instead of a commit")
+    .into()
+}
+
+/// Make sure that a compiled or downloaded library matches the checksum pinned for the target
+fn verify_checksum(library_name: &str, library: &Path, options: &BundleOptions) -> Result<()> {
+    let target = options.target().to_string();
+    let expected = match options
+        .pinned_library(library_name)
+        .and_then(|pinned| pinned.sha256.get(&target))
+    {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    let actual = checksums::sha256_of_file(library)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Error::library(format!(
+            "Checksum mismatch of {} for {}: {} has SHA-256 {}, but {} is pinned",
+            library_name,
+            target,
+            library.display(),
+            actual,
+            expected
+        ))
+        .hint("The prebuilt binary or release may have been replaced. Verify it and update the versions file")
+        .into();
+    }
+    Ok(())
+}
+
+fn has_entries(directory: &Path) -> bool {
+    directory
+        .read_dir()
+        .map_or(false, |mut entries| entries.next().is_some())
+}
+
+fn modification_time(path: &Path) -> Option<std::time::SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
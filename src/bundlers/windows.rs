@@ -1,12 +1,12 @@
-use crate::bundlers::Bundler;
+use crate::bundlers::{create_directory, Bundler};
 use crate::options::BundleOptions;
-use crate::{Executable, ExecutableOptions};
+use crate::requirements::{common_requirements, Requirement};
+use crate::{Context, Error, Executable, ExecutableOptions, Result, Target};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::ffi::OsString;
 use std::fs;
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use user_error::UserFacingError;
 
 #[derive(Debug, Clone)]
 pub struct WindowsBundler {}
@@ -18,20 +18,24 @@ impl WindowsBundler {
         Self {}
     }
 
-    pub fn create_ico(&self, options: &ExecutableOptions) -> Option<PathBuf> {
+    pub fn create_ico(&self, options: &ExecutableOptions) -> Result<Option<PathBuf>> {
         for icon in options.icons() {
             let icon_path = Path::new(&icon);
             if icon_path.exists() {
                 if let Some(extension) = icon_path.extension() {
                     if extension == "ico" {
-                        let icon_path =
-                            fs::canonicalize(icon_path).expect("Icon could not be located");
-                        return Some(icon_path);
+                        let icon_path = fs::canonicalize(icon_path).context(|| {
+                            Error::invalid_options(format!(
+                                "Icon {} could not be located",
+                                icon_path.display()
+                            ))
+                        })?;
+                        return Ok(Some(icon_path));
                     }
                 }
             }
         }
-        None
+        Ok(None)
     }
 
     fn set_stack_size(
@@ -39,31 +43,35 @@ impl WindowsBundler {
         bundle_options: &BundleOptions,
         binary: impl AsRef<Path>,
         size_in_bytes: usize,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<()> {
         let mut editbin =
             cc::windows_registry::find(bundle_options.target().to_string().as_str(), "editbin.exe")
-                .expect("Could not find editbin.exe");
+                .context(|| {
+                    Error::missing_tool("Could not find editbin.exe").hint(
+                        "Install Visual Studio Build Tools with the \"Desktop development with C++\" workload",
+                    )
+                })?;
 
         let binary = binary.as_ref();
-        if !editbin
+        let status = editbin
             .arg(format!("/STACK:{}", size_in_bytes))
             .arg(binary)
-            .status()?
-            .success()
-        {
-            return Err(Box::new(UserFacingError::new(format!(
-                "Failed to set /STACK of {}",
-                binary.display(),
-            ))));
+            .status()
+            .context(|| Error::missing_tool("Failed to start editbin.exe"))?;
+        if !status.success() {
+            return Error::compilation(format!("Failed to set /STACK of {}", binary.display()))
+                .into();
         }
         Ok(())
     }
 
-    fn temporary_directory(&self) -> PathBuf {
-        std::env::current_dir().unwrap().join("temp")
+    fn resource_file(&self, options: &ExecutableOptions) -> PathBuf {
+        options
+            .scratch_directory()
+            .join(format!("{}.rc", options.executable_name()))
     }
 
-    fn debug_symbol_file(binary: &Path) -> PathBuf {
+    pub fn debug_symbol_file(binary: &Path) -> PathBuf {
         // PDB filenames are derived from the executable name,
         // but MSVC/LLVM does not allow certain characters like "-"
         // in filenames for PDBs, so it replaces hyphens - with underscores _.
@@ -81,10 +89,20 @@ impl WindowsBundler {
 }
 
 impl Bundler for WindowsBundler {
-    fn pre_compile(&self, options: &ExecutableOptions) {
-        let temp_dir = self.temporary_directory();
+    fn requirements(&self, target: &Target) -> Vec<Requirement> {
+        let mut requirements = common_requirements(target);
+        requirements.push(Requirement::msvc_tool(
+            *target,
+            "editbin.exe",
+            "to set the stack size of executables",
+        ));
+        requirements
+    }
 
-        let icon = self.create_ico(options);
+    fn pre_compile(&self, options: &ExecutableOptions) -> Result<()> {
+        let scratch_dir = options.scratch_directory();
+
+        let icon = self.create_ico(options)?;
 
         let info = Info {
             bundle_name: options.app_name().to_owned(),
@@ -99,38 +117,47 @@ impl Bundler for WindowsBundler {
             executable_name: options.executable_name(),
         };
 
-        let resource = mustache::compile_str(RESOURCE).unwrap();
-        let manifest = mustache::compile_str(MANIFEST).unwrap();
+        let resource = mustache::compile_str(RESOURCE)?;
+        let manifest = mustache::compile_str(MANIFEST)?;
 
-        if !temp_dir.exists() {
-            fs::create_dir_all(&temp_dir).unwrap();
+        if !scratch_dir.exists() {
+            create_directory(&scratch_dir)?;
         }
 
-        let resource_file_path = temp_dir.join(format!("{}.rc", options.executable_name()));
+        let resource_file_path = self.resource_file(options);
 
-        let manifest_file_path = temp_dir.join(format!("{}.manifest", options.executable_name()));
+        let manifest_file_path =
+            scratch_dir.join(format!("{}.manifest", options.executable_name()));
 
-        let mut resource_file = File::create(&resource_file_path).unwrap();
-        let mut manifest_file = File::create(&manifest_file_path).unwrap();
+        let mut resource_file = File::create(&resource_file_path)
+            .context(|| Error::io(format!("Could not create {}", resource_file_path.display())))?;
+        let mut manifest_file = File::create(&manifest_file_path)
+            .context(|| Error::io(format!("Could not create {}", manifest_file_path.display())))?;
 
-        resource.render(&mut resource_file, &info).unwrap();
-        manifest.render(&mut manifest_file, &info).unwrap();
+        resource.render(&mut resource_file, &info)?;
+        manifest.render(&mut manifest_file, &info)?;
+        Ok(())
+    }
 
-        std::env::set_var(
-            "VM_CLIENT_EMBED_RESOURCES",
-            format!("{}", &resource_file_path.display()),
-        );
+    fn compile_environment(&self, options: &ExecutableOptions) -> Vec<(String, OsString)> {
+        let mut environment = options.cargo_environment();
+        environment.push((
+            "VM_CLIENT_EMBED_RESOURCES".to_string(),
+            self.resource_file(options).into_os_string(),
+        ));
+        environment
     }
 
     fn post_compile(
         &self,
         bundle_options: &BundleOptions,
         executable: &Executable,
-        _executable_options: &ExecutableOptions,
-    ) {
-        let temp_dir = self.temporary_directory();
-        if temp_dir.exists() {
-            fs::remove_dir_all(&temp_dir).unwrap();
+        executable_options: &ExecutableOptions,
+    ) -> Result<()> {
+        let scratch_dir = executable_options.scratch_directory();
+        if scratch_dir.exists() {
+            fs::remove_dir_all(&scratch_dir)
+                .context(|| Error::io(format!("Could not remove {}", scratch_dir.display())))?;
         }
 
         self.set_stack_size(
@@ -138,79 +165,53 @@ impl Bundler for WindowsBundler {
             bundle_options.compiled_executable_path(executable),
             STACK_SIZE,
         )
-        .expect("Failed to set /STACK size");
     }
 
-    fn bundle(&self, options: &BundleOptions) {
-        let bundle_location = options.bundle_location();
-        let app_name = options.app_name();
-
-        let app_dir = bundle_location.join(&app_name);
+    fn bundle(&self, options: &BundleOptions) -> Result<()> {
         let binary_dir = self.bundled_executable_directory(options);
 
-        if app_dir.exists() {
-            fs::remove_dir_all(&app_dir).unwrap();
-        }
-        fs::create_dir_all(&app_dir).unwrap();
-        fs::create_dir(&binary_dir).unwrap();
+        let mut bundle = self.start_bundle(options)?;
+        create_directory(&binary_dir)?;
 
-        options.executables().iter().for_each(|executable| {
+        for executable in options.executables() {
             let compiled_executable_path = options.compiled_executable_path(executable);
             let bundled_executable_path =
                 binary_dir.join(options.bundled_executable_name(executable));
-            match fs::copy(&compiled_executable_path, &bundled_executable_path) {
-                Ok(_) => {}
-                Err(error) => {
-                    panic!(
-                        "Could not copy {} to {} due to {}",
-                        &compiled_executable_path.display(),
-                        &bundled_executable_path.display(),
-                        error
-                    );
-                }
-            };
+            bundle.copy_file(&compiled_executable_path, &bundled_executable_path)?;
 
             if options.include_debug_symbols() {
                 let compiled_symbols_path = Self::debug_symbol_file(&compiled_executable_path);
                 let bundled_symbols_path = Self::debug_symbol_file(
                     &binary_dir.join(options.compiled_executable_name(executable)),
                 );
-
-                match fs::copy(&compiled_symbols_path, &bundled_symbols_path) {
-                    Ok(_) => {}
-                    Err(error) => {
-                        panic!(
-                            "Could not copy {} to {} due to {}",
-                            &compiled_symbols_path.display(),
-                            &bundled_symbols_path.display(),
-                            error
-                        );
-                    }
-                };
+                let bundled_symbols_path = bundle.debug_symbols_path(bundled_symbols_path);
+                bundle
+                    .debug_symbols()
+                    .copy_file(&compiled_symbols_path, &bundled_symbols_path)?;
             }
-        });
+        }
 
-        fs_extra::copy_items(
-            &self.compiled_libraries(options),
-            &binary_dir,
-            &fs_extra::dir::CopyOptions::new(),
-        )
-        .unwrap();
+        let compiled_libraries = self.compiled_libraries(options)?;
+        bundle.copy_items(&compiled_libraries, &binary_dir)?;
 
         if options.include_debug_symbols() {
-            let compiled_libraries_debug_symbols: Vec<PathBuf> = self
-                .compiled_libraries(options)
+            let compiled_libraries_debug_symbols: Vec<PathBuf> = compiled_libraries
                 .iter()
                 .map(|each| Self::debug_symbol_file(each))
                 .filter(|each| each.exists())
                 .collect();
-            fs_extra::copy_items(
-                &compiled_libraries_debug_symbols,
-                &binary_dir,
-                &fs_extra::dir::CopyOptions::new(),
-            )
-            .unwrap();
+            let symbols_dir = bundle.debug_symbols_path(&binary_dir);
+            bundle
+                .debug_symbols()
+                .copy_items(&compiled_libraries_debug_symbols, symbols_dir)?;
         }
+
+        self.bundle_resources(options, &mut bundle)?;
+        bundle.finish()
+    }
+
+    fn bundled_app_directory(&self, options: &BundleOptions) -> PathBuf {
+        options.bundle_location().join(options.app_name())
     }
 
     fn bundled_executable_directory(&self, options: &BundleOptions) -> PathBuf {
@@ -0,0 +1,182 @@
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// Compute a lowercase hex encoded SHA-256 of the content of a file
+pub fn sha256_of_file(path: impl AsRef<Path>) -> Result<String> {
+    digest_of_file::<Sha256>(path.as_ref())
+}
+
+/// Compute a lowercase hex encoded SHA-1 of the content of a file
+pub fn sha1_of_file(path: impl AsRef<Path>) -> Result<String> {
+    digest_of_file::<Sha1>(path.as_ref())
+}
+
+/// Compute a lowercase hex encoded SHA-256 of the content of a file, or of the path
+/// a symbolic link points to so that links are compared without following them
+pub fn sha256_of_entry(path: impl AsRef<Path>) -> Result<String> {
+    let path = path.as_ref();
+    match link_target(path)? {
+        Some(target) => Ok(to_hex(Sha256::digest(target.as_bytes()).as_slice())),
+        None => sha256_of_file(path),
+    }
+}
+
+/// The path a symbolic link points to, or None if the path is not a symbolic link
+pub fn link_target(path: impl AsRef<Path>) -> Result<Option<String>> {
+    let path = path.as_ref();
+    let metadata = fs::symlink_metadata(path).map_err(|error| {
+        Error::new(format!("Could not read the metadata of {}", path.display())).from(error)
+    })?;
+    if !metadata.file_type().is_symlink() {
+        return Ok(None);
+    }
+    let target = fs::read_link(path).map_err(|error| {
+        Error::new(format!("Could not read the link {}", path.display())).from(error)
+    })?;
+    Ok(Some(target.to_string_lossy().to_string()))
+}
+
+fn digest_of_file<D: Digest>(path: &Path) -> Result<String> {
+    let mut file = File::open(path).map_err(|error| {
+        Error::new(format!(
+            "Could not open {} to compute its checksum",
+            path.display()
+        ))
+        .from(error)
+    })?;
+
+    let mut hasher = D::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(to_hex(hasher.finalize().as_slice()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Return all files within a directory and its subdirectories, sorted by path.
+/// Symbolic links are returned as files without following them
+pub fn files_in_directory(directory: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    collect_files(directory.as_ref(), &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.symlink_metadata()?.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A path relative to the root with `/` as a separator independent of the platform
+pub fn relative_path(root: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+    let relative = path.strip_prefix(root.as_ref()).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Write a checksum file in the format of `sha256sum` that lists the given files
+/// relative to the directory in which the checksum file is created
+pub fn write_checksums_file(checksums_file: impl AsRef<Path>, files: &[PathBuf]) -> Result<()> {
+    let checksums_file = checksums_file.as_ref();
+    let root = checksums_file
+        .parent()
+        .map(|parent| parent.to_path_buf())
+        .unwrap_or_default();
+
+    let mut entries = files
+        .iter()
+        .map(|file| Ok((relative_path(&root, file), sha256_of_file(file)?)))
+        .collect::<Result<Vec<(String, String)>>>()?;
+    entries.sort();
+
+    let mut file = File::create(checksums_file).map_err(|error| {
+        Error::new(format!("Could not create {}", checksums_file.display())).from(error)
+    })?;
+    for (path, checksum) in entries {
+        writeln!(&mut file, "{}  {}", checksum, path)?;
+    }
+
+    Ok(())
+}
+
+/// Summarize the paths, sizes and modification times of the sources in a directory,
+/// skipping hidden directories and cargo's `target`, to cheaply tell if any of them changed
+pub fn sources_fingerprint(directory: impl AsRef<Path>) -> Result<String> {
+    let directory = directory.as_ref();
+    let mut sources = vec![];
+    collect_sources(directory, &mut sources)?;
+    sources.sort();
+
+    let mut hasher = Sha256::new();
+    for source in sources {
+        let metadata = fs::metadata(&source)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        hasher.update(relative_path(directory, &source).as_bytes());
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(modified.as_nanos().to_le_bytes());
+    }
+    Ok(to_hex(hasher.finalize().as_slice()))
+}
+
+/// Compute a SHA-256 of the relative paths and contents of the sources in a directory,
+/// skipping hidden directories like `.git` and cargo's `target`
+pub fn sources_sha256(directory: impl AsRef<Path>) -> Result<String> {
+    let directory = directory.as_ref();
+    let mut sources = vec![];
+    collect_sources(directory, &mut sources)?;
+    sources.sort();
+
+    let mut hasher = Sha256::new();
+    for source in sources {
+        hasher.update(relative_path(directory, &source).as_bytes());
+        hasher.update([0u8]);
+        hasher.update(sha256_of_file(&source)?.as_bytes());
+        hasher.update([0u8]);
+    }
+    Ok(to_hex(hasher.finalize().as_slice()))
+}
+
+fn collect_sources(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name != "target" && !name.starts_with('.') {
+                collect_sources(&path, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::bundlers::Bundler;
+use crate::commands::{human_size, size_of};
+use crate::{BundleOptions, CleanOptions, Context, Error, Result};
+
+/// Remove the artifacts selected by the clean options. The paths are computed the same way
+/// as when building, so that only what the builder produces is removed
+pub fn clean(
+    bundler: &dyn Bundler,
+    bundle_options: &BundleOptions,
+    clean_options: &CleanOptions,
+) -> Result<()> {
+    let paths = paths_to_clean(bundler, bundle_options, clean_options)?;
+
+    if paths.is_empty() {
+        println!("Nothing to clean, select what to clean with --bundle, --libraries, --library-sources, --executables or --all");
+        return Ok(());
+    }
+
+    let mut freed = 0;
+    for path in paths.iter().filter(|path| path.exists()) {
+        let size = size_of(path);
+        if clean_options.dry_run() {
+            println!("Would remove {} ({})", path.display(), human_size(size));
+        } else {
+            let result = if path.is_dir() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            };
+            result.map_err(|error| {
+                Error::new(format!("Could not remove {}", path.display())).from(error)
+            })?;
+            println!("Removed {} ({})", path.display(), human_size(size));
+        }
+        freed += size;
+    }
+
+    if clean_options.dry_run() {
+        println!("Would free {}", human_size(freed));
+    } else {
+        println!("Freed {}", human_size(freed));
+    }
+
+    Ok(())
+}
+
+fn paths_to_clean(
+    bundler: &dyn Bundler,
+    bundle_options: &BundleOptions,
+    clean_options: &CleanOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+
+    // only what bundling writes is removed, the bundle location may be any directory given with --bundle-dir
+    if clean_options.clean_bundle() {
+        paths.push(bundler.bundled_app_directory(bundle_options));
+        paths.extend(bundler.bundled_archives(bundle_options));
+        paths.push(bundle_options.symbols_directory());
+        paths.push(bundle_options.symbols_archive_path());
+    }
+
+    if clean_options.clean_executables() {
+        for target_options in bundle_options.target_variants()? {
+            for executable in target_options.executables() {
+                paths.push(target_options.executable_compilation_location(executable));
+                paths.push(target_options.scratch_directory(executable));
+            }
+            paths.push(target_options.build_info_path());
+        }
+    }
+
+    if let Some(names) = clean_options.clean_libraries() {
+        let build_directory = bundle_options.third_party_libraries_build_directory();
+        for library_name in library_names(bundle_options, names)? {
+            paths.push(build_directory.join(library_name));
+        }
+    }
+
+    if let Some(names) = clean_options.clean_library_sources() {
+        let sources_directory = bundle_options.third_party_libraries_sources_directory();
+        if names.is_empty() {
+            paths.push(sources_directory);
+        } else {
+            for library_name in library_names(bundle_options, names)? {
+                paths.push(sources_directory.join(library_name));
+            }
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Names of the libraries to clean as used for their directories, all known libraries if none are given
+fn library_names(bundle_options: &BundleOptions, names: &[String]) -> Result<Vec<String>> {
+    let known_libraries = bundle_options.known_library_names()?;
+    if names.is_empty() {
+        return Ok(known_libraries.into_values().collect());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            known_libraries
+                .iter()
+                .find(|(selected_name, library_name)| {
+                    selected_name.eq_ignore_ascii_case(name) || library_name.as_str() == name
+                })
+                .map(|(_, library_name)| library_name.clone())
+                .context(|| {
+                    Error::invalid_options(format!("Unknown library {}", name)).hint(format!(
+                        "Known libraries are {}",
+                        known_libraries
+                            .keys()
+                            .cloned()
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ))
+                })
+        })
+        .collect()
+}
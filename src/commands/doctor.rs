@@ -0,0 +1,90 @@
+use serde::Serialize;
+
+use crate::bundlers::Bundler;
+use crate::requirements::{RequirementCheck, RequirementStatus};
+use crate::{DoctorOptions, Error, Result, Target};
+
+#[derive(Debug, Serialize)]
+struct DoctorReport {
+    target: Target,
+    blockers: usize,
+    checks: Vec<RequirementCheck>,
+}
+
+/// Check that every tool and environment variable needed by the bundler
+/// of the target and by the selected libraries is available
+pub fn doctor(bundler: &dyn Bundler, options: &DoctorOptions) -> Result<()> {
+    let target = options.target();
+
+    let mut requirements = bundler.requirements(&target);
+    for library in options.libraries() {
+        requirements.extend(library.requirements(&target));
+    }
+
+    let mut checks: Vec<RequirementCheck> = vec![];
+    for requirement in requirements {
+        let name = requirement.name();
+        match checks.iter_mut().find(|check| check.name == name) {
+            Some(check) => check.reason = format!("{}, {}", check.reason, requirement.reason()),
+            None => checks.push(requirement.check()),
+        }
+    }
+
+    let blockers = checks.iter().filter(|check| check.is_blocker()).count();
+    let report = DoctorReport {
+        target,
+        blockers,
+        checks,
+    };
+
+    if options.json() {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+
+    if blockers > 0 {
+        return Error::missing_tool(format!(
+            "{} required tool(s) or environment variable(s) are missing for {}",
+            blockers,
+            target.to_string()
+        ))
+        .into();
+    }
+    Ok(())
+}
+
+fn print_report(report: &DoctorReport) {
+    println!("Checking the toolchain for {}", report.target.to_string());
+    for check in &report.checks {
+        let status = match check.status {
+            RequirementStatus::Found => "ok",
+            RequirementStatus::Warning => "warning",
+            RequirementStatus::Missing => "missing",
+        };
+        let details = [check.version.as_ref(), check.location.as_ref()]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        if details.is_empty() {
+            println!("[{:^7}] {} ({})", status, check.name, check.reason);
+        } else {
+            println!(
+                "[{:^7}] {} ({}): {}",
+                status, check.name, check.reason, details
+            );
+        }
+        if let Some(hint) = &check.hint {
+            println!("          hint: {}", hint);
+        }
+    }
+    if report.blockers == 0 {
+        println!(
+            "Everything needed to build for {} is available",
+            report.target.to_string()
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use xz2::read::XzDecoder;
+use zip::ZipArchive;
+
+use crate::checksums::files_in_directory;
+use crate::{Context, Error, InfoOptions, Result, BUILD_INFO_FILE};
+
+/// Find the build info in a bundle or its archive and print what the bundle was built with
+pub fn info(options: &InfoOptions) -> Result<()> {
+    let (location, content) = read_build_info(options.bundle())?;
+    let build_info: Value = serde_json::from_str(&content)
+        .context(|| Error::new(format!("Could not parse the build info in {}", &location)))?;
+
+    if options.json() {
+        println!("{}", serde_json::to_string_pretty(&build_info)?);
+        return Ok(());
+    }
+
+    let field = |pointer: &str| build_info.pointer(pointer).and_then(as_text);
+    let row = |name: &str, value: String| println!("{:<14}{}", name, value);
+    let unknown = || "unknown".to_string();
+
+    row("Build info", location.clone());
+    row(
+        "App",
+        format!(
+            "{} {}",
+            field("/app_name").unwrap_or_else(unknown),
+            field("/version").unwrap_or_else(unknown)
+        ),
+    );
+    if let Some(identifier) = field("/identifier") {
+        row("Identifier", identifier);
+    }
+    row("Target", field("/target").unwrap_or_else(unknown));
+    let release = build_info
+        .pointer("/builder_flags/release")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    row(
+        "Profile",
+        if release { "release" } else { "debug" }.to_string(),
+    );
+    row(
+        "App commit",
+        format!(
+            "{} on {}, built {}",
+            field("/app_build_info/git_sha").unwrap_or_else(unknown),
+            field("/app_build_info/git_branch").unwrap_or_else(unknown),
+            field("/app_build_info/build_timestamp").unwrap_or_else(unknown)
+        ),
+    );
+    row(
+        "Builder",
+        format!(
+            "{} ({}, built {})",
+            field("/builder_info/version").unwrap_or_else(unknown),
+            field("/builder_info/git_sha").unwrap_or_else(unknown),
+            field("/builder_info/build_timestamp").unwrap_or_else(unknown)
+        ),
+    );
+    row("Executables", list(build_info.pointer("/executables")));
+    row(
+        "Features",
+        list(build_info.pointer("/builder_flags/features")),
+    );
+
+    if let Some(epoch) = field("/source_date_epoch") {
+        row("Reproducible", format!("yes, SOURCE_DATE_EPOCH={}", epoch));
+    }
+    if build_info
+        .pointer("/non_release")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+    {
+        row(
+            "Non-release",
+            "yes, some libraries were built from local checkouts".to_string(),
+        );
+    }
+
+    let versions = build_info
+        .pointer("/library_versions")
+        .and_then(Value::as_object);
+    match versions {
+        Some(versions) if !versions.is_empty() => {
+            println!("Libraries");
+            let width = versions.keys().map(|name| name.len()).max().unwrap_or(0);
+            for (name, version) in versions {
+                let local_path = build_info
+                    .pointer(&format!("/local_libraries/{}", name))
+                    .and_then(as_text);
+                let version = match local_path {
+                    Some(path) => format!("local checkout {}", path),
+                    None => as_text(version).unwrap_or_else(|| "latest release".to_string()),
+                };
+                println!("  {:<width$}  {}", name, version, width = width);
+            }
+        }
+        _ => row("Libraries", "none".to_string()),
+    }
+
+    Ok(())
+}
+
+/// Read the build info from a bundle directory, an archive or the build-info.json itself.
+/// Returns where it was found together with its content
+fn read_build_info(path: &Path) -> Result<(String, String)> {
+    if !path.exists() {
+        return Error::invalid_options(format!("{} does not exist", path.display())).into();
+    }
+
+    if path.is_dir() {
+        let build_info = files_in_directory(path)?
+            .into_iter()
+            .filter(|file| {
+                file.file_name()
+                    .map_or(false, |name| name == BUILD_INFO_FILE)
+            })
+            .min_by_key(|file| file.components().count())
+            .context(|| not_found(path))?;
+        let content = fs::read_to_string(&build_info)
+            .context(|| Error::io(format!("Could not read {}", build_info.display())))?;
+        return Ok((build_info.display().to_string(), content));
+    }
+
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    if name == BUILD_INFO_FILE || name.ends_with(".json") {
+        let content = fs::read_to_string(path)
+            .context(|| Error::io(format!("Could not read {}", path.display())))?;
+        return Ok((path.display().to_string(), content));
+    }
+
+    let file =
+        File::open(path).context(|| Error::io(format!("Could not open {}", path.display())))?;
+    let (entry, content) = if [".zip", ".apk", ".aab"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+    {
+        read_from_zip(file)?
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        read_from_tar(GzDecoder::new(file))?
+    } else if name.ends_with(".tar.xz") {
+        read_from_tar(XzDecoder::new(file))?
+    } else if name.ends_with(".tar") {
+        read_from_tar(file)?
+    } else {
+        return Error::invalid_options(format!("Can not read the build info from {}", path.display()))
+            .hint("Pass a bundle directory, a .zip, .apk, .aab, .tar.gz or .tar.xz archive, or a build-info.json")
+            .into();
+    };
+
+    let entry = entry.context(|| not_found(path))?;
+    Ok((format!("{}!{}", path.display(), entry), content))
+}
+
+/// The build info closest to the root of a zip archive
+fn read_from_zip(file: File) -> Result<(Option<String>, String)> {
+    let mut archive = ZipArchive::new(file)?;
+    let entry = archive
+        .file_names()
+        .filter(|entry| is_build_info_entry(entry))
+        .min_by_key(|entry| entry.matches('/').count())
+        .map(|entry| entry.to_string());
+
+    let mut content = String::new();
+    if let Some(entry) = &entry {
+        archive.by_name(entry)?.read_to_string(&mut content)?;
+    }
+    Ok((entry, content))
+}
+
+/// The build info closest to the root of a tar archive
+fn read_from_tar(reader: impl Read) -> Result<(Option<String>, String)> {
+    let mut archive = tar::Archive::new(reader);
+    let mut found: Option<(String, String)> = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        if !is_build_info_entry(&entry_path) {
+            continue;
+        }
+        let depth = entry_path.matches('/').count();
+        if found
+            .as_ref()
+            .map_or(true, |(path, _)| depth < path.matches('/').count())
+        {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            found = Some((entry_path, content));
+        }
+    }
+    Ok(match found {
+        Some((entry, content)) => (Some(entry), content),
+        None => (None, String::new()),
+    })
+}
+
+fn is_build_info_entry(entry: &str) -> bool {
+    entry == BUILD_INFO_FILE || entry.ends_with(&format!("/{}", BUILD_INFO_FILE))
+}
+
+fn not_found(path: &Path) -> Error {
+    Error::invalid_options(format!(
+        "Could not find {} in {}",
+        BUILD_INFO_FILE,
+        path.display()
+    ))
+    .hint("Only bundles built by a recent gtoolkit-vm-builder contain the build info")
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        Value::Object(object)
+            if object.contains_key("major")
+                && object.contains_key("minor")
+                && object.contains_key("patch") =>
+        {
+            Some(format!(
+                "{}.{}.{}",
+                object["major"], object["minor"], object["patch"]
+            ))
+        }
+        _ => Some(value.to_string()),
+    }
+}
+
+fn list(value: Option<&Value>) -> String {
+    let items = value
+        .and_then(Value::as_array)
+        .map(|items| items.iter().filter_map(as_text).collect::<Vec<String>>())
+        .unwrap_or_default();
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
@@ -0,0 +1,53 @@
+mod clean;
+mod doctor;
+mod info;
+mod report;
+mod reproducible;
+mod vendor;
+mod verify_integrity;
+
+pub use clean::clean;
+pub use doctor::doctor;
+pub use info::info;
+pub use report::report_diff;
+pub use reproducible::compare_bundles;
+pub use vendor::vendor;
+pub use verify_integrity::verify_integrity;
+
+use std::fs;
+use std::path::Path;
+
+/// Return the size of a file or the total size of all files within a directory
+pub fn size_of(path: impl AsRef<Path>) -> u64 {
+    let path = path.as_ref();
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| size_of(entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Format a number of bytes for humans, for example 12.3 MB
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,131 @@
+use crate::commands::human_size;
+use crate::report::{seconds, BuildReport};
+use crate::{ReportDiffOptions, Result};
+
+/// Print how the duration of every phase and the size of every file changed between two builds
+pub fn report_diff(options: &ReportDiffOptions) -> Result<()> {
+    let old = BuildReport::read(options.old_report())?;
+    let new = BuildReport::read(options.new_report())?;
+
+    let mut phases: Vec<String> = vec![];
+    for timing in old.phases.iter().chain(new.phases.iter()) {
+        let key = timing.key();
+        if !phases.contains(&key) {
+            phases.push(key);
+        }
+    }
+
+    let width = phases
+        .iter()
+        .map(|key| key.len())
+        .chain(new.files.iter().map(|file| file.name.len()))
+        .chain(old.files.iter().map(|file| file.name.len()))
+        .max()
+        .unwrap_or(0)
+        .max(5);
+
+    println!(
+        "{:<width$} {:>10} {:>10} {:>10} {:>8}",
+        "Phase",
+        "Old",
+        "New",
+        "Change",
+        "",
+        width = width
+    );
+    for key in &phases {
+        let duration_of = |report: &BuildReport| -> Option<u64> {
+            let durations = report
+                .phases
+                .iter()
+                .filter(|timing| &timing.key() == key)
+                .map(|timing| timing.duration_ms)
+                .collect::<Vec<u64>>();
+            (!durations.is_empty()).then(|| durations.iter().sum())
+        };
+        print_row(key, duration_of(&old), duration_of(&new), seconds, width);
+    }
+    print_row(
+        "total",
+        Some(old.duration_ms),
+        Some(new.duration_ms),
+        seconds,
+        width,
+    );
+
+    let mut files: Vec<String> = vec![];
+    for file in old.files.iter().chain(new.files.iter()) {
+        if !files.contains(&file.name) {
+            files.push(file.name.clone());
+        }
+    }
+
+    if !files.is_empty() {
+        println!();
+        println!(
+            "{:<width$} {:>10} {:>10} {:>10} {:>8}",
+            "File",
+            "Old",
+            "New",
+            "Change",
+            "",
+            width = width
+        );
+        for name in &files {
+            let size_of = |report: &BuildReport| -> Option<u64> {
+                report
+                    .files
+                    .iter()
+                    .find(|file| &file.name == name)
+                    .map(|file| file.size)
+            };
+            print_row(name, size_of(&old), size_of(&new), format_size, width);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_row(
+    name: &str,
+    old: Option<u64>,
+    new: Option<u64>,
+    format: impl Fn(i64) -> String,
+    width: usize,
+) {
+    let column = |value: Option<u64>| value.map_or("-".to_string(), |value| format(value as i64));
+
+    let (change, percentage) = match (old, new) {
+        (Some(old), Some(new)) => {
+            let change = new as i64 - old as i64;
+            let sign = if change > 0 { "+" } else { "" };
+            let percentage = if old > 0 {
+                format!("{}{:.1}%", sign, change as f64 * 100.0 / old as f64)
+            } else {
+                "".to_string()
+            };
+            (format!("{}{}", sign, format(change)), percentage)
+        }
+        (None, Some(_)) => ("added".to_string(), "".to_string()),
+        (Some(_), None) => ("removed".to_string(), "".to_string()),
+        (None, None) => ("".to_string(), "".to_string()),
+    };
+
+    println!(
+        "{:<width$} {:>10} {:>10} {:>10} {:>8}",
+        name,
+        column(old),
+        column(new),
+        change,
+        percentage,
+        width = width
+    );
+}
+
+fn format_size(bytes: i64) -> String {
+    if bytes < 0 {
+        format!("-{}", human_size(bytes.unsigned_abs()))
+    } else {
+        human_size(bytes as u64)
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::checksums::{files_in_directory, relative_path, sha256_of_entry};
+use crate::{Context, Error, Result};
+
+/// Compare the files of the bundles of two builds and fail with the list of differences
+pub fn compare_bundles(first: &Path, second: &Path) -> Result<()> {
+    let first_files = checksums_of_bundle(first)?;
+    let second_files = checksums_of_bundle(second)?;
+
+    let mut differences = vec![];
+    for (path, checksum) in &first_files {
+        match second_files.get(path) {
+            None => differences.push(format!("  - {} is only in {}", path, first.display())),
+            Some(second_checksum) if second_checksum != checksum => {
+                differences.push(format!("  - {} differs", path))
+            }
+            Some(_) => {}
+        }
+    }
+    for path in second_files
+        .keys()
+        .filter(|path| !first_files.contains_key(*path))
+    {
+        differences.push(format!("  - {} is only in {}", path, second.display()));
+    }
+
+    if !differences.is_empty() {
+        return Error::bundling(format!(
+            "The bundles of two reproducible builds differ in {} files:\n{}",
+            differences.len(),
+            differences.join("\n")
+        ))
+        .hint(format!(
+            "Compare the differing files in {} and {}, for example with diffoscope",
+            first.display(),
+            second.display()
+        ))
+        .into();
+    }
+
+    println!(
+        "The bundles in {} and {} are identical ({} files)",
+        first.display(),
+        second.display(),
+        first_files.len()
+    );
+    Ok(())
+}
+
+/// SHA-256 of every file in a bundle by its path relative to the bundle
+fn checksums_of_bundle(bundle: &Path) -> Result<BTreeMap<String, String>> {
+    let files = files_in_directory(bundle)
+        .context(|| Error::bundling(format!("Could not list the files of {}", bundle.display())))?;
+    files
+        .iter()
+        .map(|file| Ok((relative_path(bundle, file), sha256_of_entry(file)?)))
+        .collect()
+}
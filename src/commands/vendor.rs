@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use shared_library_builder::{Library, LibraryCompilationContext, LibraryTarget};
+
+use crate::bundlers::create_directory;
+use crate::environment::{tool_environment, ScopedEnvironment};
+use crate::events::Phase;
+use crate::{events, BundleOptions, Context, Error, Result, VendorOptions};
+
+/// A name of the versions file written into the vendored directory
+pub const VENDORED_VERSIONS_FILE: &str = "libraries.version";
+/// A name of the file with the builder options for an offline build of the vendored directory
+pub const VENDORED_BUILD_OPTIONS_FILE: &str = "build-offline.args";
+
+/// Put the vm-client workspace, the sources of the selected libraries at their resolved versions,
+/// the crates the workspace depends on and the VMMaker VM and image into one directory,
+/// so that it can be built with `--offline` on a machine without network access
+pub fn vendor(bundle_options: &BundleOptions, vendor_options: &VendorOptions) -> Result<()> {
+    let workspace = bundle_options.workspace_directory().context(|| {
+        Error::invalid_options("Could not find the workspace of the vm-client to vendor")
+    })?;
+    let directory = absolute(vendor_options.directory())?;
+    create_directory(&directory)?;
+
+    events::phase(Phase::Vendor, "workspace", || {
+        copy_workspace(&workspace, &directory, &directory)
+    })?;
+
+    let mut checkouts = BTreeMap::new();
+    for target_options in bundle_options.target_variants()? {
+        for library in target_options.libraries() {
+            if checkouts.contains_key(library.name()) {
+                continue;
+            }
+            if let Some(path) = target_options.local_library_path(library.name()) {
+                return Error::invalid_options(format!(
+                    "Can not vendor {} built from the local checkout {}",
+                    library.name(),
+                    path.display()
+                ))
+                .hint("Vendor released libraries, without --library-path")
+                .into();
+            }
+            if target_options.is_built_from_local_path(library.name()) {
+                continue;
+            }
+            let sources_directory = directory.join("libs").join(library.name());
+            let build_directory = directory.join("target").join(library.name());
+            events::phase(Phase::Vendor, library.name(), || {
+                checkout_library(
+                    library,
+                    &target_options,
+                    &sources_directory,
+                    &build_directory,
+                )
+            })?;
+            checkouts.insert(library.name().to_string(), sources_directory);
+        }
+    }
+
+    events::phase(Phase::Vendor, "crates", || {
+        vendor_crates(&directory, bundle_options)
+    })?;
+
+    let mut build_options = vec![
+        "--offline".to_string(),
+        "--libraries-versions".to_string(),
+        VENDORED_VERSIONS_FILE.to_string(),
+    ];
+
+    if let Some(vmmaker_vm) = bundle_options.vmmaker_vm() {
+        // the vm may be prefixed with its type, for example pharo:/path/to/vm
+        let vmmaker_vm = vmmaker_vm.to_string_lossy().to_string();
+        let (vm_type, vm_path) = match vmmaker_vm.split_once(':') {
+            Some((vm_type, vm_path)) if vm_type == "gtoolkit" || vm_type == "pharo" => {
+                (Some(vm_type), vm_path)
+            }
+            _ => (None, vmmaker_vm.as_str()),
+        };
+        let vm = events::phase(Phase::Vendor, "vmmaker-vm", || {
+            copy_next_to(Path::new(vm_path), &directory.join("vmmaker").join("vm"))
+        })?;
+        build_options.push("--vmmaker-vm".to_string());
+        build_options.push(match vm_type {
+            Some(vm_type) => format!("{}:{}", vm_type, relative_to(&directory, &vm)),
+            None => relative_to(&directory, &vm),
+        });
+    }
+
+    if let Some(vmmaker_image) = bundle_options.vmmaker_image() {
+        let image = events::phase(Phase::Vendor, "vmmaker-image", || {
+            copy_next_to(vmmaker_image, &directory.join("vmmaker").join("image"))
+        })?;
+        build_options.push("--vmmaker-image".to_string());
+        build_options.push(relative_to(&directory, &image));
+    }
+
+    if let Some(libraries) = vendor_options.builder_options().libraries() {
+        build_options.push("--libraries".to_string());
+        build_options.extend(libraries.iter().map(|library| library.to_string()));
+    }
+
+    let versions = bundle_options.pinned_libraries_versions(|name| {
+        checkouts
+            .get(name)
+            .and_then(|sources_directory| checked_out_tag(sources_directory))
+    })?;
+    let versions_file = directory.join(VENDORED_VERSIONS_FILE);
+    fs::write(&versions_file, serde_json::to_string_pretty(&versions)?)
+        .context(|| Error::io(format!("Could not write {}", versions_file.display())))?;
+
+    let build_options_file = directory.join(VENDORED_BUILD_OPTIONS_FILE);
+    fs::write(&build_options_file, build_options.join(" "))
+        .context(|| Error::io(format!("Could not write {}", build_options_file.display())))?;
+
+    println!(
+        "Vendored into {}, build it without network access from that directory with:",
+        directory.display()
+    );
+    println!("  gtoolkit-vm-builder build {}", build_options.join(" "));
+    Ok(())
+}
+
+/// Copy the sources of the workspace skipping build artifacts, hidden files and the vendored directory
+fn copy_workspace(from: &Path, to: &Path, vendored_directory: &Path) -> Result<()> {
+    create_directory(to)?;
+    let entries = from
+        .read_dir()
+        .context(|| Error::io(format!("Could not read {}", from.display())))?;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "target" || name == "libs" || path == vendored_directory
+        {
+            continue;
+        }
+        let destination = to.join(path.file_name().unwrap_or_default());
+        if path.is_dir() {
+            copy_workspace(&path, &destination, vendored_directory)?;
+        } else {
+            fs::copy(&path, &destination).context(|| {
+                Error::io(format!(
+                    "Could not copy {} to {}",
+                    path.display(),
+                    destination.display()
+                ))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Check out the sources of a library into the vendored directory,
+/// the same way they are checked out before compiling it
+fn checkout_library(
+    library: &Box<dyn Library>,
+    options: &BundleOptions,
+    sources_directory: &Path,
+    build_directory: &Path,
+) -> Result<()> {
+    create_directory(sources_directory)?;
+    create_directory(build_directory)?;
+
+    let library_target =
+        LibraryTarget::try_from(options.target().to_string().as_str()).context(|| {
+            Error::invalid_options(format!(
+                "Libraries can not be compiled for {}",
+                options.target().to_string()
+            ))
+        })?;
+
+    let context = LibraryCompilationContext::new(
+        sources_directory.to_path_buf(),
+        build_directory.to_path_buf(),
+        library_target,
+        !options.release(),
+    );
+    let _environment = ScopedEnvironment::set(tool_environment(options));
+    library.ensure_sources(&context).context(|| {
+        Error::library(format!(
+            "Failed to check out the sources of {}",
+            library.name()
+        ))
+    })?;
+
+    Ok(())
+}
+
+/// Run `cargo vendor` in the copied workspace and let cargo use the vendored crates
+fn vendor_crates(workspace: &Path, options: &BundleOptions) -> Result<()> {
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(workspace)
+        .envs(tool_environment(options))
+        .arg("vendor")
+        .arg("--versioned-dirs")
+        .arg("vendor");
+    let output = command.output().context(|| {
+        Error::missing_tool(format!("Failed to start {:?}", &command))
+            .hint("Make sure that cargo is installed and is in the PATH")
+    })?;
+    if !output.status.success() {
+        return Error::new(format!(
+            "Failed to vendor the crates of {}:\n{}",
+            workspace.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+        .into();
+    }
+
+    let cargo_directory = workspace.join(".cargo");
+    create_directory(&cargo_directory)?;
+    let cargo_config = cargo_directory.join("config.toml");
+    let mut config = fs::read_to_string(&cargo_config).unwrap_or_default();
+    config.push_str(&String::from_utf8_lossy(&output.stdout));
+    fs::write(&cargo_config, config)
+        .context(|| Error::io(format!("Could not write {}", cargo_config.display())))
+}
+
+/// Copy a file together with the other files in its directory, for example an image
+/// with its .changes and .sources. Returns the path of the copied file
+fn copy_next_to(file: &Path, directory: &Path) -> Result<PathBuf> {
+    let source_directory = file
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    create_directory(directory)?;
+    let mut copy_options = fs_extra::dir::CopyOptions::new();
+    copy_options.overwrite = true;
+    copy_options.content_only = true;
+    fs_extra::dir::copy(source_directory, directory, &copy_options).context(|| {
+        Error::io(format!(
+            "Could not copy {} to {}",
+            source_directory.display(),
+            directory.display()
+        ))
+    })?;
+    Ok(directory.join(file.file_name().unwrap_or_default()))
+}
+
+/// A tag that points at the checked out commit of a git repository
+fn checked_out_tag(repository: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(repository)
+        .args(["describe", "--tags", "--exact-match"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let tag = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if tag.is_empty() {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+fn relative_to(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
@@ -0,0 +1,49 @@
+use crate::{integrity, Error, Result, VerifyIntegrityOptions};
+
+/// Check the files of a bundle against its integrity manifest
+/// and fail with the missing, modified and unexpected files
+pub fn verify_integrity(options: &VerifyIntegrityOptions) -> Result<()> {
+    let report = integrity::verify(options.bundle())?;
+
+    if report.is_intact() {
+        println!(
+            "All {} files of {} match {}",
+            report.checked,
+            report.app_directory.display(),
+            report.manifest.display()
+        );
+        return Ok(());
+    }
+
+    let mut differences = vec![];
+    differences.extend(
+        report
+            .missing
+            .iter()
+            .map(|path| format!("  - {} is missing", path)),
+    );
+    differences.extend(
+        report
+            .modified
+            .iter()
+            .map(|(path, change)| format!("  - {} is modified: {}", path, change)),
+    );
+    differences.extend(
+        report
+            .unexpected
+            .iter()
+            .map(|path| format!("  - {} is unexpected", path)),
+    );
+
+    Error::bundling(format!(
+        "{} does not match {}: {} missing, {} modified and {} unexpected files:\n{}",
+        report.app_directory.display(),
+        report.manifest.display(),
+        report.missing.len(),
+        report.modified.len(),
+        report.unexpected.len(),
+        differences.join("\n")
+    ))
+    .hint("Re-install the bundle from its original archive")
+    .into()
+}
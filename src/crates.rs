@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::{Context, Error, Result};
+
+/// A Rust crate the compiled executables are built from, as reported by `cargo metadata`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Crate {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    /// Where the crate comes from, for example crates.io or a git repository.
+    /// None for the crates of the workspace
+    pub source: Option<String>,
+    /// A license as an SPDX expression
+    pub license: Option<String>,
+    /// A file with the license, relative to the manifest, for crates with a non-standard license
+    pub license_file: Option<String>,
+    pub repository: Option<String>,
+    pub manifest_path: PathBuf,
+    /// Ids of the crates compiled into this one, dev- and build-dependencies excluded
+    #[serde(skip)]
+    pub dependencies: Vec<String>,
+}
+
+impl Crate {
+    pub fn directory(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// The license as an SPDX expression, old crates separate alternatives with `/`
+    pub fn license_expression(&self) -> Option<String> {
+        self.license.as_ref().map(|license| {
+            license
+                .split('/')
+                .map(|each| each.trim())
+                .collect::<Vec<&str>>()
+                .join(" OR ")
+        })
+    }
+
+    /// A package url as used by SBOM formats, for example `pkg:cargo/serde@1.0.136`
+    pub fn purl(&self) -> String {
+        format!("pkg:cargo/{}@{}", &self.name, &self.version)
+    }
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Crate>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+struct Resolve {
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    id: String,
+    #[serde(default)]
+    deps: Vec<NodeDependency>,
+}
+
+#[derive(Deserialize)]
+struct NodeDependency {
+    pkg: String,
+    #[serde(default)]
+    dep_kinds: Vec<DependencyKind>,
+}
+
+#[derive(Deserialize)]
+struct DependencyKind {
+    kind: Option<String>,
+}
+
+impl NodeDependency {
+    /// A normal dependency, as opposed to the dev- and build-dependencies that do not end up in the binaries.
+    /// Old versions of cargo do not report the kinds
+    fn is_normal(&self) -> bool {
+        self.dep_kinds.is_empty() || self.dep_kinds.iter().any(|kind| kind.kind.is_none())
+    }
+}
+
+/// The given packages of the workspace and all crates compiled into them for a target with the given features,
+/// sorted by name and version. Cargo runs with the given environment, for example to stay offline
+pub fn dependencies_of(
+    workspace: &Path,
+    packages: &[&str],
+    features: &[String],
+    target: &str,
+    environment: Vec<(String, OsString)>,
+) -> Result<Vec<Crate>> {
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(workspace)
+        .envs(environment)
+        .arg("metadata")
+        .arg("--format-version")
+        .arg("1")
+        .arg("--filter-platform")
+        .arg(target);
+    if !features.is_empty() {
+        // every package is compiled with the same features, so each of them has them
+        let features = packages
+            .iter()
+            .flat_map(|package| {
+                features
+                    .iter()
+                    .map(move |feature| format!("{}/{}", package, feature))
+            })
+            .collect::<Vec<String>>();
+        command.arg("--features").arg(features.join(","));
+    }
+    let output = command.output().context(|| {
+        Error::missing_tool(format!("Failed to start {:?}", &command))
+            .hint("Make sure that cargo is installed and is in the PATH")
+    })?;
+    if !output.status.success() {
+        return Error::new(format!(
+            "Failed to read the dependencies of {}:\n{}",
+            workspace.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+        .into();
+    }
+
+    let metadata: Metadata = serde_json::from_slice(&output.stdout)
+        .context(|| Error::new("Could not parse the output of cargo metadata"))?;
+
+    let dependencies: BTreeMap<String, Vec<String>> = metadata
+        .resolve
+        .map(|resolve| resolve.nodes)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|node| {
+            let dependencies = node
+                .deps
+                .iter()
+                .filter(|dependency| dependency.is_normal())
+                .map(|dependency| dependency.pkg.clone())
+                .collect();
+            (node.id, dependencies)
+        })
+        .collect();
+
+    let mut crates: BTreeMap<String, Crate> = metadata
+        .packages
+        .into_iter()
+        .map(|mut each| {
+            each.dependencies = dependencies.get(&each.id).cloned().unwrap_or_default();
+            (each.id.clone(), each)
+        })
+        .collect();
+
+    let mut reachable = BTreeSet::new();
+    let mut pending = crates
+        .values()
+        .filter(|each| each.source.is_none() && packages.contains(&each.name.as_str()))
+        .map(|each| each.id.clone())
+        .collect::<Vec<String>>();
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id.clone()) {
+            if let Some(each) = crates.get(&id) {
+                pending.extend(each.dependencies.iter().cloned());
+            }
+        }
+    }
+
+    let mut result = reachable
+        .into_iter()
+        .filter_map(|id| crates.remove(&id))
+        .collect::<Vec<Crate>>();
+    result.sort_by(|first, second| {
+        (&first.name, &first.version).cmp(&(&second.name, &second.version))
+    });
+    Ok(result)
+}
@@ -0,0 +1,161 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{git_urls, BundleOptions, Platform, SOURCE_DATE_EPOCH_VARIABLE};
+
+/// Environment variables of the tools started during a build, for example cargo, git and cmake.
+/// They are given to every started process instead of being set in the builder's process
+pub fn tool_environment(options: &BundleOptions) -> Vec<(String, OsString)> {
+    let mut environment = vec![];
+
+    // fail instead of accessing the network
+    if options.offline() {
+        environment.push(("CARGO_NET_OFFLINE".to_string(), OsString::from("true")));
+        environment.push(("GIT_ALLOW_PROTOCOL".to_string(), OsString::from("file")));
+    }
+
+    environment.extend(git_urls::environment(options.git_url_rewrites()));
+
+    if options.reproducible() {
+        environment.extend(reproducible_environment(options));
+    }
+
+    environment
+}
+
+/// Make cargo, rustc and C compilers of the executables and libraries produce the same output
+/// independently of where the workspace is
+fn reproducible_environment(options: &BundleOptions) -> Vec<(String, OsString)> {
+    let mut environment = vec![];
+
+    if let Some(epoch) = options.source_date_epoch() {
+        environment.push((
+            SOURCE_DATE_EPOCH_VARIABLE.to_string(),
+            OsString::from(epoch.to_string()),
+        ));
+    }
+
+    let prefixes = remap_path_prefixes(options);
+
+    let mut rustflags = match std::env::var("CARGO_ENCODED_RUSTFLAGS") {
+        Ok(flags) => flags
+            .split('\x1f')
+            .filter(|flag| !flag.is_empty())
+            .map(|flag| flag.to_string())
+            .collect::<Vec<String>>(),
+        Err(_) => std::env::var("RUSTFLAGS")
+            .map(|flags| {
+                flags
+                    .split_whitespace()
+                    .map(|flag| flag.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    rustflags.extend(prefixes.iter().map(|(path, replacement)| {
+        format!("--remap-path-prefix={}={}", path.display(), replacement)
+    }));
+    environment.push((
+        "CARGO_ENCODED_RUSTFLAGS".to_string(),
+        OsString::from(rustflags.join("\x1f")),
+    ));
+
+    // msvc does not support prefix maps
+    if options.platform() != Platform::Windows {
+        for name in ["CFLAGS", "CXXFLAGS"] {
+            let mut flags = std::env::var(name)
+                .map(|flags| vec![flags])
+                .unwrap_or_default();
+            flags.extend(prefixes.iter().map(|(path, replacement)| {
+                format!("-ffile-prefix-map={}={}", path.display(), replacement)
+            }));
+            environment.push((name.to_string(), OsString::from(flags.join(" "))));
+        }
+    }
+
+    environment
+}
+
+/// Local paths that end up in the compiled binaries, for example in panic messages and debug info,
+/// with their replacements. Later prefixes take precedence as they are nested in the earlier ones
+fn remap_path_prefixes(options: &BundleOptions) -> Vec<(PathBuf, &'static str)> {
+    let mut prefixes = vec![];
+    if let Some(cargo_home) = cargo_home() {
+        prefixes.push((cargo_home, "/cargo"));
+    }
+    if let Some(workspace) = options.workspace_directory() {
+        prefixes.push((workspace, "/workspace"));
+    }
+    prefixes.push((
+        absolute(&options.third_party_libraries_sources_directory()),
+        "/libs",
+    ));
+    prefixes.push((absolute(options.target_dir()), "/target"));
+    prefixes
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".cargo"))
+        })
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|directory| directory.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+/// Held while the environment of the process is changed, so that only one scope changes it at a time
+static ENVIRONMENT_LOCK: Mutex<()> = Mutex::new(());
+
+/// The tool environment set in the builder's process while a library builder runs,
+/// since the library builders start git, cargo and cmake themselves and take no environment.
+/// The previous values are restored when it is dropped.
+///
+/// Libraries are compiled one after another on the main thread once the cargo processes of the executables
+/// have finished, so no other process is started while it is alive. The scope holds a lock, which makes
+/// scopes exclusive and, being a `MutexGuard`, can not be sent to another thread, so it is
+/// restored on the thread that set it. Scopes must not be nested
+pub struct ScopedEnvironment {
+    previous: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl ScopedEnvironment {
+    pub fn set(environment: Vec<(String, OsString)>) -> Self {
+        let lock = ENVIRONMENT_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut previous = vec![];
+        for (name, value) in environment {
+            previous.push((name.clone(), std::env::var_os(&name)));
+            std::env::set_var(&name, value);
+        }
+        Self {
+            previous,
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for ScopedEnvironment {
+    fn drop(&mut self) {
+        // restore in reverse order in case a variable was set more than once
+        for (name, value) in self.previous.drain(..).rev() {
+            match value {
+                Some(value) => std::env::set_var(&name, value),
+                None => std::env::remove_var(&name),
+            }
+        }
+    }
+}
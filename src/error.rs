@@ -1,9 +1,66 @@
 pub type BoxError = std::boxed::Box<dyn std::error::Error>;
 pub type Result<T> = core::result::Result<T, BoxError>;
 
+/// A category of a failure. Every category ends the builder with its own exit code,
+/// so that scripts can tell what went wrong without parsing the output
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// A failure that does not belong to any other category
+    Other,
+    /// The options are invalid or do not make sense together
+    InvalidOptions,
+    /// A tool, SDK or environment variable needed for the build is missing
+    MissingTool,
+    /// The vm-client executables failed to compile
+    Compilation,
+    /// A third party library failed to download or compile
+    Library,
+    /// Compiled artifacts could not be packaged into a bundle
+    Bundling,
+    /// A file or directory could not be read, written or copied
+    Io,
+}
+
+impl ErrorKind {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::InvalidOptions => 2,
+            ErrorKind::MissingTool => 3,
+            ErrorKind::Compilation => 4,
+            ErrorKind::Library => 5,
+            ErrorKind::Bundling => 6,
+            ErrorKind::Io => 7,
+        }
+    }
+
+    /// Find the category of an error looking through the chain of its causes.
+    /// The outermost categorized error wins
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut current = Some(error);
+        let mut io = false;
+        while let Some(error) = current {
+            if let Some(error) = error.downcast_ref::<Error>() {
+                if error.kind != ErrorKind::Other {
+                    return error.kind;
+                }
+            }
+            io |= error.is::<std::io::Error>() || error.is::<fs_extra::error::Error>();
+            current = error.source();
+        }
+        if io {
+            ErrorKind::Io
+        } else {
+            ErrorKind::Other
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Error {
     message: String,
+    kind: ErrorKind,
+    hint: Option<String>,
     source: Option<BoxError>,
 }
 
@@ -11,18 +68,59 @@ impl Error {
     pub fn new(message: impl Into<String>) -> Self {
         Self {
             message: message.into(),
+            kind: ErrorKind::Other,
+            hint: None,
             source: None,
         }
     }
 
+    pub fn invalid_options(message: impl Into<String>) -> Self {
+        Self::new(message).kind(ErrorKind::InvalidOptions)
+    }
+
+    pub fn missing_tool(message: impl Into<String>) -> Self {
+        Self::new(message).kind(ErrorKind::MissingTool)
+    }
+
+    pub fn compilation(message: impl Into<String>) -> Self {
+        Self::new(message).kind(ErrorKind::Compilation)
+    }
+
+    pub fn library(message: impl Into<String>) -> Self {
+        Self::new(message).kind(ErrorKind::Library)
+    }
+
+    pub fn bundling(message: impl Into<String>) -> Self {
+        Self::new(message).kind(ErrorKind::Bundling)
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Self::new(message).kind(ErrorKind::Io)
+    }
+
+    pub fn kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Suggest the user how to fix the problem
+    pub fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn error_kind(&self) -> ErrorKind {
+        self.kind
+    }
+
     pub fn result<T>(self) -> Result<T> {
         Err(Box::new(self))
     }
 
     pub fn from(self, error: impl Into<Box<dyn std::error::Error>>) -> Self {
         Self {
-            message: self.message,
             source: Some(error.into()),
+            ..self
         }
     }
 }
@@ -33,6 +131,9 @@ impl std::fmt::Display for Error {
         if let Some(error) = &self.source {
             write!(f, "\nCaused by: {}", error)?;
         }
+        if let Some(hint) = &self.hint {
+            write!(f, "\nHint: {}", hint)?;
+        }
         Ok(())
     }
 }
@@ -51,5 +152,22 @@ impl<T> From<Error> for Result<T> {
     }
 }
 
+/// Wrap a failure into an [`Error`] that explains what was being done when it happened
+pub trait Context<T> {
+    fn context(self, error: impl FnOnce() -> Error) -> Result<T>;
+}
+
+impl<T, E: Into<BoxError>> Context<T> for core::result::Result<T, E> {
+    fn context(self, error: impl FnOnce() -> Error) -> Result<T> {
+        self.map_err(|source| Box::new(error().from(source)) as BoxError)
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context(self, error: impl FnOnce() -> Error) -> Result<T> {
+        self.ok_or_else(|| Box::new(error()) as BoxError)
+    }
+}
+
 unsafe impl Sync for Error {}
 unsafe impl Send for Error {}
@@ -0,0 +1,380 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use clap::ArgEnum;
+use filedescriptor::{FileDescriptor, StdioDescriptor};
+use serde::{Deserialize, Serialize};
+
+use crate::{BundleOptions, Executable, Result};
+
+static MESSAGE_FORMAT: Mutex<MessageFormat> = Mutex::new(MessageFormat::Human);
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+/// The original standard output, reserved for JSON events
+static EVENTS_OUTPUT: Mutex<Option<FileDescriptor>> = Mutex::new(None);
+
+/// How the progress of a build is reported
+#[derive(ArgEnum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MessageFormat {
+    /// Human-readable messages
+    Human,
+    /// One JSON event per line
+    Json,
+}
+
+/// A phase of a build that takes time and may fail
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    ResolveOptions,
+    ExportBuildInfo,
+    PreCompile,
+    Compile,
+    PostCompile,
+    CompileLibrary,
+    Bundle,
+    BundleResources,
+    Vendor,
+    Licenses,
+    Sbom,
+    Symbols,
+    Integrity,
+    Package,
+}
+
+impl Phase {
+    fn description(&self) -> &str {
+        match self {
+            Phase::ResolveOptions => "Resolving options for",
+            Phase::ExportBuildInfo => "Exporting build info for",
+            Phase::PreCompile => "Preparing",
+            Phase::Compile => "Compiling",
+            Phase::PostCompile => "Post-processing",
+            Phase::CompileLibrary => "Compiling library",
+            Phase::Bundle => "Bundling",
+            Phase::BundleResources => "Bundling resources of",
+            Phase::Vendor => "Vendoring",
+            Phase::Licenses => "Collecting licenses of",
+            Phase::Sbom => "Writing the SBOM of",
+            Phase::Symbols => "Archiving the debug symbols of",
+            Phase::Integrity => "Writing the integrity manifest of",
+            Phase::Package => "Packaging",
+        }
+    }
+}
+
+/// Events reported since the recording started
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub started: Instant,
+    pub events: Vec<Event>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    OptionsResolved {
+        target: String,
+        app_name: String,
+        executables: Vec<Executable>,
+        libraries: Vec<String>,
+        compilation_location: PathBuf,
+        bundle_location: PathBuf,
+    },
+    PhaseStarted {
+        phase: Phase,
+        subject: String,
+    },
+    PhaseFinished {
+        phase: Phase,
+        subject: String,
+        duration_ms: u64,
+    },
+    PhaseFailed {
+        phase: Phase,
+        subject: String,
+        duration_ms: u64,
+        error: String,
+    },
+    /// A compiled executable or library
+    ArtifactProduced {
+        phase: Phase,
+        subject: String,
+        path: PathBuf,
+    },
+    /// A library was already compiled and was not rebuilt
+    LibraryCacheHit {
+        library: String,
+        path: PathBuf,
+    },
+    BuildInfoExported {
+        path: PathBuf,
+        changed: bool,
+    },
+    BuildReportWritten {
+        path: PathBuf,
+    },
+    FileCopied {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// A bundled file did not change since the previous bundling and was not copied again
+    FileUpToDate {
+        path: PathBuf,
+    },
+    /// A file left in the bundle by the previous bundling that is no longer part of it
+    StaleFileRemoved {
+        path: PathBuf,
+    },
+    RpathSet {
+        binary: PathBuf,
+        rpath: String,
+    },
+    /// The debug info was removed from a bundled binary and moved to the debug file, if the symbols are included
+    DebugInfoSplit {
+        binary: PathBuf,
+        debug_file: Option<PathBuf>,
+    },
+    /// An install name of a dynamic library or a reference to it was changed
+    InstallNameChanged {
+        binary: PathBuf,
+        from: Option<String>,
+        to: String,
+    },
+    ArchiveCreated {
+        path: PathBuf,
+        size: u64,
+    },
+    /// Something is likely wrong with the bundle, but it does not fail the build
+    Warning {
+        message: String,
+    },
+    /// The command failed with an error and exits with the code of its category
+    Failed {
+        error: String,
+        exit_code: i32,
+    },
+}
+
+impl Event {
+    pub fn options_resolved(options: &BundleOptions) -> Self {
+        Self::OptionsResolved {
+            target: options.target().to_string(),
+            app_name: options.app_name().to_string(),
+            executables: options.executables().clone(),
+            libraries: options
+                .libraries()
+                .iter()
+                .map(|library| library.name().to_string())
+                .collect(),
+            compilation_location: options.compilation_location(),
+            bundle_location: options.bundle_location(),
+        }
+    }
+
+    pub fn file_copied(from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        Self::FileCopied {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    pub fn archive_created(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let size = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Self::ArchiveCreated { path, size }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::OptionsResolved {
+                target,
+                app_name,
+                bundle_location,
+                ..
+            } => write!(
+                f,
+                "Building {} for {} into {}",
+                app_name,
+                target,
+                bundle_location.display()
+            ),
+            Event::PhaseStarted { phase, subject } => {
+                write!(f, "{} {}...", phase.description(), subject)
+            }
+            Event::PhaseFinished {
+                phase,
+                subject,
+                duration_ms,
+            } => write!(
+                f,
+                "{} {} finished in {}",
+                phase.description(),
+                subject,
+                seconds(*duration_ms)
+            ),
+            Event::PhaseFailed {
+                phase,
+                subject,
+                duration_ms,
+                error,
+            } => write!(
+                f,
+                "{} {} failed after {}: {}",
+                phase.description(),
+                subject,
+                seconds(*duration_ms),
+                error
+            ),
+            Event::ArtifactProduced { path, .. } => write!(f, "   Produced {}", path.display()),
+            Event::LibraryCacheHit { library, path } => {
+                write!(f, "   {} is up to date at {}", library, path.display())
+            }
+            Event::BuildInfoExported { path, changed } => {
+                if *changed {
+                    write!(f, "   Exported build info to {}", path.display())
+                } else {
+                    write!(f, "   Build info at {} is up to date", path.display())
+                }
+            }
+            Event::BuildReportWritten { path } => {
+                write!(f, "Written the build report to {}", path.display())
+            }
+            Event::FileCopied { from, to } => {
+                write!(f, "   Copied {} to {}", from.display(), to.display())
+            }
+            Event::FileUpToDate { path } => write!(f, "   {} is up to date", path.display()),
+            Event::StaleFileRemoved { path } => {
+                write!(f, "   Removed stale {}", path.display())
+            }
+            Event::RpathSet { binary, rpath } => {
+                write!(f, "   Set rpath of {} to {}", binary.display(), rpath)
+            }
+            Event::DebugInfoSplit { binary, debug_file } => match debug_file {
+                None => write!(f, "   Stripped the debug info of {}", binary.display()),
+                Some(debug_file) => write!(
+                    f,
+                    "   Moved the debug info of {} to {}",
+                    binary.display(),
+                    debug_file.display()
+                ),
+            },
+            Event::InstallNameChanged { binary, from, to } => match from {
+                None => write!(f, "   Changed id of {} to {}", binary.display(), to),
+                Some(from) => write!(
+                    f,
+                    "   Changed dependency of {} from {} to {}",
+                    binary.display(),
+                    from,
+                    to
+                ),
+            },
+            Event::ArchiveCreated { path, size } => {
+                write!(f, "Created {} ({} bytes)", path.display(), size)
+            }
+            Event::Warning { message } => write!(f, "   Warning: {}", message),
+            Event::Failed { error, .. } => write!(f, "Error: {}", error),
+        }
+    }
+}
+
+fn seconds(duration_ms: u64) -> String {
+    format!("{:.2}s", duration_ms as f64 / 1000.0)
+}
+
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+pub fn set_message_format(format: MessageFormat) {
+    *MESSAGE_FORMAT.lock().unwrap() = format;
+    if format == MessageFormat::Json {
+        reserve_stdout_for_events();
+    }
+}
+
+/// Keep the standard output for JSON events only. Everything else written to it, including
+/// the output of cargo, cmake and other tools spawned while building, goes to the standard error
+fn reserve_stdout_for_events() {
+    let mut events_output = EVENTS_OUTPUT.lock().unwrap();
+    if events_output.is_none() {
+        let _ = std::io::stdout().flush();
+        *events_output =
+            FileDescriptor::redirect_stdio(&std::io::stderr(), StdioDescriptor::Stdout).ok();
+    }
+}
+
+pub fn message_format() -> MessageFormat {
+    *MESSAGE_FORMAT.lock().unwrap()
+}
+
+/// Start collecting all reported events, for example to write a build report
+pub fn start_recording() {
+    *RECORDING.lock().unwrap() = Some(Recording {
+        started: Instant::now(),
+        events: vec![],
+    });
+}
+
+/// Stop collecting events and return the ones collected so far
+pub fn stop_recording() -> Option<Recording> {
+    RECORDING.lock().unwrap().take()
+}
+
+/// Report an event either as a human-readable line or as a line of JSON
+pub fn emit(event: Event) {
+    if let Some(recording) = RECORDING.lock().unwrap().as_mut() {
+        recording.events.push(event.clone());
+    }
+
+    match message_format() {
+        MessageFormat::Human => println!("{}", &event),
+        MessageFormat::Json => {
+            if let Ok(json) = serde_json::to_string(&event) {
+                match EVENTS_OUTPUT.lock().unwrap().as_mut() {
+                    Some(events_output) => {
+                        let _ = writeln!(events_output, "{}", json);
+                    }
+                    None => println!("{}", json),
+                }
+            }
+        }
+    }
+}
+
+/// Run a phase of the build reporting when it starts, how long it took and
+/// why it failed
+pub fn phase<T>(
+    phase: Phase,
+    subject: impl Into<String>,
+    action: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let subject = subject.into();
+    emit(Event::PhaseStarted {
+        phase,
+        subject: subject.clone(),
+    });
+
+    let started = Instant::now();
+    let result = action();
+    let duration_ms = millis(started.elapsed());
+
+    match &result {
+        Ok(_) => emit(Event::PhaseFinished {
+            phase,
+            subject,
+            duration_ms,
+        }),
+        Err(error) => emit(Event::PhaseFailed {
+            phase,
+            subject,
+            duration_ms,
+            error: error.to_string(),
+        }),
+    }
+    result
+}
@@ -0,0 +1,230 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Context, Error, Result};
+
+/// An environment variable with rewrite rules in `url=prefix` format separated by `;`
+pub const GIT_URL_REWRITES_VARIABLE: &str = "VM_BUILDER_GIT_INSTEAD_OF";
+
+/// Fetch from `url` instead of any repository url that starts with `instead_of`,
+/// like `url.<url>.insteadOf` in the git config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UrlRewrite {
+    pub url: String,
+    pub instead_of: String,
+}
+
+impl UrlRewrite {
+    pub fn new(url: impl Into<String>, instead_of: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            instead_of: instead_of.into(),
+        }
+    }
+}
+
+/// Read the rewrite rules given in the environment
+pub fn rewrites_from_environment() -> Result<Vec<UrlRewrite>> {
+    let rules = match std::env::var(GIT_URL_REWRITES_VARIABLE) {
+        Ok(rules) => rules,
+        Err(_) => return Ok(vec![]),
+    };
+
+    rules
+        .split(';')
+        .map(|rule| rule.trim())
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let (url, instead_of) = rule.split_once('=').context(|| {
+                Error::invalid_options(format!(
+                    "Invalid git url rewrite `{}` in {}",
+                    rule, GIT_URL_REWRITES_VARIABLE
+                ))
+                .hint("Use the url=prefix format, for example https://git.example.com/=https://github.com/")
+            })?;
+            Ok(UrlRewrite::new(url, instead_of))
+        })
+        .collect()
+}
+
+/// A rule for every bare repository `<owner>/<repo>.git` in the mirror directory
+/// to be used instead of the same repository on GitHub
+pub fn mirror_rewrites(mirror_directory: &Path) -> Result<Vec<UrlRewrite>> {
+    let mirror_directory = if mirror_directory.is_absolute() {
+        mirror_directory.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(mirror_directory)
+    };
+
+    let owners = mirror_directory.read_dir().context(|| {
+        Error::invalid_options(format!(
+            "Mirror directory {} does not exist",
+            mirror_directory.display()
+        ))
+    })?;
+
+    let mut rewrites = vec![];
+    for owner in owners {
+        let owner = owner?.path();
+        if !owner.is_dir() {
+            continue;
+        }
+        for repository in owner.read_dir()? {
+            let repository = repository?.path();
+            let is_bare_repository = repository.is_dir()
+                && repository
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    == Some("git");
+            if !is_bare_repository {
+                continue;
+            }
+            if let (Some(owner_name), Some(repository_name)) = (
+                owner.file_name().and_then(|name| name.to_str()),
+                repository.file_name().and_then(|name| name.to_str()),
+            ) {
+                // git matches the rules as plain prefixes, so every rule ends where the
+                // repository name ends, otherwise a rule for `libwinit` would also take `libwinit30`
+                let url = repository.display().to_string();
+                let github_url = format!(
+                    "https://github.com/{}/{}",
+                    owner_name,
+                    repository_name.trim_end_matches(".git")
+                );
+                rewrites.push(UrlRewrite::new(&url, format!("{}.git", &github_url)));
+                rewrites.push(UrlRewrite::new(
+                    format!("{}/", &url),
+                    format!("{}/", &github_url),
+                ));
+            }
+        }
+    }
+    rewrites.sort_by(|first, second| first.instead_of.cmp(&second.instead_of));
+    Ok(rewrites)
+}
+
+/// Environment variables that pass the rewrite rules to a git process,
+/// in addition to the configuration already given in the builder's environment
+pub fn environment(rewrites: &[UrlRewrite]) -> Vec<(String, OsString)> {
+    if rewrites.is_empty() {
+        return vec![];
+    }
+
+    let mut count = std::env::var("GIT_CONFIG_COUNT")
+        .ok()
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut environment = vec![];
+    for rewrite in rewrites {
+        environment.push((
+            format!("GIT_CONFIG_KEY_{}", count),
+            OsString::from(format!("url.{}.insteadOf", &rewrite.url)),
+        ));
+        environment.push((
+            format!("GIT_CONFIG_VALUE_{}", count),
+            OsString::from(&rewrite.instead_of),
+        ));
+        count += 1;
+    }
+    environment.push((
+        "GIT_CONFIG_COUNT".to_string(),
+        OsString::from(count.to_string()),
+    ));
+    environment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Rewrite a url like git does, with the rule of the longest matching prefix
+    fn rewrite(url: &str, rewrites: &[UrlRewrite]) -> String {
+        rewrites
+            .iter()
+            .filter(|rewrite| url.starts_with(&rewrite.instead_of))
+            .max_by_key(|rewrite| rewrite.instead_of.len())
+            .map_or(url.to_string(), |rewrite| {
+                format!("{}{}", rewrite.url, &url[rewrite.instead_of.len()..])
+            })
+    }
+
+    fn mirror_directory() -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("gtoolkit-vm-builder-mirror-{}", std::process::id()));
+        for repository in ["feenkcom/libwinit.git", "feenkcom/libwinit30.git"] {
+            std::fs::create_dir_all(directory.join(repository)).unwrap();
+        }
+        std::fs::create_dir_all(directory.join("feenkcom/not-bare")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn mirror_rewrites_of_bare_repositories() {
+        let mirror = mirror_directory();
+        let rewrites = mirror_rewrites(&mirror).unwrap();
+        let libwinit = mirror.join("feenkcom/libwinit.git").display().to_string();
+        let libwinit30 = mirror.join("feenkcom/libwinit30.git").display().to_string();
+
+        assert_eq!(rewrites.len(), 4);
+        assert_eq!(
+            rewrite("https://github.com/feenkcom/libwinit.git", &rewrites),
+            libwinit
+        );
+        assert_eq!(
+            rewrite("https://github.com/feenkcom/libwinit/", &rewrites),
+            format!("{}/", libwinit)
+        );
+        assert_eq!(
+            rewrite("https://github.com/feenkcom/libwinit30.git", &rewrites),
+            libwinit30
+        );
+        assert_eq!(
+            rewrite("https://github.com/feenkcom/not-bare.git", &rewrites),
+            "https://github.com/feenkcom/not-bare.git"
+        );
+    }
+
+    #[test]
+    fn mirror_rewrites_of_missing_directory() {
+        let missing = std::env::temp_dir().join("gtoolkit-vm-builder-missing-mirror");
+        assert!(mirror_rewrites(&missing).is_err());
+    }
+
+    #[test]
+    fn environment_of_rewrites() {
+        let count = std::env::var("GIT_CONFIG_COUNT")
+            .ok()
+            .and_then(|count| count.parse::<usize>().ok())
+            .unwrap_or(0);
+        let environment = environment(&[UrlRewrite::new(
+            "https://git.example.com/",
+            "https://github.com/",
+        )]);
+        assert_eq!(
+            environment,
+            vec![
+                (
+                    format!("GIT_CONFIG_KEY_{}", count),
+                    OsString::from("url.https://git.example.com/.insteadOf")
+                ),
+                (
+                    format!("GIT_CONFIG_VALUE_{}", count),
+                    OsString::from("https://github.com/")
+                ),
+                (
+                    "GIT_CONFIG_COUNT".to_string(),
+                    OsString::from((count + 1).to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn environment_without_rewrites() {
+        assert!(environment(&[]).is_empty());
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bundlers::{Bundler, INTEGRITY_MANIFEST_FILE};
+use crate::checksums::{
+    files_in_directory, link_target, relative_path, sha256_of_entry, sha256_of_file,
+};
+use crate::events::{Event, Phase};
+use crate::{events, reproducible, BundleOptions, Context, Error, Result};
+
+/// The first line of a manifest, the number is bumped whenever the format changes
+const MANIFEST_HEADER: &str = "# gtoolkit-vm integrity manifest 1";
+
+/// A header line with the path of the manifest itself relative to the app directory,
+/// which tells where the app directory is when only the manifest is known
+const MANIFEST_PATH_PREFIX: &str = "# manifest ";
+
+/// The unix file type bits of a symbolic link, added to the mode of links
+const SYMLINK_MODE: u32 = 0o120000;
+const FILE_TYPE_MASK: u32 = 0o170000;
+
+/// A bundled file as listed in the manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    /// A path relative to the app directory with `/` as a separator
+    pub path: String,
+    pub size: u64,
+    /// A lowercase hex encoded SHA-256 of the content, or of the target path of a symbolic link
+    pub sha256: String,
+    /// Unix permission bits, with the file type bits of a symbolic link for links
+    pub mode: u32,
+}
+
+/// The result of checking a bundle against its manifest
+#[derive(Debug, Clone)]
+pub struct IntegrityReport {
+    pub app_directory: PathBuf,
+    pub manifest: PathBuf,
+    pub checked: usize,
+    pub missing: Vec<String>,
+    /// Paths of the changed files with what changed about them
+    pub modified: Vec<(String, String)>,
+    pub unexpected: Vec<String>,
+}
+
+impl ManifestEntry {
+    pub fn is_link(&self) -> bool {
+        self.mode & FILE_TYPE_MASK == SYMLINK_MODE
+    }
+}
+
+impl IntegrityReport {
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Write the manifest of every file in the app directory into its resources directory.
+/// On Android it lists the asset tree and is packaged with it
+pub fn write_manifest(bundler: &dyn Bundler, options: &BundleOptions) -> Result<()> {
+    let app_directory = bundler.bundled_app_directory(options);
+    if !app_directory.is_dir() {
+        return Error::bundling(format!(
+            "Could not write the integrity manifest, {} does not exist",
+            app_directory.display()
+        ))
+        .into();
+    }
+
+    let manifest = bundler
+        .bundled_resources_directory(options)
+        .join(INTEGRITY_MANIFEST_FILE);
+    if manifest.exists() {
+        fs::remove_file(&manifest)
+            .context(|| Error::io(format!("Could not remove {}", manifest.display())))?;
+    }
+    let content = manifest_content(&app_directory, &manifest)?;
+    fs::write(&manifest, content)
+        .context(|| Error::io(format!("Could not write {}", manifest.display())))?;
+    if options.reproducible() {
+        reproducible::normalize_path(options, &manifest)?;
+    }
+    events::emit(Event::ArtifactProduced {
+        phase: Phase::Integrity,
+        subject: "integrity manifest".to_string(),
+        path: manifest,
+    });
+    Ok(())
+}
+
+/// The manifest of every file in the app directory: a header, the path of the manifest
+/// and a `<sha256> <size> <mode> <path>` line per file sorted by path
+fn manifest_content(app_directory: &Path, manifest: &Path) -> Result<String> {
+    let entries = manifest_entries(app_directory, manifest)?;
+
+    let mut content = String::new();
+    writeln!(content, "{}", MANIFEST_HEADER).unwrap();
+    writeln!(
+        content,
+        "{}{}",
+        MANIFEST_PATH_PREFIX,
+        relative_path(app_directory, manifest)
+    )
+    .unwrap();
+    for entry in entries.values() {
+        writeln!(
+            content,
+            "{} {} {:04o} {}",
+            entry.sha256, entry.size, entry.mode, entry.path
+        )
+        .unwrap();
+    }
+    Ok(content)
+}
+
+/// Check the files of a bundle against its manifest. The bundle may be the app directory
+/// or any directory that contains it, the manifest closest to its root is used
+pub fn verify(bundle: &Path) -> Result<IntegrityReport> {
+    if !bundle.is_dir() {
+        return Error::invalid_options(format!("{} is not a directory", bundle.display()))
+            .hint("Pass the directory of an unpacked bundle")
+            .into();
+    }
+
+    let manifest = files_in_directory(bundle)?
+        .into_iter()
+        .filter(|file| {
+            file.file_name()
+                .map_or(false, |name| name == INTEGRITY_MANIFEST_FILE)
+        })
+        .min_by_key(|file| file.components().count())
+        .context(|| {
+            Error::invalid_options(format!(
+                "Could not find {} in {}",
+                INTEGRITY_MANIFEST_FILE,
+                bundle.display()
+            ))
+            .hint(
+                "Only bundles built by a recent gtoolkit-vm-builder contain the integrity manifest",
+            )
+        })?;
+
+    let (manifest_path, expected) = read_manifest(&manifest)?;
+    let app_directory = manifest
+        .ancestors()
+        .nth(manifest_path.split('/').count())
+        .map(|directory| directory.to_path_buf())
+        .context(|| {
+            Error::bundling(format!(
+                "The manifest {} is not at {}",
+                manifest.display(),
+                &manifest_path
+            ))
+        })?;
+    let actual = manifest_entries(&app_directory, &manifest)?;
+
+    let mut report = IntegrityReport {
+        app_directory,
+        manifest,
+        checked: expected.len(),
+        missing: vec![],
+        modified: vec![],
+        unexpected: vec![],
+    };
+
+    for (path, expected_entry) in &expected {
+        match actual.get(path) {
+            None => report.missing.push(path.clone()),
+            Some(actual_entry) => {
+                if let Some(change) = change_of(expected_entry, actual_entry) {
+                    report.modified.push((path.clone(), change));
+                }
+            }
+        }
+    }
+    report.unexpected = actual
+        .keys()
+        .filter(|path| !expected.contains_key(*path))
+        .cloned()
+        .collect();
+
+    Ok(report)
+}
+
+/// Describe how a file differs from its manifest entry.
+/// Permissions are only compared on unix as windows does not have them
+fn change_of(expected: &ManifestEntry, actual: &ManifestEntry) -> Option<String> {
+    match (expected.is_link(), actual.is_link()) {
+        (false, true) => return Some("is a symbolic link instead of a file".to_string()),
+        (true, false) => return Some("is a file instead of a symbolic link".to_string()),
+        _ => {}
+    }
+    if expected.size != actual.size {
+        return Some(format!(
+            "size is {} bytes instead of {}",
+            actual.size, expected.size
+        ));
+    }
+    if expected.sha256 != actual.sha256 {
+        return Some("content differs".to_string());
+    }
+    if cfg!(unix) && expected.mode != actual.mode {
+        return Some(format!(
+            "mode is {:04o} instead of {:04o}",
+            actual.mode, expected.mode
+        ));
+    }
+    None
+}
+
+/// Parse a manifest into the path of the manifest relative to the app directory and its entries by path
+pub fn read_manifest(manifest: &Path) -> Result<(String, BTreeMap<String, ManifestEntry>)> {
+    let content = fs::read_to_string(manifest)
+        .context(|| Error::io(format!("Could not read {}", manifest.display())))?;
+    let invalid = |line: &str| {
+        Error::bundling(format!(
+            "{} is not a valid integrity manifest, could not parse: {}",
+            manifest.display(),
+            line
+        ))
+    };
+
+    let mut lines = content.lines();
+    let header = lines.next().unwrap_or_default();
+    if header != MANIFEST_HEADER {
+        return invalid(header)
+            .hint("The manifest was written by a different version of gtoolkit-vm-builder")
+            .into();
+    }
+
+    let mut manifest_path = None;
+    let mut entries = BTreeMap::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        if let Some(path) = line.strip_prefix(MANIFEST_PATH_PREFIX) {
+            manifest_path = Some(path.to_string());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.splitn(4, ' ');
+        let entry = (|| {
+            let sha256 = fields.next()?.to_string();
+            let size = fields.next()?.parse().ok()?;
+            let mode = u32::from_str_radix(fields.next()?, 8).ok()?;
+            let path = fields.next()?.to_string();
+            Some(ManifestEntry {
+                path,
+                size,
+                sha256,
+                mode,
+            })
+        })()
+        .context(|| invalid(line))?;
+        entries.insert(entry.path.clone(), entry);
+    }
+
+    let manifest_path = manifest_path.context(|| invalid("missing the manifest path"))?;
+    Ok((manifest_path, entries))
+}
+
+/// Entries of all files in the app directory except for the manifest itself, by path
+fn manifest_entries(
+    app_directory: &Path,
+    manifest: &Path,
+) -> Result<BTreeMap<String, ManifestEntry>> {
+    files_in_directory(app_directory)?
+        .into_iter()
+        .filter(|file| file != manifest)
+        .map(|file| {
+            let entry = manifest_entry(app_directory, &file)?;
+            Ok((entry.path.clone(), entry))
+        })
+        .collect()
+}
+
+/// Symbolic links are recorded as links with the size and SHA-256 of their target path,
+/// what they point to is checked by its own entry
+fn manifest_entry(app_directory: &Path, file: &Path) -> Result<ManifestEntry> {
+    if let Some(target) = link_target(file)? {
+        return Ok(ManifestEntry {
+            path: relative_path(app_directory, file),
+            size: target.len() as u64,
+            sha256: sha256_of_entry(file)?,
+            mode: SYMLINK_MODE | 0o777,
+        });
+    }
+    let metadata = fs::metadata(file)
+        .context(|| Error::io(format!("Could not read the metadata of {}", file.display())))?;
+    Ok(ManifestEntry {
+        path: relative_path(app_directory, file),
+        size: metadata.len(),
+        sha256: sha256_of_file(file)?,
+        mode: mode_of(&metadata),
+    })
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+/// Windows only knows if a file is read-only, which is mapped onto the closest unix permissions
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SHA-256 of `content`
+    const CONTENT_SHA256: &str = "ed7002b439e9ac845f22357d822bac1444730fbdb6016d3ec9432297b9ec9f73";
+
+    /// An app directory with a file and a nested file, and the path of its manifest
+    fn app_directory(name: &str) -> (PathBuf, PathBuf) {
+        let app_directory = std::env::temp_dir()
+            .join(format!(
+                "gtoolkit-vm-builder-integrity-{}",
+                std::process::id()
+            ))
+            .join(name);
+        if app_directory.exists() {
+            fs::remove_dir_all(&app_directory).unwrap();
+        }
+        fs::create_dir_all(app_directory.join("share")).unwrap();
+        fs::write(app_directory.join("vm"), "content").unwrap();
+        fs::write(app_directory.join("share/build-info.json"), "{}").unwrap();
+        let manifest = app_directory.join("share").join(INTEGRITY_MANIFEST_FILE);
+        (app_directory, manifest)
+    }
+
+    fn write_manifest_of(app_directory: &Path, manifest: &Path) {
+        fs::write(manifest, manifest_content(app_directory, manifest).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn manifest_format() {
+        let (app_directory, manifest) = app_directory("format");
+        let content = manifest_content(&app_directory, &manifest).unwrap();
+        let lines = content.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], MANIFEST_HEADER);
+        assert_eq!(
+            lines[1],
+            format!("{}share/{}", MANIFEST_PATH_PREFIX, INTEGRITY_MANIFEST_FILE)
+        );
+        assert!(lines[2]
+            .starts_with("44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a 2 "));
+        assert!(lines[2].ends_with(" share/build-info.json"));
+        assert!(lines[3].starts_with(&format!("{} 7 ", CONTENT_SHA256)));
+        assert!(lines[3].ends_with(" vm"));
+    }
+
+    #[test]
+    fn read_written_manifest() {
+        let (app_directory, manifest) = app_directory("read");
+        write_manifest_of(&app_directory, &manifest);
+
+        let (manifest_path, entries) = read_manifest(&manifest).unwrap();
+        assert_eq!(manifest_path, format!("share/{}", INTEGRITY_MANIFEST_FILE));
+        assert_eq!(
+            entries.keys().cloned().collect::<Vec<String>>(),
+            vec!["share/build-info.json".to_string(), "vm".to_string()]
+        );
+        let vm = &entries["vm"];
+        assert_eq!(vm.size, 7);
+        assert_eq!(vm.sha256, CONTENT_SHA256);
+        assert!(!vm.is_link());
+    }
+
+    #[test]
+    fn read_manifest_with_other_header() {
+        let (_, manifest) = app_directory("other-header");
+        fs::write(&manifest, "# gtoolkit-vm integrity manifest 0\n").unwrap();
+        assert!(read_manifest(&manifest).is_err());
+    }
+
+    #[test]
+    fn read_manifest_with_invalid_entry() {
+        let (_, manifest) = app_directory("invalid-entry");
+        fs::write(
+            &manifest,
+            format!(
+                "{}\n{}share/{}\n{} seven 0644 vm\n",
+                MANIFEST_HEADER, MANIFEST_PATH_PREFIX, INTEGRITY_MANIFEST_FILE, CONTENT_SHA256
+            ),
+        )
+        .unwrap();
+        assert!(read_manifest(&manifest).is_err());
+    }
+
+    #[test]
+    fn verify_intact_bundle() {
+        let (app_directory, manifest) = app_directory("intact");
+        write_manifest_of(&app_directory, &manifest);
+
+        let report = verify(&app_directory).unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.app_directory, app_directory);
+    }
+
+    #[test]
+    fn verify_changed_bundle() {
+        let (app_directory, manifest) = app_directory("changed");
+        write_manifest_of(&app_directory, &manifest);
+        fs::write(app_directory.join("vm"), "CONTENT").unwrap();
+        fs::remove_file(app_directory.join("share/build-info.json")).unwrap();
+        fs::write(app_directory.join("extra"), "").unwrap();
+
+        let report = verify(&app_directory).unwrap();
+        assert!(!report.is_intact());
+        assert_eq!(
+            report.modified,
+            vec![("vm".to_string(), "content differs".to_string())]
+        );
+        assert_eq!(report.missing, vec!["share/build-info.json".to_string()]);
+        assert_eq!(report.unexpected, vec!["extra".to_string()]);
+    }
+}
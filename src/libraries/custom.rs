@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use shared_library_builder::{
+    CMakeLibrary, CompiledLibraryName, GitLocation, Library, LibraryLocation, PathLocation,
+    RustLibrary,
+};
+
+use crate::libraries::PinnedVersion;
+
+/// A third party library declared in the versions file rather than built into the builder
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CustomLibrary {
+    /// A name of the library, also used for its sources and build directories
+    pub name: String,
+    pub location: CustomLibraryLocation,
+    pub kind: CustomLibraryKind,
+    /// A name of the compiled library, for example `mylib` for `libmylib.so` or `mylib.dll`,
+    /// at most one as a library is bundled as a single file
+    #[serde(default)]
+    pub exported: Vec<String>,
+    /// The commit the sources must be checked out at, like for a pinned built-in library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// A SHA-256 of the fetched sources as computed by `checksums::sources_sha256`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<String>,
+    /// SHA-256 checksums of the compiled library per target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sha256: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CustomLibraryLocation {
+    /// A GitHub repository checked out at a tag, or at the latest release when no tag is given
+    Git {
+        owner: String,
+        repository: String,
+        #[serde(default)]
+        tag: Option<String>,
+    },
+    /// A directory on the local disk
+    Path(PathBuf),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CustomLibraryKind {
+    /// A Rust crate compiled with cargo
+    Rust { package: String },
+    /// A project compiled with cmake
+    Cmake,
+}
+
+impl CustomLibraryLocation {
+    fn as_location(&self) -> LibraryLocation {
+        match self {
+            CustomLibraryLocation::Git {
+                owner,
+                repository,
+                tag,
+            } => LibraryLocation::Git(
+                GitLocation::github(owner, repository).tag_or_latest(tag.as_deref()),
+            ),
+            CustomLibraryLocation::Path(path) => {
+                LibraryLocation::Path(PathLocation::new(path.clone()))
+            }
+        }
+    }
+}
+
+impl CustomLibrary {
+    /// The same library compiled from a local checkout instead of its declared location
+    pub fn with_path(&self, path: &Path) -> Self {
+        Self {
+            location: CustomLibraryLocation::Path(path.to_path_buf()),
+            ..self.clone()
+        }
+    }
+
+    /// The commit and checksums the library is pinned to, if any
+    pub fn pinned(&self) -> Option<PinnedVersion> {
+        if self.commit.is_none() && self.sources.is_none() && self.sha256.is_empty() {
+            return None;
+        }
+        let version = match &self.location {
+            CustomLibraryLocation::Git { tag, .. } => tag.clone().unwrap_or_default(),
+            CustomLibraryLocation::Path(_) => String::new(),
+        };
+        Some(PinnedVersion {
+            version,
+            commit: self.commit.clone(),
+            sources: self.sources.clone(),
+            sha256: self.sha256.clone(),
+        })
+    }
+
+    pub fn as_library(&self) -> Box<dyn Library> {
+        let location = self.location.as_location();
+        match &self.kind {
+            CustomLibraryKind::Rust { package } => RustLibrary::new(&self.name, location)
+                .package(package)
+                .into(),
+            CustomLibraryKind::Cmake => {
+                let compiled_name = self
+                    .exported
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| self.name.clone());
+                CMakeLibrary::new(&self.name, location)
+                    .compiled_name(CompiledLibraryName::Matching(compiled_name))
+                    .into()
+            }
+        }
+    }
+}
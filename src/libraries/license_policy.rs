@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Target;
+
+/// Licenses that may or may not be shipped in a bundle, declared in the versions file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicensePolicy {
+    /// Targets the policy applies to, all targets when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<Target>,
+    /// SPDX license identifiers that may be shipped. When empty, every license that is not denied is allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// SPDX license identifiers that must not be shipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Components shipped regardless of their license
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exceptions: Vec<LicenseException>,
+}
+
+/// A library or crate excluded from the license policy, with the reason why
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicenseException {
+    pub component: String,
+    pub justification: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum LicenseExpression {
+    License(String),
+    And(Vec<LicenseExpression>),
+    Or(Vec<LicenseExpression>),
+}
+
+impl LicensePolicy {
+    pub fn applies_to(&self, target: &Target) -> bool {
+        self.targets.is_empty() || self.targets.contains(target)
+    }
+
+    pub fn exception_for(&self, component: &str) -> Option<&LicenseException> {
+        self.exceptions
+            .iter()
+            .find(|exception| exception.component == component)
+    }
+
+    /// Check a license expression against the policy and return why it is not allowed.
+    /// An expression with alternatives (`OR`) is allowed if any of them is,
+    /// a combination (`AND`) only if all of them are. An unknown license violates any policy
+    /// that allows or denies licenses, unless an exception covers the component
+    pub fn violation(&self, license: Option<&str>) -> Option<String> {
+        let license = match license {
+            None => {
+                return if self.allow.is_empty() && self.deny.is_empty() {
+                    None
+                } else {
+                    Some("the license is unknown".to_string())
+                }
+            }
+            Some(license) => license,
+        };
+
+        if self.is_satisfied_by(&LicenseExpression::parse(license)) {
+            None
+        } else if self.allow.is_empty() {
+            Some(format!("{} is denied", license))
+        } else {
+            Some(format!("{} is not allowed", license))
+        }
+    }
+
+    fn is_satisfied_by(&self, expression: &LicenseExpression) -> bool {
+        match expression {
+            LicenseExpression::License(license) => self.is_allowed(license),
+            LicenseExpression::And(expressions) => expressions
+                .iter()
+                .all(|expression| self.is_satisfied_by(expression)),
+            LicenseExpression::Or(expressions) => expressions
+                .iter()
+                .any(|expression| self.is_satisfied_by(expression)),
+        }
+    }
+
+    fn is_allowed(&self, license: &str) -> bool {
+        let is_denied = self
+            .deny
+            .iter()
+            .any(|denied| license_matches(license, denied));
+        let is_allowed = self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|allowed| license_matches(license, allowed));
+        is_allowed && !is_denied
+    }
+}
+
+/// A license with an exception, for example `GPL-2.0 WITH GCC-exception-2.0`, matches both the full
+/// identifier and the license alone. `-only`, `-or-later` and `+` variants match the plain identifier
+fn license_matches(license: &str, policy_license: &str) -> bool {
+    let license = normalize_license(license);
+    let policy_license = normalize_license(policy_license);
+    license == policy_license
+        || license
+            .split_once(" with ")
+            .map_or(false, |(license, _)| license == policy_license)
+}
+
+fn normalize_license(license: &str) -> String {
+    let license = license
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+    let (license, exception) = match license.split_once(" with ") {
+        Some((license, exception)) => (license.to_string(), Some(exception.to_string())),
+        None => (license, None),
+    };
+    let license = license
+        .trim_end_matches('+')
+        .trim_end_matches("-or-later")
+        .trim_end_matches("-only")
+        .to_string();
+    match exception {
+        Some(exception) => format!("{} with {}", license, exception),
+        None => license,
+    }
+}
+
+impl LicenseExpression {
+    /// Parse an SPDX expression. Anything that can not be parsed is treated as a single license
+    fn parse(expression: &str) -> Self {
+        let tokens = expression
+            .replace('(', " ( ")
+            .replace(')', " ) ")
+            .split_whitespace()
+            .map(|token| token.to_string())
+            .collect::<Vec<String>>();
+        let mut position = 0;
+        match Self::parse_or(&tokens, &mut position) {
+            Some(parsed) if position == tokens.len() => parsed,
+            _ => Self::License(expression.to_string()),
+        }
+    }
+
+    fn parse_or(tokens: &[String], position: &mut usize) -> Option<Self> {
+        let mut alternatives = vec![Self::parse_and(tokens, position)?];
+        while Self::accept(tokens, position, "or") {
+            alternatives.push(Self::parse_and(tokens, position)?);
+        }
+        Some(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            Self::Or(alternatives)
+        })
+    }
+
+    fn parse_and(tokens: &[String], position: &mut usize) -> Option<Self> {
+        let mut licenses = vec![Self::parse_license(tokens, position)?];
+        while Self::accept(tokens, position, "and") {
+            licenses.push(Self::parse_license(tokens, position)?);
+        }
+        Some(if licenses.len() == 1 {
+            licenses.remove(0)
+        } else {
+            Self::And(licenses)
+        })
+    }
+
+    fn parse_license(tokens: &[String], position: &mut usize) -> Option<Self> {
+        if Self::accept(tokens, position, "(") {
+            let expression = Self::parse_or(tokens, position)?;
+            return if Self::accept(tokens, position, ")") {
+                Some(expression)
+            } else {
+                None
+            };
+        }
+
+        let license = tokens.get(*position)?.clone();
+        if ["and", "or", "with", ")"].contains(&license.to_lowercase().as_str()) {
+            return None;
+        }
+        *position += 1;
+        if Self::accept(tokens, position, "with") {
+            let exception = tokens.get(*position)?.clone();
+            *position += 1;
+            return Some(Self::License(format!("{} WITH {}", license, exception)));
+        }
+        Some(Self::License(license))
+    }
+
+    fn accept(tokens: &[String], position: &mut usize, token: &str) -> bool {
+        if tokens
+            .get(*position)
+            .map_or(false, |each| each.eq_ignore_ascii_case(token))
+        {
+            *position += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn license(name: &str) -> LicenseExpression {
+        LicenseExpression::License(name.to_string())
+    }
+
+    fn policy(allow: &[&str], deny: &[&str]) -> LicensePolicy {
+        LicensePolicy {
+            targets: vec![],
+            allow: allow.iter().map(|each| each.to_string()).collect(),
+            deny: deny.iter().map(|each| each.to_string()).collect(),
+            exceptions: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_single_license() {
+        assert_eq!(LicenseExpression::parse("MIT"), license("MIT"));
+    }
+
+    #[test]
+    fn parse_and_binds_tighter_than_or() {
+        assert_eq!(
+            LicenseExpression::parse("MIT OR Apache-2.0 AND BSD-3-Clause"),
+            LicenseExpression::Or(vec![
+                license("MIT"),
+                LicenseExpression::And(vec![license("Apache-2.0"), license("BSD-3-Clause")]),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_parentheses_and_exceptions() {
+        assert_eq!(
+            LicenseExpression::parse("(MIT or Apache-2.0) and GPL-2.0 WITH GCC-exception-2.0"),
+            LicenseExpression::And(vec![
+                LicenseExpression::Or(vec![license("MIT"), license("Apache-2.0")]),
+                license("GPL-2.0 WITH GCC-exception-2.0"),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_invalid_expression_as_license() {
+        assert_eq!(
+            LicenseExpression::parse("MIT OR (Apache-2.0"),
+            license("MIT OR (Apache-2.0")
+        );
+        assert_eq!(LicenseExpression::parse("MIT AND"), license("MIT AND"));
+    }
+
+    #[test]
+    fn violation_of_allow_list() {
+        let policy = policy(&["MIT", "Apache-2.0"], &[]);
+        assert_eq!(policy.violation(Some("MIT")), None);
+        assert_eq!(policy.violation(Some("MIT OR GPL-3.0")), None);
+        assert_eq!(
+            policy.violation(Some("MIT AND GPL-3.0")),
+            Some("MIT AND GPL-3.0 is not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn violation_of_deny_list() {
+        let policy = policy(&[], &["GPL-3.0"]);
+        assert_eq!(policy.violation(Some("MIT")), None);
+        assert_eq!(
+            policy.violation(Some("GPL-3.0-or-later")),
+            Some("GPL-3.0-or-later is denied".to_string())
+        );
+        assert_eq!(
+            policy.violation(Some("GPL-3.0 WITH GCC-exception-3.1")),
+            Some("GPL-3.0 WITH GCC-exception-3.1 is denied".to_string())
+        );
+    }
+
+    #[test]
+    fn violation_of_unknown_license() {
+        assert_eq!(policy(&[], &[]).violation(None), None);
+        assert_eq!(
+            policy(&[], &["GPL-3.0"]).violation(None),
+            Some("the license is unknown".to_string())
+        );
+        assert_eq!(
+            policy(&["MIT"], &[]).violation(None),
+            Some("the license is unknown".to_string())
+        );
+    }
+}
@@ -1,8 +1,15 @@
+mod custom;
+mod license_policy;
 mod third_party;
 
-pub use third_party::{ThirdPartyLibrary, VersionedThirdPartyLibraries};
+pub use custom::{CustomLibrary, CustomLibraryKind, CustomLibraryLocation};
+pub use license_policy::{LicenseException, LicensePolicy};
+pub use third_party::{
+    LibraryVersion, PinnedVersion, ThirdPartyLibrary, VersionedThirdPartyLibraries,
+};
 
 use shared_library_builder::{LibraryLocation, PathLocation, RustLibrary};
+use std::path::{Path, PathBuf};
 
 pub fn test_library() -> RustLibrary {
     RustLibrary::new(
@@ -10,3 +17,19 @@ pub fn test_library() -> RustLibrary {
         LibraryLocation::Path(PathLocation::new("vm-client-test-library")),
     )
 }
+
+/// Git checkouts of a library, which is either cloned into its sources directory or into one of its subdirectories.
+/// Directories without `.git` are skipped, otherwise git finds the enclosing workspace repository
+pub fn library_repositories(sources_directory: &Path) -> Vec<PathBuf> {
+    let mut subdirectories = sources_directory
+        .read_dir()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect::<Vec<PathBuf>>();
+    subdirectories.sort();
+    std::iter::once(sources_directory.to_path_buf())
+        .chain(subdirectories)
+        .filter(|path| path.join(".git").exists())
+        .collect()
+}
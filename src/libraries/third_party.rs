@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use clap::ArgEnum;
@@ -20,11 +21,19 @@ use libwebview_builder::libwebview;
 use libwinit30_builder::libwinit as libwinit30;
 use libwinit_builder::libwinit;
 use serde::{Deserialize, Serialize};
-use shared_library_builder::{GitLocation, Library, LibraryLocation, LibraryTarget, RustLibrary};
+use shared_library_builder::{
+    GitLocation, Library, LibraryLocation, LibraryTarget, PathLocation, RustLibrary,
+};
 
-use crate::libraries::test_library;
+use crate::git_urls::UrlRewrite;
+use crate::libraries::{test_library, CustomLibrary, CustomLibraryLocation, LicensePolicy};
+use crate::requirements::Requirement;
+use crate::Context;
+use crate::Target;
 
-#[derive(ArgEnum, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(
+    ArgEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 #[repr(u32)]
 pub enum ThirdPartyLibrary {
     #[clap(name = "git")]
@@ -69,19 +78,73 @@ pub enum ThirdPartyLibrary {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VersionedThirdPartyLibraries {
+    /// Libraries declared by the user in addition to the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    libraries: Vec<CustomLibrary>,
+    /// Libraries built from local checkouts instead of their releases, relative to the versions file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    library_paths: BTreeMap<String, PathBuf>,
+    /// Fetch library sources from other locations, like `insteadOf` in the git config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    url_rewrites: Vec<UrlRewrite>,
+    /// Licenses of the libraries and crates that may be shipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    license_policies: Vec<LicensePolicy>,
     #[serde(flatten)]
-    libraries: HashMap<ThirdPartyLibrary, String>,
+    versions: BTreeMap<ThirdPartyLibrary, LibraryVersion>,
+}
+
+/// A version of a library, either as a plain tag or pinned to a commit and checksums
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LibraryVersion {
+    Version(String),
+    Pinned(PinnedVersion),
+}
+
+/// A version with the commit its sources must be checked out at, a SHA-256 of the fetched sources
+/// and SHA-256 checksums of the compiled or downloaded library per target
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PinnedVersion {
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// A SHA-256 of the paths and contents of the fetched sources as computed by `checksums::sources_sha256`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sha256: BTreeMap<String, String>,
+}
+
+impl LibraryVersion {
+    pub fn version(&self) -> &str {
+        match self {
+            LibraryVersion::Version(version) => version.as_str(),
+            LibraryVersion::Pinned(pinned) => pinned.version.as_str(),
+        }
+    }
+
+    pub fn pinned(&self) -> Option<&PinnedVersion> {
+        match self {
+            LibraryVersion::Version(_) => None,
+            LibraryVersion::Pinned(pinned) => Some(pinned),
+        }
+    }
 }
 
 impl VersionedThirdPartyLibraries {
     pub fn new() -> Self {
         Self {
-            libraries: HashMap::new(),
+            libraries: vec![],
+            library_paths: BTreeMap::new(),
+            url_rewrites: vec![],
+            license_policies: vec![],
+            versions: BTreeMap::new(),
         }
     }
 
     pub fn get_version_of(&self, library: ThirdPartyLibrary) -> Option<&str> {
-        self.libraries.get(&library).map(|version| version.as_str())
+        self.versions.get(&library).map(|version| version.version())
     }
 
     pub fn version_of(&self, library: ThirdPartyLibrary) -> &str {
@@ -89,8 +152,53 @@ impl VersionedThirdPartyLibraries {
             .expect("Could not find a library version")
     }
 
+    /// Changing the version drops the commit and checksums pinned for the previous one
     pub fn set_version_of(&mut self, library: ThirdPartyLibrary, version: impl Into<String>) {
-        self.libraries.insert(library, version.into());
+        self.versions
+            .insert(library, LibraryVersion::Version(version.into()));
+    }
+
+    pub fn pinned_version_of(&self, library: ThirdPartyLibrary) -> Option<&PinnedVersion> {
+        self.versions
+            .get(&library)
+            .and_then(|version| version.pinned())
+    }
+
+    /// Libraries pinned to a commit or checksums, whether selected or not
+    pub fn pinned_versions(&self) -> impl Iterator<Item = (ThirdPartyLibrary, &PinnedVersion)> {
+        self.versions
+            .iter()
+            .filter_map(|(library, version)| version.pinned().map(|pinned| (*library, pinned)))
+    }
+
+    pub fn custom_libraries(&self) -> &[CustomLibrary] {
+        self.libraries.as_slice()
+    }
+
+    pub fn library_paths(&self) -> &BTreeMap<String, PathBuf> {
+        &self.library_paths
+    }
+
+    pub fn url_rewrites(&self) -> &[UrlRewrite] {
+        self.url_rewrites.as_slice()
+    }
+
+    pub fn license_policies(&self) -> &[LicensePolicy] {
+        self.license_policies.as_slice()
+    }
+
+    /// Check out a custom library from git at the given tag instead of its latest release
+    pub fn set_tag_of_custom_library(&mut self, name: &str, new_tag: impl Into<String>) {
+        let new_tag = new_tag.into();
+        for library in self
+            .libraries
+            .iter_mut()
+            .filter(|library| library.name == name)
+        {
+            if let CustomLibraryLocation::Git { ref mut tag, .. } = library.location {
+                *tag = Some(new_tag.clone());
+            }
+        }
     }
 }
 
@@ -113,73 +221,219 @@ impl Display for ThirdPartyLibrary {
 }
 
 impl ThirdPartyLibrary {
+    /// External tools needed to build the library in addition to the rust toolchain
+    pub fn requirements(&self, target: &Target) -> Vec<Requirement> {
+        let reason = format!("to build {}", self);
+        match self {
+            ThirdPartyLibrary::Crypto | ThirdPartyLibrary::Ssl => {
+                let mut requirements = vec![Requirement::tool("perl", &reason)
+                    .hint("Install perl, on Windows for example Strawberry Perl")];
+                if target.is_windows() {
+                    requirements.push(
+                        Requirement::tool("nasm", &reason)
+                            .hint("Install nasm from https://nasm.us to build OpenSSL with assembly")
+                            .optional(),
+                    );
+                }
+                requirements
+            }
+            ThirdPartyLibrary::Git
+            | ThirdPartyLibrary::Sdl2
+            | ThirdPartyLibrary::Freetype
+            | ThirdPartyLibrary::Cairo => {
+                let mut requirements = vec![Requirement::tool("cmake", &reason)
+                    .hint("Install cmake from https://cmake.org/download")];
+                if target.is_unix() {
+                    requirements.push(Requirement::tool("pkg-config", &reason).optional());
+                }
+                requirements
+            }
+            ThirdPartyLibrary::Skia => vec![
+                Requirement::tool("python3", "to build skia from sources")
+                    .hint("Install python 3, it is only needed when prebuilt skia binaries are not available")
+                    .optional(),
+                Requirement::tool("ninja", "to build skia from sources")
+                    .hint("Install ninja, it is only needed when prebuilt skia binaries are not available")
+                    .optional(),
+            ],
+            _ => vec![],
+        }
+    }
+
     pub fn as_library(
         &self,
         target: LibraryTarget,
         versions: &VersionedThirdPartyLibraries,
-    ) -> Box<dyn Library> {
+    ) -> crate::Result<Box<dyn Library>> {
+        self.as_rust_library(target, versions)
+            .map(|library| library.into())
+            .or_else(|| self.as_cmake_library(versions))
+            .context(|| {
+                crate::Error::library(format!(
+                    "{} is neither compiled with cargo nor with cmake",
+                    self
+                ))
+            })
+    }
+
+    /// The library as it is configured when it is built with cmake,
+    /// or None if it is compiled with cargo
+    fn as_cmake_library(
+        &self,
+        versions: &VersionedThirdPartyLibraries,
+    ) -> Option<Box<dyn Library>> {
         match self {
-            ThirdPartyLibrary::Boxer => {
-                libboxer(versions.get_version_of(ThirdPartyLibrary::Boxer)).into()
-            }
             ThirdPartyLibrary::Cairo => {
-                libcairo(versions.get_version_of(ThirdPartyLibrary::Cairo)).into()
-            }
-            ThirdPartyLibrary::Clipboard => {
-                libclipboard(versions.get_version_of(ThirdPartyLibrary::Clipboard)).into()
-            }
-            ThirdPartyLibrary::Filewatcher => {
-                libfilewatcher(versions.get_version_of(ThirdPartyLibrary::Filewatcher)).into()
+                Some(libcairo(versions.get_version_of(ThirdPartyLibrary::Cairo)).into())
             }
             ThirdPartyLibrary::Crypto => {
-                libcrypto(versions.get_version_of(ThirdPartyLibrary::Crypto)).into()
+                Some(libcrypto(versions.get_version_of(ThirdPartyLibrary::Crypto)).into())
             }
-            ThirdPartyLibrary::Editor => RustLibrary::new(
-                "Editor",
-                LibraryLocation::Git(
-                    GitLocation::github("feenkcom", "gtoolkit-editor-rs-release")
-                        .tag_or_latest(versions.get_version_of(ThirdPartyLibrary::Editor)),
-                ),
-            )
-            .package("libeditor")
-            .into(),
             ThirdPartyLibrary::Freetype => {
-                libfreetype(versions.get_version_of(ThirdPartyLibrary::Freetype)).into()
+                Some(libfreetype(versions.get_version_of(ThirdPartyLibrary::Freetype)).into())
             }
             ThirdPartyLibrary::Git => {
-                libgit2(versions.get_version_of(ThirdPartyLibrary::Git)).into()
-            }
-            ThirdPartyLibrary::Gleam => {
-                libgleam(versions.get_version_of(ThirdPartyLibrary::Gleam)).into()
-            }
-            ThirdPartyLibrary::Glutin => {
-                libglutin(versions.get_version_of(ThirdPartyLibrary::Glutin)).into()
-            }
-            ThirdPartyLibrary::Process => {
-                libprocess(versions.get_version_of(ThirdPartyLibrary::Process)).into()
+                Some(libgit2(versions.get_version_of(ThirdPartyLibrary::Git)).into())
             }
             ThirdPartyLibrary::Sdl2 => {
-                libsdl2(versions.get_version_of(ThirdPartyLibrary::Sdl2)).into()
-            }
-            ThirdPartyLibrary::Skia => {
-                libskia(target, versions.get_version_of(ThirdPartyLibrary::Skia)).into()
+                Some(libsdl2(versions.get_version_of(ThirdPartyLibrary::Sdl2)).into())
             }
             ThirdPartyLibrary::Ssl => {
-                libssl(versions.get_version_of(ThirdPartyLibrary::Ssl)).into()
-            }
-            ThirdPartyLibrary::Winit => {
-                libwinit(versions.get_version_of(ThirdPartyLibrary::Winit)).into()
+                Some(libssl(versions.get_version_of(ThirdPartyLibrary::Ssl)).into())
             }
-            ThirdPartyLibrary::Winit30 => {
-                libwinit30(versions.get_version_of(ThirdPartyLibrary::Winit30)).into()
+            ThirdPartyLibrary::Boxer
+            | ThirdPartyLibrary::Clipboard
+            | ThirdPartyLibrary::Filewatcher
+            | ThirdPartyLibrary::Editor
+            | ThirdPartyLibrary::Gleam
+            | ThirdPartyLibrary::Glutin
+            | ThirdPartyLibrary::Process
+            | ThirdPartyLibrary::Skia
+            | ThirdPartyLibrary::Winit
+            | ThirdPartyLibrary::Winit30
+            | ThirdPartyLibrary::Pixels
+            | ThirdPartyLibrary::WebView
+            | ThirdPartyLibrary::TestLibrary => None,
+        }
+    }
+
+    /// The library as it is configured when it is compiled with cargo,
+    /// or None if it is built with cmake
+    fn as_rust_library(
+        &self,
+        target: LibraryTarget,
+        versions: &VersionedThirdPartyLibraries,
+    ) -> Option<RustLibrary> {
+        match self {
+            ThirdPartyLibrary::Boxer => {
+                Some(libboxer(versions.get_version_of(ThirdPartyLibrary::Boxer)))
             }
-            ThirdPartyLibrary::Pixels => {
-                libpixels(versions.get_version_of(ThirdPartyLibrary::Pixels)).into()
+            ThirdPartyLibrary::Clipboard => Some(libclipboard(
+                versions.get_version_of(ThirdPartyLibrary::Clipboard),
+            )),
+            ThirdPartyLibrary::Filewatcher => Some(libfilewatcher(
+                versions.get_version_of(ThirdPartyLibrary::Filewatcher),
+            )),
+            ThirdPartyLibrary::Editor => Some(
+                RustLibrary::new(
+                    "Editor",
+                    LibraryLocation::Git(
+                        GitLocation::github("feenkcom", "gtoolkit-editor-rs-release")
+                            .tag_or_latest(versions.get_version_of(ThirdPartyLibrary::Editor)),
+                    ),
+                )
+                .package("libeditor"),
+            ),
+            ThirdPartyLibrary::Gleam => {
+                Some(libgleam(versions.get_version_of(ThirdPartyLibrary::Gleam)))
             }
-            ThirdPartyLibrary::WebView => {
-                libwebview(versions.get_version_of(ThirdPartyLibrary::WebView)).into()
+            ThirdPartyLibrary::Glutin => Some(libglutin(
+                versions.get_version_of(ThirdPartyLibrary::Glutin),
+            )),
+            ThirdPartyLibrary::Process => Some(libprocess(
+                versions.get_version_of(ThirdPartyLibrary::Process),
+            )),
+            ThirdPartyLibrary::Skia => Some(libskia(
+                target,
+                versions.get_version_of(ThirdPartyLibrary::Skia),
+            )),
+            ThirdPartyLibrary::Winit => {
+                Some(libwinit(versions.get_version_of(ThirdPartyLibrary::Winit)))
             }
-            ThirdPartyLibrary::TestLibrary => test_library().into(),
+            ThirdPartyLibrary::Winit30 => Some(libwinit30(
+                versions.get_version_of(ThirdPartyLibrary::Winit30),
+            )),
+            ThirdPartyLibrary::Pixels => Some(libpixels(
+                versions.get_version_of(ThirdPartyLibrary::Pixels),
+            )),
+            ThirdPartyLibrary::WebView => Some(libwebview(
+                versions.get_version_of(ThirdPartyLibrary::WebView),
+            )),
+            ThirdPartyLibrary::TestLibrary => Some(test_library()),
+            ThirdPartyLibrary::Cairo
+            | ThirdPartyLibrary::Crypto
+            | ThirdPartyLibrary::Freetype
+            | ThirdPartyLibrary::Git
+            | ThirdPartyLibrary::Sdl2
+            | ThirdPartyLibrary::Ssl => None,
+        }
+    }
+
+    /// Libraries built with cmake are described by their builder crates
+    /// and can not be built from a local checkout
+    pub fn can_be_built_from_local_checkout(&self) -> bool {
+        !matches!(
+            self,
+            ThirdPartyLibrary::Git
+                | ThirdPartyLibrary::Crypto
+                | ThirdPartyLibrary::Ssl
+                | ThirdPartyLibrary::Sdl2
+                | ThirdPartyLibrary::Freetype
+                | ThirdPartyLibrary::Cairo
+        )
+    }
+
+    /// A cargo package of the library within its repository,
+    /// or None if the repository is the package itself
+    fn local_package(&self) -> Option<&str> {
+        match self {
+            ThirdPartyLibrary::Git
+            | ThirdPartyLibrary::Crypto
+            | ThirdPartyLibrary::Ssl
+            | ThirdPartyLibrary::Sdl2
+            | ThirdPartyLibrary::Freetype
+            | ThirdPartyLibrary::Cairo
+            | ThirdPartyLibrary::TestLibrary => None,
+            ThirdPartyLibrary::Boxer => Some("libboxer"),
+            ThirdPartyLibrary::Editor => Some("libeditor"),
+            ThirdPartyLibrary::Skia => Some("libskia"),
+            ThirdPartyLibrary::Glutin => Some("libglutin"),
+            ThirdPartyLibrary::Gleam => Some("libgleam"),
+            ThirdPartyLibrary::Winit => Some("libwinit"),
+            ThirdPartyLibrary::Winit30 => Some("libwinit30"),
+            ThirdPartyLibrary::Pixels => Some("libpixels"),
+            ThirdPartyLibrary::Clipboard => Some("libclipboard"),
+            ThirdPartyLibrary::Filewatcher => Some("libfilewatcher"),
+            ThirdPartyLibrary::Process => Some("libprocess"),
+            ThirdPartyLibrary::WebView => Some("libwebview"),
         }
     }
+
+    /// The same library compiled from a local checkout of its repository instead of a release,
+    /// or None if it is built with cmake
+    pub fn as_local_library(
+        &self,
+        target: LibraryTarget,
+        versions: &VersionedThirdPartyLibraries,
+        path: &Path,
+    ) -> Option<Box<dyn Library>> {
+        // keep the features, requirements and environment of the configured library
+        let library = self
+            .as_rust_library(target, versions)?
+            .with_location(LibraryLocation::Path(PathLocation::new(path.to_path_buf())));
+        Some(match self.local_package() {
+            Some(package) => library.package(package).into(),
+            None => library.into(),
+        })
+    }
 }
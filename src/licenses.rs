@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bundlers::{create_directory, recreate_directory};
+use crate::events::Event;
+use crate::libraries::library_repositories;
+use crate::{events, sbom, BundleOptions, Context, Error, Result};
+
+/// A name of the generated file with the license texts grouped by license
+pub const THIRD_PARTY_NOTICES_FILE: &str = "THIRD_PARTY_NOTICES";
+
+/// Files whose names start with one of these are considered to be license files
+const LICENSE_FILE_PREFIXES: [&str; 5] = ["license", "licence", "copying", "notice", "copyright"];
+
+/// Phrases of the license texts, all of them must be present to detect a license.
+/// More specific licenses come first, for example the LGPL text mentions the GPL
+const LICENSE_MARKERS: [(&str, &[&str]); 18] = [
+    (
+        "GPL-2.0 WITH GCC-exception-2.0",
+        &["gnu general public license", "linking exception"],
+    ),
+    ("OpenSSL", &["openssl license", "original ssleay license"]),
+    ("AGPL-3.0", &["gnu affero general public license version 3"]),
+    ("LGPL-3.0", &["gnu lesser general public license version 3"]),
+    (
+        "LGPL-2.1",
+        &["gnu lesser general public license version 2.1"],
+    ),
+    (
+        "LGPL-2.0",
+        &["gnu library general public license version 2"],
+    ),
+    ("GPL-3.0", &["gnu general public license version 3"]),
+    ("GPL-2.0", &["gnu general public license version 2"]),
+    ("MPL-2.0", &["mozilla public license version 2.0"]),
+    ("MPL-1.1", &["mozilla public license version 1.1"]),
+    ("Apache-2.0", &["apache license version 2.0"]),
+    ("FTL", &["the freetype project license"]),
+    (
+        "Zlib",
+        &["provided 'as-is', without any express or implied warranty"],
+    ),
+    (
+        "BSD-3-Clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "neither the name",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["redistribution and use in source and binary forms"],
+    ),
+    ("MIT", &["permission is hereby granted, free of charge"]),
+    (
+        "ISC",
+        &["permission to use, copy, modify, and/or distribute this software for any purpose"],
+    ),
+    (
+        "Unlicense",
+        &["this is free and unencumbered software released into the public domain"],
+    ),
+];
+
+/// The license of a third party library or a Rust crate shipped in the bundle
+#[derive(Debug, Clone)]
+pub struct ComponentLicense {
+    pub name: String,
+    pub version: Option<String>,
+    /// An SPDX expression, either declared by the crate or detected from the license files
+    pub license: Option<String>,
+    pub files: Vec<PathBuf>,
+}
+
+impl ComponentLicense {
+    pub fn display_name(&self) -> String {
+        match &self.version {
+            None => self.name.clone(),
+            Some(version) => format!("{} {}", &self.name, version),
+        }
+    }
+
+    fn directory_name(&self) -> String {
+        match &self.version {
+            None => self.name.clone(),
+            Some(version) => format!("{}-{}", &self.name, version),
+        }
+    }
+
+    fn is_found(&self) -> bool {
+        self.license.is_some() || !self.files.is_empty()
+    }
+}
+
+/// Find the licenses of the selected third party libraries and of the crates
+/// the executables depend on, sorted by name
+pub fn detect_licenses(options: &BundleOptions) -> Result<Vec<ComponentLicense>> {
+    let mut licenses = vec![];
+
+    for library in sbom::library_components(options) {
+        let files = library_license_files(&library.sources_directory)?;
+        let detected = files
+            .iter()
+            .filter_map(|file| fs::read_to_string(file).ok())
+            .filter_map(|text| detect_license(&text))
+            .collect::<BTreeSet<&str>>();
+        // a library may ship several licenses that apply to different parts of it
+        let license = if detected.is_empty() {
+            None
+        } else {
+            Some(detected.into_iter().collect::<Vec<&str>>().join(" AND "))
+        };
+        licenses.push(ComponentLicense {
+            name: library.name,
+            version: library.version,
+            license,
+            files,
+        });
+    }
+
+    // the crates of the workspace are the app itself
+    for each in sbom::executable_crates(options)?
+        .into_iter()
+        .filter(|each| each.source.is_some())
+    {
+        let mut files = license_files(each.directory())?;
+        if let Some(license_file) = &each.license_file {
+            let license_file = each.directory().join(license_file);
+            if license_file.is_file() && !files.contains(&license_file) {
+                files.push(license_file);
+            }
+        }
+        let license = each.license_expression().or_else(|| {
+            files
+                .iter()
+                .filter_map(|file| fs::read_to_string(file).ok())
+                .find_map(|text| detect_license(&text))
+                .map(|license| license.to_string())
+        });
+        licenses.push(ComponentLicense {
+            name: each.name.clone(),
+            version: Some(each.version.clone()),
+            license,
+            files,
+        });
+    }
+
+    licenses.sort_by(|first, second| {
+        (&first.name, &first.version).cmp(&(&second.name, &second.version))
+    });
+    Ok(licenses)
+}
+
+/// Copy the license files of third party libraries and crates into the licenses directory
+/// together with the THIRD_PARTY_NOTICES, to be placed into the resources of the bundle
+pub fn collect_licenses(options: &BundleOptions) -> Result<()> {
+    let licenses = detect_licenses(options)?;
+    check_license_policies(options, &licenses)?;
+
+    let missing = licenses
+        .iter()
+        .filter(|license| !license.is_found())
+        .map(|license| license.display_name())
+        .collect::<Vec<String>>();
+    if !missing.is_empty() {
+        if options.strict_licenses() {
+            return Error::bundling(format!(
+                "Could not find the license of {}",
+                missing.join(", ")
+            ))
+            .hint("Make sure the sources contain a LICENSE or COPYING file, or build without --strict-licenses")
+            .into();
+        }
+        for each in &missing {
+            events::emit(Event::Warning {
+                message: format!("Could not find the license of {}", each),
+            });
+        }
+    }
+
+    let directory = options.licenses_directory();
+    recreate_directory(&directory)?;
+
+    for license in &licenses {
+        if license.files.is_empty() {
+            continue;
+        }
+        let component_directory = directory.join(license.directory_name());
+        create_directory(&component_directory)?;
+        for file in &license.files {
+            let copied_file = component_directory.join(file.file_name().unwrap_or_default());
+            fs::copy(file, &copied_file).context(|| {
+                Error::io(format!(
+                    "Could not copy {} to {}",
+                    file.display(),
+                    copied_file.display()
+                ))
+            })?;
+        }
+    }
+
+    let notices_file = directory.join(THIRD_PARTY_NOTICES_FILE);
+    fs::write(&notices_file, third_party_notices(options, &licenses))
+        .context(|| Error::io(format!("Could not write {}", notices_file.display())))?;
+
+    Ok(())
+}
+
+/// Fail with a report of all components whose licenses are not allowed by the policies for the target
+fn check_license_policies(options: &BundleOptions, licenses: &[ComponentLicense]) -> Result<()> {
+    let mut violations = vec![];
+    for policy in options.license_policies() {
+        for license in licenses {
+            if policy.exception_for(&license.name).is_some() {
+                continue;
+            }
+            if let Some(violation) = policy.violation(license.license.as_deref()) {
+                let violation = format!("  - {}: {}", license.display_name(), violation);
+                if !violations.contains(&violation) {
+                    violations.push(violation);
+                }
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Error::bundling(format!(
+        "The licenses of {} components are not allowed for {}:\n{}",
+        violations.len(),
+        options.target().to_string(),
+        violations.join("\n")
+    ))
+    .hint("Remove the components, or add an exception with a justification to the license policy")
+    .into()
+}
+
+/// The license texts grouped by license. Components that ship the same text share it
+fn third_party_notices(options: &BundleOptions, licenses: &[ComponentLicense]) -> String {
+    // license -> text -> components
+    let mut groups: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for license in licenses.iter().filter(|license| license.is_found()) {
+        let group = groups
+            .entry(
+                license
+                    .license
+                    .clone()
+                    .unwrap_or_else(|| "Unknown license".to_string()),
+            )
+            .or_default();
+        let texts = license
+            .files
+            .iter()
+            .filter_map(|file| fs::read_to_string(file).ok())
+            .collect::<Vec<String>>();
+        if texts.is_empty() {
+            group
+                .entry(String::new())
+                .or_default()
+                .push(license.display_name());
+        }
+        for text in texts {
+            group
+                .entry(text.trim().to_string())
+                .or_default()
+                .push(license.display_name());
+        }
+    }
+
+    let separator = "=".repeat(80);
+    let mut notices = String::new();
+    writeln!(
+        notices,
+        "{} {} includes the following third party software.",
+        options.app_name(),
+        options.version()
+    )
+    .unwrap();
+
+    for (license, texts) in groups {
+        write!(notices, "\n{}\n{}\n{}\n", &separator, license, &separator).unwrap();
+        for (text, components) in texts {
+            writeln!(notices).unwrap();
+            for component in components {
+                writeln!(notices, "  - {}", component).unwrap();
+            }
+            if !text.is_empty() {
+                write!(notices, "\n{}\n{}\n", text, "-".repeat(80)).unwrap();
+            }
+        }
+    }
+    notices
+}
+
+/// License files of a library, which may be checked out into a subdirectory of its sources directory
+fn library_license_files(sources_directory: &Path) -> Result<Vec<PathBuf>> {
+    let files = license_files(sources_directory)?;
+    if !files.is_empty() || !sources_directory.is_dir() {
+        return Ok(files);
+    }
+
+    let mut files = vec![];
+    for repository in library_repositories(sources_directory) {
+        files.extend(license_files(&repository)?);
+    }
+    Ok(files)
+}
+
+/// License files directly in a given directory, sorted by name
+fn license_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    if !directory.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut files = vec![];
+    for entry in directory
+        .read_dir()
+        .context(|| Error::io(format!("Could not read {}", directory.display())))?
+    {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if path.is_file()
+            && LICENSE_FILE_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Recognize a license by the characteristic phrases of its text
+pub fn detect_license(text: &str) -> Option<&'static str> {
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+    LICENSE_MARKERS
+        .iter()
+        .find(|(_, markers)| markers.iter().all(|marker| text.contains(marker)))
+        .map(|(license, _)| *license)
+}
@@ -17,6 +17,8 @@ extern crate xz2;
 
 use std::fs;
 use std::io::Write;
+use std::process::Child;
+use std::time::Instant;
 
 use clap::{Parser, Subcommand};
 
@@ -28,11 +30,28 @@ use crate::bundlers::linux::LinuxBundler;
 use crate::bundlers::mac::MacBundler;
 use crate::bundlers::windows::WindowsBundler;
 use crate::bundlers::Bundler;
+use crate::events::{Event, MessageFormat, Phase};
+use crate::report::BuildReport;
 
+mod archives;
 mod bundlers;
+mod checksums;
+mod commands;
+mod crates;
+mod environment;
 mod error;
+mod events;
+mod git_urls;
+mod integrity;
 mod libraries;
+mod licenses;
+mod offline;
 mod options;
+mod report;
+mod reproducible;
+mod requirements;
+mod sbom;
+mod symbols;
 
 #[derive(Parser, Debug)]
 #[clap(version = "1.0", author = "feenk gmbh <contact@feenk.com>")]
@@ -51,69 +70,284 @@ enum Command {
     Bundle(BuilderOptions),
     /// Compile and bundle in one go
     Build(BuilderOptions),
+    /// Build twice with --reproducible into separate target directories and compare the bundles
+    CheckReproducible(BuilderOptions),
+    /// Remove the bundle, compiled executables, third party libraries or their sources
+    Clean(CleanOptions),
+    /// Put the workspace, library sources and all dependencies into a directory for offline builds
+    Vendor(VendorOptions),
+    /// Check that the tools needed to build for a target are installed
+    Doctor(DoctorOptions),
+    /// Print the version, target, features and libraries an existing bundle was built with
+    Info(InfoOptions),
+    /// Check the files of a bundle against the integrity manifest written when bundling
+    VerifyIntegrity(VerifyIntegrityOptions),
+    /// Work with build reports
+    Report {
+        #[clap(subcommand)]
+        command: ReportCommand,
+    },
 }
 
-fn main() -> Result<()> {
+fn main() {
     let cli = Cli::parse();
 
-    match cli.command {
+    if let Err(error) = run(cli.command) {
+        let exit_code = ErrorKind::of(error.as_ref()).exit_code();
+        let failed = Event::Failed {
+            error: error.to_string(),
+            exit_code,
+        };
+        match events::message_format() {
+            MessageFormat::Human => eprintln!("{}", failed),
+            MessageFormat::Json => events::emit(failed),
+        }
+        std::process::exit(exit_code);
+    }
+}
+
+fn run(command: Command) -> Result<()> {
+    match command {
         Command::Compile(build_options) => compile(build_options)?,
         Command::Bundle(build_options) => bundle(build_options)?,
         Command::Build(build_options) => build(build_options)?,
+        Command::CheckReproducible(build_options) => check_reproducible(build_options)?,
         Command::CompileThirdParty(build_options) => build_third_party(build_options)?,
+        Command::Clean(clean_options) => clean(clean_options)?,
+        Command::Vendor(vendor_options) => vendor(vendor_options)?,
+        Command::Doctor(doctor_options) => {
+            let bundler = bundler_for_platform(doctor_options.target().platform());
+            commands::doctor(&*bundler, &doctor_options)?
+        }
+        Command::Info(info_options) => commands::info(&info_options)?,
+        Command::VerifyIntegrity(verify_options) => commands::verify_integrity(&verify_options)?,
+        Command::Report { command } => match command {
+            ReportCommand::Diff(diff_options) => commands::report_diff(&diff_options)?,
+        },
     }
 
     Ok(())
 }
 
 fn build(build_options: BuilderOptions) -> Result<()> {
-    let (bundler, bundle_options) = prepare(build_options);
-    compile_components(&*bundler, &bundle_options)?;
-    bundler.bundle(&bundle_options);
+    let (bundler, bundle_options) = prepare(build_options)?;
+    bundler.validate_bundling(&bundle_options)?;
+    if bundle_options.offline() {
+        offline::check_inputs(&*bundler, &bundle_options, true)?;
+    }
+    let result = compile_components(&*bundler, &bundle_options)
+        .and_then(|_| bundle_components(&*bundler, &bundle_options));
+    write_build_report(&bundle_options, result)
+}
 
-    Ok(())
+fn check_reproducible(build_options: BuilderOptions) -> Result<()> {
+    let target_dir = ResolvedOptions::new(build_options.clone())?
+        .target_dir()
+        .join("reproducible");
+
+    let mut bundles = vec![];
+    for build_name in ["first", "second"] {
+        let options = build_options.for_reproducibility_check(target_dir.join(build_name));
+        let bundle_location =
+            BundleOptions::new(ResolvedOptions::new(options.clone())?).bundle_location();
+        build(options)?;
+        bundles.push(bundle_location);
+    }
+
+    commands::compare_bundles(&bundles[0], &bundles[1])
 }
 
 fn build_third_party(build_options: BuilderOptions) -> Result<()> {
-    let (bundler, bundle_options) = prepare(build_options);
-    bundler.ensure_compiled_libraries_directory(&bundle_options)?;
-    bundler.compile_third_party_libraries(&bundle_options)?;
-    Ok(())
+    let (bundler, bundle_options) = prepare(build_options)?;
+    if bundle_options.offline() {
+        offline::check_inputs(&*bundler, &bundle_options, false)?;
+    }
+    let result = bundle_options
+        .target_variants()
+        .and_then(|target_variants| {
+            target_variants.iter().try_for_each(|target_options| {
+                bundler.ensure_compiled_libraries_directory(target_options)?;
+                bundler.compile_third_party_libraries(target_options)
+            })
+        });
+    write_build_report(&bundle_options, result)
 }
 
 fn compile(build_options: BuilderOptions) -> Result<()> {
-    let (bundler, bundle_options) = prepare(build_options);
-    compile_components(&*bundler, &bundle_options)
+    let (bundler, bundle_options) = prepare(build_options)?;
+    if bundle_options.offline() {
+        offline::check_inputs(&*bundler, &bundle_options, true)?;
+    }
+    let result = compile_components(&*bundler, &bundle_options);
+    write_build_report(&bundle_options, result)
 }
 
 fn bundle(build_options: BuilderOptions) -> Result<()> {
-    let (bundler, bundle_options) = prepare(build_options);
-    bundler.ensure_compiled_libraries_directory(&bundle_options)?;
-    bundler.bundle(&bundle_options);
-
-    Ok(())
+    let (bundler, bundle_options) = prepare(build_options)?;
+    bundler.validate_bundling(&bundle_options)?;
+    let result = bundler
+        .ensure_compiled_libraries_directory(&bundle_options)
+        .and_then(|_| bundle_components(&*bundler, &bundle_options));
+    write_build_report(&bundle_options, result)
 }
 
-fn prepare(build_options: BuilderOptions) -> (Box<dyn Bundler>, BundleOptions) {
-    let resolved_options = ResolvedOptions::new(build_options);
+fn clean(clean_options: CleanOptions) -> Result<()> {
+    // cleaning only needs the paths, so nothing is validated or set up for building
+    let resolved_options = ResolvedOptions::resolve(clean_options.builder_options())?;
     let bundler = bundler(&resolved_options);
-
     let bundle_options = BundleOptions::new(resolved_options);
+    commands::clean(&*bundler, &bundle_options, &clean_options)
+}
+
+fn vendor(vendor_options: VendorOptions) -> Result<()> {
+    let (_, bundle_options) = prepare(vendor_options.builder_options().clone())?;
+    commands::vendor(&bundle_options, &vendor_options)
+}
+
+fn prepare(build_options: BuilderOptions) -> Result<(Box<dyn Bundler>, BundleOptions)> {
+    events::set_message_format(build_options.message_format());
+    events::start_recording();
+
+    let (bundler, bundle_options) = events::phase(
+        Phase::ResolveOptions,
+        build_options.target().to_string(),
+        || {
+            let resolved_options = ResolvedOptions::new(build_options)?;
+            let bundler = bundler(&resolved_options);
+
+            let bundle_options = BundleOptions::new(resolved_options);
+            bundler.validate(&bundle_options)?;
+
+            Ok((bundler, bundle_options))
+        },
+    )?;
+    events::emit(Event::options_resolved(&bundle_options));
 
-    (bundler, bundle_options)
+    Ok((bundler, bundle_options))
 }
 
 fn compile_components(bundler: &dyn Bundler, bundle_options: &BundleOptions) -> Result<()> {
+    for target_options in bundle_options.target_variants()? {
+        compile_target_components(bundler, &target_options)?;
+    }
+    Ok(())
+}
+
+fn compile_target_components(bundler: &dyn Bundler, bundle_options: &BundleOptions) -> Result<()> {
     bundler.ensure_compiled_libraries_directory(bundle_options)?;
 
-    export_build_info(bundler, bundle_options)?;
+    events::phase(
+        Phase::ExportBuildInfo,
+        bundle_options.target().to_string(),
+        || export_build_info(bundler, bundle_options),
+    )?;
 
-    bundle_options.executables().iter().for_each(|executable| {
-        let executable_options = ExecutableOptions::new(bundle_options, executable.clone());
-        bundler.pre_compile(&executable_options);
-        bundler.compile_binary(&executable_options);
-        bundler.post_compile(bundle_options, executable, &executable_options)
-    });
+    let executables_options = bundle_options
+        .executables()
+        .iter()
+        .map(|executable| ExecutableOptions::new(bundle_options, executable.clone()))
+        .collect::<Vec<ExecutableOptions>>();
+
+    for executable_options in &executables_options {
+        events::phase(
+            Phase::PreCompile,
+            executable_options.cargo_package_name(),
+            || bundler.pre_compile(executable_options),
+        )?;
+    }
+
+    // every executable has its own environment, scratch and cargo target directory,
+    // so the cargo processes compile at the same time without waiting for each other's build lock
+    let mut compilations: Vec<(&ExecutableOptions, Child, Instant)> = vec![];
+    let mut first_error: Option<Error> = None;
+
+    for executable_options in &executables_options {
+        let mut command = bundler.compile_binary_command(executable_options);
+        events::emit(Event::PhaseStarted {
+            phase: Phase::Compile,
+            subject: executable_options.cargo_package_name().to_string(),
+        });
+        match command.spawn() {
+            Ok(child) => compilations.push((executable_options, child, Instant::now())),
+            Err(error) => {
+                first_error = Some(
+                    Error::missing_tool(format!("Failed to start {:?}", &command))
+                        .hint("Make sure that cargo is installed and is in the PATH")
+                        .from(error),
+                );
+                break;
+            }
+        }
+    }
+
+    // if some compilation could not be started the already running ones are stopped,
+    // either way every child is waited for so that none outlives the builder
+    for (executable_options, mut child, started) in compilations {
+        let subject = executable_options.cargo_package_name().to_string();
+        if first_error.is_some() {
+            let _ = child.kill();
+            let _ = child.wait();
+            continue;
+        }
+
+        let status = child.wait();
+        let duration_ms = events::millis(started.elapsed());
+
+        let error = match status {
+            Ok(status) if status.success() => None,
+            Ok(_) => Some(
+                Error::compilation(format!("Failed to compile {}", &subject))
+                    .hint("See the cargo output above for the compilation errors"),
+            ),
+            Err(error) => Some(
+                Error::compilation(format!(
+                    "Failed to wait for the compilation of {}",
+                    &subject
+                ))
+                .from(error),
+            ),
+        };
+
+        if let Some(error) = error {
+            events::emit(Event::PhaseFailed {
+                phase: Phase::Compile,
+                subject,
+                duration_ms,
+                error: error.to_string(),
+            });
+            first_error = Some(error);
+            continue;
+        }
+
+        events::emit(Event::PhaseFinished {
+            phase: Phase::Compile,
+            subject: subject.clone(),
+            duration_ms,
+        });
+        events::emit(Event::ArtifactProduced {
+            phase: Phase::Compile,
+            subject,
+            path: bundle_options.compiled_executable_path(executable_options.executable()),
+        });
+    }
+
+    if let Some(error) = first_error {
+        return error.into();
+    }
+
+    for executable_options in &executables_options {
+        events::phase(
+            Phase::PostCompile,
+            executable_options.cargo_package_name(),
+            || {
+                bundler.post_compile(
+                    bundle_options,
+                    executable_options.executable(),
+                    executable_options,
+                )
+            },
+        )?;
+    }
 
     bundler.compile_third_party_libraries(bundle_options)?;
 
@@ -129,28 +363,79 @@ fn export_build_info(bundler: &dyn Bundler, bundle_options: &BundleOptions) -> R
 
     // export the info about the app and third party libs
     let json = serde_json::to_string_pretty(&bundle_options)?;
-    let file_path = bundler
-        .compilation_location(bundle_options)
-        .join("build-info.json");
+    let file_path = bundle_options.build_info_path();
 
     let existing_content = if file_path.exists() {
         fs::read_to_string(&file_path).ok()
     } else {
         None
     };
-    
-    if existing_content.as_ref() != Some(&json) {
+
+    let changed = existing_content.as_ref() != Some(&json);
+    if changed {
         let mut file = fs::File::create(&file_path)?;
-        write!(&mut file, "{}", json).unwrap();
+        write!(&mut file, "{}", json)?;
     }
-
-    std::env::set_var("APP_BUILD_INFO", file_path.as_os_str());
+    events::emit(Event::BuildInfoExported {
+        path: file_path,
+        changed,
+    });
 
     Ok(())
 }
 
+fn bundle_components(bundler: &dyn Bundler, bundle_options: &BundleOptions) -> Result<()> {
+    events::phase(Phase::Licenses, bundle_options.app_name(), || {
+        licenses::collect_licenses(bundle_options)
+    })?;
+    events::phase(Phase::Bundle, bundle_options.app_name(), || {
+        bundler.bundle(bundle_options)
+    })?;
+    events::phase(Phase::Symbols, bundle_options.app_name(), || {
+        symbols::write_symbols_archive(bundler, bundle_options)
+    })?;
+    events::phase(Phase::Sbom, bundle_options.app_name(), || {
+        sbom::write_sbom(bundler, bundle_options)
+    })?;
+    if bundle_options.reproducible() {
+        reproducible::normalize_bundle(bundle_options)?;
+    }
+    // the manifest lists the files with their final permissions
+    events::phase(Phase::Integrity, bundle_options.app_name(), || {
+        integrity::write_manifest(bundler, bundle_options)
+    })?;
+    events::phase(Phase::Package, bundle_options.app_name(), || {
+        bundler.package(bundle_options)
+    })
+}
+
+/// Write the durations and sizes recorded while building to `build-report.json`
+/// and summarize them, whether the build succeeded or not
+fn write_build_report(bundle_options: &BundleOptions, result: Result<()>) -> Result<()> {
+    let recording = match events::stop_recording() {
+        Some(recording) => recording,
+        None => return result,
+    };
+
+    let report = BuildReport::new(bundle_options, &recording, result.is_ok());
+    let path = bundle_options.build_report_path();
+    let written = report.write(&path);
+    if written.is_ok() {
+        events::emit(Event::BuildReportWritten { path });
+    }
+    if events::message_format() == MessageFormat::Human {
+        report.print_summary();
+    }
+
+    result.and(written)
+}
+
 fn bundler(options: &ResolvedOptions) -> Box<dyn Bundler> {
-    match options.platform() {
+    bundler_for_platform(options.platform())
+}
+
+fn bundler_for_platform(platform: Platform) -> Box<dyn Bundler> {
+    match platform {
         Platform::Mac => Box::new(MacBundler::new()),
         Platform::Windows => Box::new(WindowsBundler::new()),
         Platform::Linux => Box::new(LinuxBundler::new()),
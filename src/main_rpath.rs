@@ -1,7 +1,14 @@
+mod archives;
 mod bundlers;
+mod checksums;
+mod environment;
 mod error;
+mod events;
+mod git_urls;
 mod libraries;
 mod options;
+mod reproducible;
+mod requirements;
 
 pub use error::*;
 pub use options::*;
@@ -0,0 +1,74 @@
+use shared_library_builder::Library;
+
+use crate::bundlers::Bundler;
+use crate::{BundleOptions, Error, Result};
+
+/// Check that everything needed for an offline build is available
+/// and report all missing inputs at once
+pub fn check_inputs(
+    bundler: &dyn Bundler,
+    options: &BundleOptions,
+    compile_executables: bool,
+) -> Result<()> {
+    let mut missing = vec![];
+
+    if compile_executables {
+        if options.vmmaker_vm().is_none() {
+            missing.push("a VM to run VMMaker, pass it with --vmmaker-vm".to_string());
+        }
+        if options.vmmaker_image().is_none() {
+            missing
+                .push("an image to build VMMaker from, pass it with --vmmaker-image".to_string());
+        }
+    }
+
+    for library in options.libraries_without_versions() {
+        missing.push(format!(
+            "a version of {} in the versions file, otherwise its latest release is looked up",
+            library
+        ));
+    }
+
+    for target_options in options.target_variants()? {
+        for library in target_options.libraries() {
+            if target_options.is_built_from_local_path(library.name()) {
+                continue;
+            }
+            let sources_directory = target_options
+                .third_party_libraries_sources_directory()
+                .join(library.name());
+            let has_sources = sources_directory
+                .read_dir()
+                .map(|mut entries| entries.next().is_some())
+                .unwrap_or(false);
+            if has_sources {
+                continue;
+            }
+            let context = bundler.library_compilation_context(library, &target_options)?;
+            let compiled_library = library.exported_library_path(&context);
+            if !compiled_library.exists() {
+                missing.push(format!(
+                    "the sources of {} in {} or the compiled {}",
+                    library.name(),
+                    sources_directory.display(),
+                    compiled_library.display()
+                ));
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Error::invalid_options(format!(
+        "Can not build offline, the following inputs are missing:\n{}",
+        missing
+            .iter()
+            .map(|input| format!("  - {}", input))
+            .collect::<Vec<String>>()
+            .join("\n")
+    ))
+    .hint("Build once with network access to download the sources, or provide the missing inputs")
+    .into()
+}
@@ -0,0 +1,348 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{ArgEnum, Args};
+use serde::{Deserialize, Serialize};
+
+use crate::options::build_options::parse_key_val;
+use crate::{Error, Result, Target};
+
+const DEFAULT_MIN_SDK_VERSION: u32 = 30;
+const DEFAULT_TARGET_SDK_VERSION: u32 = 30;
+const DEFAULT_MAX_SDK_VERSION: u32 = 33;
+const DEFAULT_THEME: &str = "@android:style/Theme.DeviceDefault.NoActionBar.Fullscreen";
+const DEFAULT_PERMISSIONS: [&str; 2] = [
+    "android.permission.INTERNET",
+    "android.permission.ACCESS_NETWORK_STATE",
+];
+
+const LAUNCH_MODES: [&str; 5] = [
+    "standard",
+    "singleTop",
+    "singleTask",
+    "singleInstance",
+    "singleInstancePerTask",
+];
+
+const ORIENTATIONS: [&str; 14] = [
+    "unspecified",
+    "behind",
+    "landscape",
+    "portrait",
+    "reverseLandscape",
+    "reversePortrait",
+    "sensorLandscape",
+    "sensorPortrait",
+    "userLandscape",
+    "userPortrait",
+    "sensor",
+    "fullSensor",
+    "nosensor",
+    "user",
+];
+
+/// A package format of an Android app
+#[derive(ArgEnum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AndroidPackageFormat {
+    /// An installable APK
+    Apk,
+    /// An Android App Bundle for uploading to the Play Store
+    Aab,
+}
+
+/// An intent filter of the main activity in the `action=...;category=...;scheme=...` format.
+/// Every key can be repeated, for example:
+///     action=android.intent.action.VIEW;category=android.intent.category.DEFAULT;scheme=gtoolkit
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AndroidIntentFilter {
+    pub actions: Vec<String>,
+    pub categories: Vec<String>,
+    pub schemes: Vec<String>,
+    pub hosts: Vec<String>,
+    pub mime_types: Vec<String>,
+}
+
+impl FromStr for AndroidIntentFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut filter = Self::default();
+
+        for entry in s.split(';').filter(|entry| !entry.trim().is_empty()) {
+            let (key, value) = entry.split_once('=').ok_or_else(|| {
+                Error::new(format!(
+                    "invalid intent filter entry: no `=` found in `{}`",
+                    entry
+                ))
+            })?;
+            let value = value.trim().to_string();
+
+            match key.trim() {
+                "action" => filter.actions.push(value),
+                "category" => filter.categories.push(value),
+                "scheme" => filter.schemes.push(value),
+                "host" => filter.hosts.push(value),
+                "mime" | "mimeType" => filter.mime_types.push(value),
+                key => {
+                    return Err(Error::new(format!(
+                        "unknown intent filter key `{}`, expected one of action, category, scheme, host, mimeType",
+                        key
+                    )))
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+#[derive(Args, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AndroidOptions {
+    /// Android only. The minimum SDK version required to run the app (default 30)
+    #[clap(long)]
+    android_min_sdk: Option<u32>,
+    /// Android only. The SDK version the app targets (default 30)
+    #[clap(long)]
+    android_target_sdk: Option<u32>,
+    /// Android only. The maximum SDK version the app can run on (default 33)
+    #[clap(long)]
+    android_max_sdk: Option<u32>,
+    /// Android only. Permissions requested by the app. By default, INTERNET and ACCESS_NETWORK_STATE.
+    /// Permissions without a package are prefixed with `android.permission.`
+    #[clap(long, multiple_values = true)]
+    android_permissions: Option<Vec<String>>,
+    /// Android only. The orientation of the main activity, for example `landscape` or `sensor`
+    #[clap(long)]
+    android_orientation: Option<String>,
+    /// Android only. The theme of the application
+    #[clap(long)]
+    android_theme: Option<String>,
+    /// Android only. The launch mode of the main activity, for example `singleTask`
+    #[clap(long)]
+    android_launch_mode: Option<String>,
+    /// Android only. Whether the application is debuggable. By default, only debug builds are debuggable
+    #[clap(long)]
+    android_debuggable: Option<bool>,
+    #[clap(long, value_parser = parse_key_val::<String, String>, multiple_values = true)]
+    /// Android only. Extra meta-data of the application in NAME=value format.
+    android_meta_data: Option<Vec<(String, String)>>,
+    /// Android only. Extra intent filters of the main activity in the
+    /// `action=...;category=...;scheme=...` format. The launcher intent filter is always present.
+    #[clap(long, multiple_values = true)]
+    android_intent_filters: Option<Vec<AndroidIntentFilter>>,
+    /// Android only. Additional Android targets to compile the executable and libraries for.
+    /// All of them are packed into the same APK together with the main --target
+    #[clap(long, arg_enum, ignore_case = true, multiple_values = true)]
+    android_targets: Option<Vec<Target>>,
+    /// Android only. Whether to package an installable APK (default) or an Android App Bundle
+    #[clap(long, arg_enum, ignore_case = true)]
+    android_package_format: Option<AndroidPackageFormat>,
+    /// Android only. A path to the aapt2 executable used to create an Android App Bundle.
+    /// By default, aapt2 is looked up in the PATH
+    #[clap(long, parse(from_os_str))]
+    #[serde(skip)]
+    android_aapt2: Option<PathBuf>,
+}
+
+impl AndroidOptions {
+    /// Default options that only compile for the given additional targets
+    pub fn for_targets(android_targets: Option<Vec<Target>>) -> Self {
+        Self {
+            android_targets,
+            ..Default::default()
+        }
+    }
+
+    pub fn min_sdk_version(&self) -> u32 {
+        self.android_min_sdk.unwrap_or(DEFAULT_MIN_SDK_VERSION)
+    }
+
+    pub fn target_sdk_version(&self) -> u32 {
+        self.android_target_sdk
+            .unwrap_or_else(|| DEFAULT_TARGET_SDK_VERSION.max(self.min_sdk_version()))
+    }
+
+    pub fn max_sdk_version(&self) -> u32 {
+        self.android_max_sdk
+            .unwrap_or_else(|| DEFAULT_MAX_SDK_VERSION.max(self.target_sdk_version()))
+    }
+
+    pub fn permissions(&self) -> Vec<String> {
+        self.android_permissions.as_ref().map_or_else(
+            || {
+                DEFAULT_PERMISSIONS
+                    .iter()
+                    .map(|permission| permission.to_string())
+                    .collect()
+            },
+            |permissions| {
+                permissions
+                    .iter()
+                    .map(|permission| {
+                        if permission.contains('.') {
+                            permission.clone()
+                        } else {
+                            format!("android.permission.{}", permission)
+                        }
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    pub fn orientation(&self) -> Option<&str> {
+        self.android_orientation.as_deref()
+    }
+
+    pub fn theme(&self) -> &str {
+        self.android_theme.as_deref().unwrap_or(DEFAULT_THEME)
+    }
+
+    pub fn launch_mode(&self) -> Option<&str> {
+        self.android_launch_mode.as_deref()
+    }
+
+    pub fn debuggable(&self, release: bool) -> bool {
+        self.android_debuggable.unwrap_or(!release)
+    }
+
+    pub fn meta_data(&self) -> &[(String, String)] {
+        self.android_meta_data
+            .as_ref()
+            .map(|meta_data| meta_data.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn intent_filters(&self) -> &[AndroidIntentFilter] {
+        self.android_intent_filters
+            .as_ref()
+            .map(|filters| filters.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Additional Android targets besides the main one
+    pub fn additional_targets(&self) -> &[Target] {
+        self.android_targets
+            .as_ref()
+            .map(|targets| targets.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn package_format(&self) -> AndroidPackageFormat {
+        self.android_package_format
+            .unwrap_or(AndroidPackageFormat::Apk)
+    }
+
+    pub fn aapt2(&self) -> Option<PathBuf> {
+        self.android_aapt2
+            .clone()
+            .or_else(|| which::which("aapt2").ok())
+    }
+
+    /// Check that the manifest options make sense together, so that
+    /// mistakes are reported before anything is compiled or packaged
+    pub fn validate(&self) -> Result<()> {
+        let mut problems: Vec<String> = vec![];
+
+        if self.min_sdk_version() > self.target_sdk_version() {
+            problems.push(format!(
+                "min sdk version ({}) must not be greater than target sdk version ({})",
+                self.min_sdk_version(),
+                self.target_sdk_version()
+            ));
+        }
+
+        if self.target_sdk_version() > self.max_sdk_version() {
+            problems.push(format!(
+                "target sdk version ({}) must not be greater than max sdk version ({})",
+                self.target_sdk_version(),
+                self.max_sdk_version()
+            ));
+        }
+
+        if let Some(launch_mode) = self.launch_mode() {
+            if !LAUNCH_MODES.contains(&launch_mode) {
+                problems.push(format!(
+                    "unknown launch mode `{}`, expected one of {}",
+                    launch_mode,
+                    LAUNCH_MODES.join(", ")
+                ));
+            }
+        }
+
+        if let Some(orientation) = self.orientation() {
+            if !ORIENTATIONS.contains(&orientation) {
+                problems.push(format!(
+                    "unknown orientation `{}`, expected one of {}",
+                    orientation,
+                    ORIENTATIONS.join(", ")
+                ));
+            }
+        }
+
+        let permissions = self.permissions();
+        for (index, permission) in permissions.iter().enumerate() {
+            if permissions[..index].contains(permission) {
+                problems.push(format!("permission `{}` is requested twice", permission));
+            }
+        }
+
+        let meta_data = self.meta_data();
+        for (index, (name, _)) in meta_data.iter().enumerate() {
+            if name == "android.app.lib_name" {
+                problems.push(
+                    "meta-data `android.app.lib_name` is derived from the executable name and can not be overridden"
+                        .to_string(),
+                );
+            }
+            if meta_data[..index].iter().any(|(other, _)| other == name) {
+                problems.push(format!("meta-data `{}` is defined twice", name));
+            }
+        }
+
+        for target in self.additional_targets() {
+            if !target.platform().is_android() {
+                problems.push(format!("{} is not an Android target", target.to_string()));
+            }
+        }
+
+        for filter in self.intent_filters() {
+            if filter.actions.is_empty() {
+                problems.push(format!(
+                    "intent filter {:?} must have at least one action",
+                    filter
+                ));
+            }
+            if !filter.hosts.is_empty() && filter.schemes.is_empty() {
+                problems.push(format!(
+                    "intent filter {:?} has a host but no scheme",
+                    filter
+                ));
+            }
+        }
+
+        if self.package_format() == AndroidPackageFormat::Aab {
+            match self.aapt2() {
+                None => problems.push(
+                    "aapt2 is required to create an Android App Bundle, add it to the PATH or pass --android-aapt2"
+                        .to_string(),
+                ),
+                Some(aapt2) => {
+                    if !Path::new(&aapt2).exists() {
+                        problems.push(format!("aapt2 {} does not exist", aapt2.display()));
+                    }
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Error::invalid_options(format!(
+                "Invalid Android manifest options:\n\t{}",
+                problems.join("\n\t")
+            ))
+            .into()
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::fs;
@@ -9,8 +10,14 @@ use clap::{ArgEnum, Args};
 use rustc_version::version_meta;
 use serde::{Deserialize, Serialize};
 
+use crate::events::MessageFormat;
+use crate::git_urls;
+use crate::git_urls::UrlRewrite;
 use crate::libraries::{ThirdPartyLibrary, VersionedThirdPartyLibraries};
-use crate::Executable;
+use crate::{AndroidOptions, Context, Error, Executable};
+
+/// A standard environment variable with the timestamp to use in reproducible builds
+pub const SOURCE_DATE_EPOCH_VARIABLE: &str = "SOURCE_DATE_EPOCH";
 
 #[derive(ArgEnum, Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(try_from = "String", into = "String")]
@@ -30,6 +37,10 @@ pub enum Target {
     AArch64UnknownlinuxGNU,
     #[clap(name = "aarch64-linux-android")]
     AArch64LinuxAndroid,
+    #[clap(name = "armv7-linux-androideabi")]
+    ArmV7LinuxAndroidEabi,
+    #[clap(name = "x86_64-linux-android")]
+    X8664LinuxAndroid,
 }
 
 impl Target {
@@ -46,6 +57,8 @@ impl Target {
             Target::X8664UnknownlinuxGNU => Platform::Linux,
             Target::AArch64UnknownlinuxGNU => Platform::Linux,
             Target::AArch64LinuxAndroid => Platform::Android,
+            Target::ArmV7LinuxAndroidEabi => Platform::Android,
+            Target::X8664LinuxAndroid => Platform::Android,
         }
     }
 
@@ -128,6 +141,10 @@ pub struct BuilderOptions {
     /// A level of verbosity, and can be used multiple times
     #[clap(short, long, parse(from_occurrences))]
     verbose: i32,
+    /// How to report the progress of the build: human-readable messages (default) or one JSON event per line
+    #[clap(long, arg_enum, ignore_case = true)]
+    #[serde(skip)]
+    message_format: Option<MessageFormat>,
     /// Build in release mode
     #[clap(long, conflicts_with = "debug")]
     release: bool,
@@ -137,6 +154,24 @@ pub struct BuilderOptions {
     /// Strip debug symbols from the bundle
     #[clap(long)]
     strip_debug_symbols: bool,
+    /// Collect the debug symbols into a separate `<app>-<version>-<target>-symbols.zip` archive
+    /// with an index of the binaries they belong to, instead of placing them into the bundle
+    #[clap(long, conflicts_with = "strip_debug_symbols")]
+    symbols_archive: bool,
+    /// Never access the network. Library versions must be given in the versions file
+    /// and their sources or compiled libraries must already exist
+    #[clap(long)]
+    offline: bool,
+    /// Fail the bundling when the license of a third party library or crate can not be found
+    #[clap(long)]
+    strict_licenses: bool,
+    /// Build a bundle that is identical when built again from the same sources. Timestamps are taken
+    /// from SOURCE_DATE_EPOCH or the last commit of the workspace, and local paths are removed from the binaries
+    #[clap(long)]
+    reproducible: bool,
+    /// Remove the previous bundle and copy everything again instead of only updating the changed files
+    #[clap(long)]
+    fresh: bool,
     #[clap(long, arg_enum, ignore_case = true)]
     /// To cross-compile and bundle an application for another OS
     target: Option<Target>,
@@ -179,6 +214,15 @@ pub struct BuilderOptions {
     #[clap(long, value_parser = parse_key_val::<ThirdPartyLibrary, String>, multiple_values = true)]
     /// Override a library version specified in LIBRARY=version format. Multiple libraries are allowed.
     override_library_version: Option<Vec<(ThirdPartyLibrary, String)>>,
+    #[clap(long, value_parser = parse_key_val::<String, PathBuf>, multiple_values = true)]
+    /// Build a library from a local checkout instead of its release, specified in LIBRARY=path format.
+    /// Multiple libraries are allowed. Bundles built with local libraries are marked as non-release.
+    library_path: Option<Vec<(String, PathBuf)>>,
+    /// A directory with bare git repositories of libraries in <owner>/<repo>.git format.
+    /// They are cloned instead of the same repositories on GitHub
+    #[clap(long, parse(from_os_str))]
+    #[serde(skip)]
+    mirror_dir: Option<PathBuf>,
     /// Use a specific VM to run a VMMaker, must be a path to the executable.
     /// When specified, the build will not attempt to download a VM.
     /// By default, it is assumed that the VM is a GToolkit VM.
@@ -193,11 +237,24 @@ pub struct BuilderOptions {
     #[serde(skip)]
     vmmaker_image: Option<PathBuf>,
     /// Pick which executables to compile. This allows users to create an app without CLI or GUI interface.
+    /// The executables are compiled at the same time, each into its own cargo target directory
     #[clap(long, arg_enum, ignore_case = true, multiple_values = true)]
     executables: Option<Vec<Executable>>,
     /// Build with specific features selected
     #[clap(long)]
     features: Option<Vec<String>>,
+    /// A Smalltalk .image to place in the resources directory of the bundle.
+    /// The .changes and .sources files next to it are bundled too.
+    #[clap(long, parse(from_os_str))]
+    #[serde(skip)]
+    image: Option<PathBuf>,
+    /// Extra files or directories (for example gt-extra) to place in the resources directory of the bundle
+    #[clap(long, parse(from_os_str), multiple_values = true)]
+    #[serde(skip)]
+    resources: Option<Vec<PathBuf>>,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    android: AndroidOptions,
 }
 
 impl BuilderOptions {
@@ -224,14 +281,8 @@ impl BuilderOptions {
         self.vmmaker_image.as_ref().map(|dir| dir.as_path())
     }
 
-    pub fn workspace_directory(&self) -> Option<PathBuf> {
-        let error_message = "Failed to locate Cargo.toml of the VM project";
-
-        which::which("cargo").expect(&format!(
-            "{}: `cargo` must be installed and be in the PATH",
-            error_message
-        ));
-
+    /// The directory of the VM workspace the builder runs in, or None when it runs outside of a cargo workspace
+    pub fn workspace_directory(&self) -> crate::Result<Option<PathBuf>> {
         let mut command = Command::new("cargo");
         command
             .arg("locate-project")
@@ -239,27 +290,22 @@ impl BuilderOptions {
             .arg("--message-format")
             .arg("plain");
 
-        let output = command
-            .output()
-            .map_err(|error| format!("{}: {:?} panicked due to {}", error_message, command, error))
-            .unwrap();
-
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let output = command.output().context(|| {
+            Error::missing_tool(format!(
+                "Failed to locate Cargo.toml of the VM project, could not start {:?}",
+                &command
+            ))
+            .hint("Make sure that cargo is installed and is in the PATH")
+        })?;
 
         if !output.status.success() {
-            panic!(
-                "{}: {:?} didn't finish successfully.\n\tExit code: {}\n\tStdout: {}\n\tStderr: {}",
-                error_message,
-                command,
-                output.status.to_string(),
-                &stdout,
-                &stderr
-            );
+            return Ok(None);
         }
 
-        let workspace_toml_path = PathBuf::new().join(stdout);
-        Some(workspace_toml_path.parent().unwrap().to_path_buf())
+        let workspace_toml_path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        Ok(workspace_toml_path
+            .parent()
+            .map(|directory| directory.to_path_buf()))
     }
 
     pub fn app_name(&self) -> Option<&str> {
@@ -284,6 +330,10 @@ impl BuilderOptions {
         self.verbose
     }
 
+    pub fn message_format(&self) -> MessageFormat {
+        self.message_format.unwrap_or(MessageFormat::Human)
+    }
+
     pub fn release(&self) -> bool {
         if self.release {
             true
@@ -298,6 +348,102 @@ impl BuilderOptions {
         !self.strip_debug_symbols
     }
 
+    pub fn symbols_archive(&self) -> bool {
+        self.symbols_archive
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn strict_licenses(&self) -> bool {
+        self.strict_licenses
+    }
+
+    pub fn reproducible(&self) -> bool {
+        self.reproducible
+    }
+
+    pub fn fresh(&self) -> bool {
+        self.fresh
+    }
+
+    /// Seconds since the unix epoch used instead of the current time when building reproducibly:
+    /// `SOURCE_DATE_EPOCH` if set, otherwise the time of the last commit of the given workspace.
+    /// Fails when building reproducibly without either of them
+    pub fn source_date_epoch(
+        &self,
+        workspace_directory: Option<&Path>,
+    ) -> crate::Result<Option<i64>> {
+        if !self.reproducible {
+            return Ok(None);
+        }
+        if let Ok(epoch) = std::env::var(SOURCE_DATE_EPOCH_VARIABLE) {
+            return epoch.trim().parse::<i64>().ok().map(Some).context(|| {
+                Error::invalid_options(format!(
+                    "{} must be a number of seconds since the unix epoch, not {}",
+                    SOURCE_DATE_EPOCH_VARIABLE, &epoch
+                ))
+            });
+        }
+        let last_commit = workspace_directory.and_then(|workspace| {
+            Command::new("git")
+                .current_dir(workspace)
+                .args(["log", "-1", "--format=%ct"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| {
+                    String::from_utf8_lossy(&output.stdout)
+                        .trim()
+                        .parse::<i64>()
+                        .ok()
+                })
+        });
+        last_commit.map(Some).context(|| {
+            Error::invalid_options(
+                "--reproducible needs the time of the last commit of the workspace, but it is not known",
+            )
+            .hint(format!(
+                "Build from a git checkout with at least one commit, or set {}",
+                SOURCE_DATE_EPOCH_VARIABLE
+            ))
+        })
+    }
+
+    /// The same options building reproducibly into another target directory with the default bundle location,
+    /// to compare the bundles of independent builds
+    pub fn for_reproducibility_check(&self, target_dir: PathBuf) -> Self {
+        let mut options = self.clone();
+        options.reproducible = true;
+        options.fresh = true;
+        options.target_dir = Some(target_dir);
+        options.bundle_dir = None;
+        options
+    }
+
+    /// Options of a build that only tell where its artifacts are, for commands that do not build
+    pub fn for_locations(
+        target: Option<Target>,
+        target_dir: Option<PathBuf>,
+        bundle_dir: Option<PathBuf>,
+        app_name: Option<String>,
+        version: Option<String>,
+        release: bool,
+        android: AndroidOptions,
+    ) -> Self {
+        Self {
+            release,
+            target,
+            target_dir,
+            bundle_dir,
+            app_name,
+            version,
+            android,
+            ..Default::default()
+        }
+    }
+
     pub fn icons(&self) -> Option<&Vec<String>> {
         self.icons.as_ref()
     }
@@ -306,15 +452,25 @@ impl BuilderOptions {
         self.libraries.as_ref()
     }
 
-    pub fn libraries_versions(&self) -> VersionedThirdPartyLibraries {
+    /// Read the versions file, with the versions overridden on the command line
+    pub fn libraries_versions(&self) -> crate::Result<VersionedThirdPartyLibraries> {
         let mut versioned_libraries = match &self.libraries_versions {
             None => VersionedThirdPartyLibraries::new(),
-            Some(versions_file) => serde_json::from_str(
-                fs::read_to_string(versions_file)
-                    .expect("Failed to read versions file")
-                    .as_str(),
-            )
-            .expect("Failed to deserialized versions"),
+            Some(versions_file) => {
+                let versions = fs::read_to_string(versions_file).context(|| {
+                    Error::invalid_options(format!(
+                        "Failed to read the versions file {}",
+                        versions_file.display()
+                    ))
+                })?;
+                serde_json::from_str(&versions).context(|| {
+                    Error::invalid_options(format!(
+                        "The versions file {} is malformed",
+                        versions_file.display()
+                    ))
+                    .hint("See the README for the format of the versions file")
+                })?
+            }
         };
 
         if let Some(ref overridden_versions) = self.override_library_version {
@@ -323,7 +479,52 @@ impl BuilderOptions {
             }
         }
 
-        versioned_libraries
+        Ok(versioned_libraries)
+    }
+
+    /// Libraries built from local checkouts, given in the versions file or on the command line.
+    /// Paths in the versions file are relative to that file
+    pub fn library_paths(
+        &self,
+        libraries_versions: &VersionedThirdPartyLibraries,
+    ) -> BTreeMap<String, PathBuf> {
+        let versions_directory = self
+            .libraries_versions
+            .as_ref()
+            .and_then(|versions_file| versions_file.parent())
+            .map(|directory| directory.to_path_buf())
+            .unwrap_or_default();
+
+        let mut library_paths: BTreeMap<String, PathBuf> = libraries_versions
+            .library_paths()
+            .iter()
+            .map(|(library, path)| (library.clone(), versions_directory.join(path)))
+            .collect();
+
+        if let Some(ref paths) = self.library_path {
+            for (library, path) in paths {
+                library_paths.insert(library.clone(), path.clone());
+            }
+        }
+
+        library_paths
+    }
+
+    /// Git url rewrite rules from the versions file, the environment and the mirror directory.
+    /// Git picks the rule with the longest matching prefix
+    pub fn git_url_rewrites(
+        &self,
+        libraries_versions: &VersionedThirdPartyLibraries,
+    ) -> crate::Result<Vec<UrlRewrite>> {
+        let mut rewrites = libraries_versions.url_rewrites().to_vec();
+        for rewrite in git_urls::rewrites_from_environment()? {
+            rewrites.retain(|each| each.instead_of != rewrite.instead_of);
+            rewrites.push(rewrite);
+        }
+        if let Some(ref mirror_dir) = self.mirror_dir {
+            rewrites.extend(git_urls::mirror_rewrites(mirror_dir)?);
+        }
+        Ok(rewrites)
     }
 
     pub fn executables(&self) -> Option<&Vec<Executable>> {
@@ -336,10 +537,25 @@ impl BuilderOptions {
             .map(|features| features.as_slice())
             .unwrap_or(&[])
     }
+
+    pub fn image(&self) -> Option<&Path> {
+        self.image.as_ref().map(|image| image.as_path())
+    }
+
+    pub fn resources(&self) -> &[PathBuf] {
+        self.resources
+            .as_ref()
+            .map(|resources| resources.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn android(&self) -> &AndroidOptions {
+        &self.android
+    }
 }
 
 /// Parse a single key-value pair
-fn parse_key_val<T, U>(
+pub(crate) fn parse_key_val<T, U>(
     s: &str,
 ) -> Result<(T, U), Box<dyn std::error::Error + Send + Sync + 'static>>
 where
@@ -1,11 +1,17 @@
-use crate::{Platform, ResolvedOptions, Target};
+use crate::git_urls::UrlRewrite;
+use crate::libraries::{LicensePolicy, PinnedVersion, VersionedThirdPartyLibraries};
+use crate::{AndroidOptions, Error, Platform, ResolvedOptions, Result, Target};
 use clap::ArgEnum;
 use feenk_releaser::Version;
 use serde::{Deserialize, Serialize};
 use shared_library_builder::Library;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// A name of the json file that describes the build, placed next to the compiled executables and into the bundle
+pub const BUILD_INFO_FILE: &str = "build-info.json";
+
 #[derive(ArgEnum, Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
 pub enum Executable {
     App,
@@ -84,6 +90,20 @@ impl BundleOptions {
         Self { options }
     }
 
+    /// Options for every target compiled within one build: the main target
+    /// followed by the additional Android targets, if any
+    pub fn target_variants(&self) -> Result<Vec<BundleOptions>> {
+        let mut variants = vec![self.clone()];
+        if self.platform().is_android() {
+            for target in self.android().additional_targets() {
+                if target != self.target() {
+                    variants.push(Self::new(self.options.with_target(*target)?));
+                }
+            }
+        }
+        Ok(variants)
+    }
+
     pub fn executables(&self) -> &Vec<Executable> {
         self.options.executables()
     }
@@ -116,6 +136,61 @@ impl BundleOptions {
         self.options.include_debug_symbols()
     }
 
+    pub fn symbols_archive(&self) -> bool {
+        self.options.symbols_archive()
+    }
+
+    pub fn offline(&self) -> bool {
+        self.options.offline()
+    }
+
+    pub fn strict_licenses(&self) -> bool {
+        self.options.strict_licenses()
+    }
+
+    pub fn reproducible(&self) -> bool {
+        self.options.reproducible()
+    }
+
+    pub fn fresh(&self) -> bool {
+        self.options.fresh()
+    }
+
+    pub fn source_date_epoch(&self) -> Option<i64> {
+        self.options.source_date_epoch()
+    }
+
+    pub fn git_url_rewrites(&self) -> &[UrlRewrite] {
+        self.options.git_url_rewrites()
+    }
+
+    pub fn license_policies(&self) -> Vec<LicensePolicy> {
+        self.options.license_policies()
+    }
+
+    pub fn library_versions(&self) -> &BTreeMap<String, Option<String>> {
+        self.options.library_versions()
+    }
+
+    pub fn pinned_library(&self, library_name: &str) -> Option<&PinnedVersion> {
+        self.options.pinned_library(library_name)
+    }
+
+    pub fn is_built_from_local_path(&self, library_name: &str) -> bool {
+        self.options.is_built_from_local_path(library_name)
+    }
+
+    pub fn libraries_without_versions(&self) -> Vec<String> {
+        self.options.libraries_without_versions()
+    }
+
+    pub fn pinned_libraries_versions(
+        &self,
+        version_of: impl Fn(&str) -> Option<String>,
+    ) -> Result<VersionedThirdPartyLibraries> {
+        self.options.pinned_libraries_versions(version_of)
+    }
+
     pub fn icons(&self) -> &Vec<PathBuf> {
         self.options.icons()
     }
@@ -136,6 +211,10 @@ impl BundleOptions {
         self.options.version()
     }
 
+    pub fn workspace_directory(&self) -> Option<PathBuf> {
+        self.options.workspace_directory()
+    }
+
     pub fn vmmaker_vm(&self) -> Option<&Path> {
         self.options.vmmaker_vm()
     }
@@ -148,12 +227,103 @@ impl BundleOptions {
         self.options.libraries()
     }
 
+    pub fn local_library_path(&self, library_name: &str) -> Option<&Path> {
+        self.options.local_library_path(library_name)
+    }
+
+    /// All third party libraries known to the builder, whether selected or not
+    pub fn known_libraries(&self) -> Result<Vec<Box<dyn Library>>> {
+        self.options.known_libraries()
+    }
+
+    /// Names of all known libraries by the names they are selected with
+    pub fn known_library_names(&self) -> Result<BTreeMap<String, String>> {
+        self.options.known_library_names()
+    }
+
     pub fn app_name(&self) -> &str {
         self.options.app_name()
     }
 
+    pub fn executable_name(&self) -> &str {
+        self.options.executable_name()
+    }
+
+    pub fn android(&self) -> &AndroidOptions {
+        self.options.android()
+    }
+
+    /// The Smalltalk image together with its .changes and .sources files
+    pub fn image_files(&self) -> Result<Vec<PathBuf>> {
+        let image = match self.options.image() {
+            None => return Ok(vec![]),
+            Some(image) => image,
+        };
+
+        if !image.is_file() {
+            return Error::invalid_options(format!("Image {} does not exist", image.display()))
+                .hint("Pass the path to a .image file with --image")
+                .into();
+        }
+
+        let mut files = vec![image.to_path_buf()];
+
+        let changes = image.with_extension("changes");
+        if changes.is_file() {
+            files.push(changes);
+        }
+
+        let image_directory = image
+            .parent()
+            .map(|directory| directory.to_path_buf())
+            .unwrap_or_default();
+        let image_directory = if image_directory.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            image_directory
+        };
+
+        let mut sources = image_directory
+            .read_dir()?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<PathBuf>>>()?
+            .into_iter()
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |extension| extension == "sources")
+            })
+            .collect::<Vec<PathBuf>>();
+        sources.sort();
+        files.extend(sources);
+
+        Ok(files)
+    }
+
+    /// Extra files and directories to be placed in the resources directory of the bundle
+    pub fn resources(&self) -> &[PathBuf] {
+        self.options.resources()
+    }
+
     pub fn compilation_location(&self) -> PathBuf {
-        let mut location = self.target_dir().clone();
+        self.profile_location(self.target_dir())
+    }
+
+    /// Every executable is compiled into its own cargo target directory, as cargo lets only one build
+    /// at a time use a target directory and the executables are compiled at the same time
+    pub fn executable_target_dir(&self, executable: &Executable) -> PathBuf {
+        self.target_dir()
+            .join("executables")
+            .join(executable.cargo_package_name())
+    }
+
+    /// Where cargo places the executable for the target and profile within its target directory
+    pub fn executable_compilation_location(&self, executable: &Executable) -> PathBuf {
+        self.profile_location(&self.executable_target_dir(executable))
+    }
+
+    fn profile_location(&self, target_dir: &Path) -> PathBuf {
+        let mut location = target_dir.to_path_buf();
         if !self.target().is_current() {
             location = location.join(self.target().to_string());
         }
@@ -181,10 +351,48 @@ impl BundleOptions {
     /// A path to the compiled binary after running cargo command.
     /// It is the same as defined in the [[bin]] section of the Cargo.toml
     pub fn compiled_executable_path(&self, executable: &Executable) -> PathBuf {
-        self.compilation_location()
+        self.executable_compilation_location(executable)
             .join(executable.compiled_name(&self.options))
     }
 
+    /// A path to the json file that describes the build, exported before compiling the executables
+    pub fn build_info_path(&self) -> PathBuf {
+        self.compilation_location().join(BUILD_INFO_FILE)
+    }
+
+    /// A file with the durations of the build phases and the sizes of the produced files
+    pub fn build_report_path(&self) -> PathBuf {
+        self.compilation_location().join("build-report.json")
+    }
+
+    /// A directory with the collected licenses of third party libraries and crates,
+    /// copied into the resources of the bundle
+    pub fn licenses_directory(&self) -> PathBuf {
+        self.compilation_location().join("licenses")
+    }
+
+    /// A directory that mirrors the bundle with the debug symbols when they are archived separately
+    pub fn symbols_directory(&self) -> PathBuf {
+        self.compilation_location().join("symbols")
+    }
+
+    /// An archive with the debug symbols and their index, next to the build info
+    pub fn symbols_archive_path(&self) -> PathBuf {
+        self.compilation_location().join(format!(
+            "{}-{}-{}-symbols.zip",
+            self.app_name(),
+            self.version(),
+            self.target().to_string()
+        ))
+    }
+
+    /// A directory for intermediate files produced while compiling a given executable
+    pub fn scratch_directory(&self, executable: &Executable) -> PathBuf {
+        self.compilation_location()
+            .join("scratch")
+            .join(executable.cargo_package_name())
+    }
+
     pub fn default_bundle_location(&self) -> PathBuf {
         self.target_dir()
             .join(self.target().to_string())
@@ -195,7 +403,7 @@ impl BundleOptions {
     pub fn third_party_libraries_sources_directory(&self) -> PathBuf {
         self.options
             .workspace_directory()
-            .unwrap_or(std::env::current_dir().unwrap())
+            .unwrap_or_default()
             .join("libs")
     }
 
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::{AndroidOptions, BuilderOptions, Target};
+
+/// Cleaning only needs to know where the builder put its artifacts,
+/// so it takes the options that affect the locations and not all of the build options
+#[derive(Args, Clone, Debug, Default)]
+pub struct CleanOptions {
+    /// Clean the release build
+    #[clap(long, conflicts_with = "debug")]
+    release: bool,
+    /// Clean the debug build
+    #[clap(long, conflicts_with = "release")]
+    debug: bool,
+    /// The target of the build to clean
+    #[clap(long, arg_enum, ignore_case = true)]
+    target: Option<Target>,
+    /// Android only. Additional Android targets the build was compiled for
+    #[clap(long, arg_enum, ignore_case = true, multiple_values = true)]
+    android_targets: Option<Vec<Target>>,
+    /// Path to directory which cargo used as the root of build directory
+    #[clap(long, parse(from_os_str))]
+    target_dir: Option<PathBuf>,
+    /// The output location of the bundle, if it was given when bundling
+    #[clap(long, parse(from_os_str))]
+    bundle_dir: Option<PathBuf>,
+    /// The name of the app, if it was given when bundling
+    #[clap(long)]
+    app_name: Option<String>,
+    /// The version of the app, if it was given when bundling
+    #[clap(long)]
+    version: Option<String>,
+    /// Remove the bundled app and the archives created when bundling it
+    #[clap(long)]
+    bundle: bool,
+    /// Remove the builds of third party libraries, only of the given ones if any, for example `skia`
+    #[clap(long, multiple_values = true, min_values = 0)]
+    libraries: Option<Vec<String>>,
+    /// Remove the downloaded sources of third party libraries, only of the given ones if any
+    #[clap(long, multiple_values = true, min_values = 0)]
+    library_sources: Option<Vec<String>>,
+    /// Remove the compiled executables and their intermediate files
+    #[clap(long)]
+    executables: bool,
+    /// Remove everything the builder produces: bundle, executables, libraries and their sources
+    #[clap(long)]
+    all: bool,
+    /// Only print what would be removed
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl CleanOptions {
+    /// Builder options that locate the artifacts the same way as the build that produced them
+    pub fn builder_options(&self) -> BuilderOptions {
+        BuilderOptions::for_locations(
+            self.target,
+            self.target_dir.clone(),
+            self.bundle_dir.clone(),
+            self.app_name.clone(),
+            self.version.clone(),
+            self.release,
+            AndroidOptions::for_targets(self.android_targets.clone()),
+        )
+    }
+
+    pub fn clean_bundle(&self) -> bool {
+        self.all || self.bundle
+    }
+
+    /// Libraries whose builds to remove, empty to remove all of them
+    pub fn clean_libraries(&self) -> Option<&[String]> {
+        if self.all {
+            return Some(&[]);
+        }
+        self.libraries.as_deref()
+    }
+
+    /// Libraries whose sources to remove, empty to remove all of them
+    pub fn clean_library_sources(&self) -> Option<&[String]> {
+        if self.all {
+            return Some(&[]);
+        }
+        self.library_sources.as_deref()
+    }
+
+    pub fn clean_executables(&self) -> bool {
+        self.all || self.executables
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
@@ -0,0 +1,34 @@
+use clap::Args;
+
+use crate::libraries::ThirdPartyLibrary;
+use crate::Target;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct DoctorOptions {
+    /// A target to check the toolchain for. By default, the current platform
+    #[clap(long, arg_enum, ignore_case = true)]
+    target: Option<Target>,
+    /// Third party libraries to check the requirements of
+    #[clap(long, arg_enum, ignore_case = true, multiple_values = true)]
+    libraries: Option<Vec<ThirdPartyLibrary>>,
+    /// Print the results in json format
+    #[clap(long)]
+    json: bool,
+}
+
+impl DoctorOptions {
+    pub fn target(&self) -> Target {
+        self.target.unwrap_or_else(Target::for_current_platform)
+    }
+
+    pub fn libraries(&self) -> &[ThirdPartyLibrary] {
+        self.libraries
+            .as_ref()
+            .map(|libraries| libraries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
@@ -1,5 +1,7 @@
+use crate::environment::tool_environment;
 use crate::{BundleOptions, Executable, Target};
 use feenk_releaser::Version;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -49,8 +51,9 @@ impl<'bundle_options> ExecutableOptions<'bundle_options> {
         self.options.target()
     }
 
-    pub fn target_dir(&self) -> &PathBuf {
-        self.options.target_dir()
+    /// The cargo target directory of this executable
+    pub fn target_dir(&self) -> PathBuf {
+        self.options.executable_target_dir(self.executable())
     }
 
     pub fn release(&self) -> bool {
@@ -80,4 +83,54 @@ impl<'bundle_options> ExecutableOptions<'bundle_options> {
     pub fn compiled_executable_name(&self) -> String {
         self.options.compiled_executable_name(self.executable())
     }
+
+    /// A directory private to this executable where bundlers can put intermediate files
+    /// needed during compilation, so that several executables can be compiled at the same time
+    pub fn scratch_directory(&self) -> PathBuf {
+        self.options.scratch_directory(self.executable())
+    }
+
+    /// Environment variables that configure the compilation of the vm-client.
+    /// They are passed explicitly to the cargo command instead of being set in the builder's process
+    pub fn cargo_environment(&self) -> Vec<(String, OsString)> {
+        let mut environment = vec![
+            (
+                "CARGO_TARGET_DIR".to_string(),
+                self.target_dir().into_os_string(),
+            ),
+            (
+                "VM_CLIENT_VERSION".to_string(),
+                OsString::from(self.version().to_string()),
+            ),
+            (
+                "APP_BUILD_INFO".to_string(),
+                self.options.build_info_path().into_os_string(),
+            ),
+        ];
+
+        if !self.target().is_current() {
+            environment.push((
+                "CARGO_TARGET".to_string(),
+                OsString::from(self.target().to_string()),
+            ));
+        }
+
+        if let Some(vmmaker_vm) = self.vmmaker_vm() {
+            environment.push((
+                "VM_CLIENT_VMMAKER".to_string(),
+                vmmaker_vm.as_os_str().to_os_string(),
+            ));
+        }
+
+        if let Some(vmmaker_image) = self.vmmaker_image() {
+            environment.push((
+                "VM_CLIENT_VMMAKER_IMAGE".to_string(),
+                vmmaker_image.as_os_str().to_os_string(),
+            ));
+        }
+
+        environment.extend(tool_environment(self.options));
+
+        environment
+    }
 }
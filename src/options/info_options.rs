@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+#[derive(Args, Clone, Debug)]
+pub struct InfoOptions {
+    /// A bundle, its archive (.zip, .apk, .aab, .tar.gz or .tar.xz) or a build-info.json
+    #[clap(parse(from_os_str))]
+    bundle: PathBuf,
+    /// Print the complete build info as json
+    #[clap(long)]
+    json: bool,
+}
+
+impl InfoOptions {
+    pub fn bundle(&self) -> &Path {
+        self.bundle.as_path()
+    }
+
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
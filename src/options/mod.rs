@@ -1,9 +1,23 @@
+mod android_options;
 mod build_options;
 mod bundle_options;
+mod clean_options;
+mod doctor_options;
 mod executable_options;
+mod info_options;
+mod report_options;
 mod resolved_options;
+mod vendor_options;
+mod verify_integrity_options;
 
-pub use build_options::{BuilderOptions, Platform, Target};
-pub use bundle_options::{BundleOptions, Executable};
+pub use android_options::{AndroidIntentFilter, AndroidOptions, AndroidPackageFormat};
+pub use build_options::{BuilderOptions, Platform, Target, SOURCE_DATE_EPOCH_VARIABLE};
+pub use bundle_options::{BundleOptions, Executable, BUILD_INFO_FILE};
+pub use clean_options::CleanOptions;
+pub use doctor_options::DoctorOptions;
 pub use executable_options::ExecutableOptions;
+pub use info_options::InfoOptions;
+pub use report_options::{ReportCommand, ReportDiffOptions};
 pub use resolved_options::ResolvedOptions;
+pub use vendor_options::VendorOptions;
+pub use verify_integrity_options::VerifyIntegrityOptions;
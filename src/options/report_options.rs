@@ -0,0 +1,29 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ReportCommand {
+    /// Compare the timings and file sizes of two build reports
+    Diff(ReportDiffOptions),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ReportDiffOptions {
+    /// A build-report.json of an earlier build
+    #[clap(parse(from_os_str))]
+    old_report: PathBuf,
+    /// A build-report.json of a later build
+    #[clap(parse(from_os_str))]
+    new_report: PathBuf,
+}
+
+impl ReportDiffOptions {
+    pub fn old_report(&self) -> &Path {
+        self.old_report.as_path()
+    }
+
+    pub fn new_report(&self) -> &Path {
+        self.new_report.as_path()
+    }
+}
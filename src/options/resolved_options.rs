@@ -1,15 +1,24 @@
-use crate::{BuilderOptions, Executable, Platform, Target};
-use chrono::Utc;
+use crate::git_urls::UrlRewrite;
+use crate::libraries::{
+    test_library, CustomLibraryLocation, LicensePolicy, PinnedVersion, ThirdPartyLibrary,
+    VersionedThirdPartyLibraries,
+};
+use crate::{AndroidOptions, BuilderOptions, Context, Error, Executable, Platform, Result, Target};
+use chrono::{TimeZone, Utc};
+use clap::ArgEnum;
 use feenk_releaser::{Version, VersionBump};
 use serde::{Deserialize, Serialize};
 use shared_library_builder::{Library, LibraryTarget};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
 const DEFAULT_BUILD_DIR: &str = "target";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuilderInfo {
+    #[serde(default)]
+    version: String,
     build_timestamp: String,
     git_branch: String,
     git_commit_timestamp: String,
@@ -20,6 +29,7 @@ pub struct BuilderInfo {
 impl BuilderInfo {
     pub fn new() -> Self {
         Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
             build_timestamp: env!("VERGEN_BUILD_TIMESTAMP").to_string(),
             git_branch: env!("VERGEN_GIT_BRANCH").to_string(),
             git_commit_timestamp: env!("VERGEN_GIT_COMMIT_TIMESTAMP").to_string(),
@@ -37,11 +47,15 @@ pub struct AppInfo {
 }
 
 impl AppInfo {
-    pub fn new() -> Self {
+    /// Describe the app built now, or at the given seconds since the unix epoch when building reproducibly
+    pub fn new(source_date_epoch: Option<i64>) -> Self {
         let info = git_info::get();
+        let build_time = source_date_epoch
+            .and_then(|epoch| Utc.timestamp_opt(epoch, 0).single())
+            .unwrap_or_else(Utc::now);
 
         Self {
-            build_timestamp: Utc::now().format("%Y-%m-%d").to_string(),
+            build_timestamp: build_time.format("%Y-%m-%d").to_string(),
             git_branch: info.current_branch,
             git_sha: info.head.last_commit_hash,
         }
@@ -62,15 +76,57 @@ pub struct ResolvedOptions {
     version: Version,
     icons: Vec<PathBuf>,
     libraries: Vec<Box<dyn Library>>,
+    /// Versions of the libraries by their names, null for the latest release
+    #[serde(default)]
+    library_versions: BTreeMap<String, Option<String>>,
+    /// Libraries built from local checkouts by their names
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    local_libraries: BTreeMap<String, PathBuf>,
+    /// Commits and checksums the libraries are verified against, by their names
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pinned_libraries: BTreeMap<String, PinnedVersion>,
+    /// Bundles with libraries built from local checkouts must not be released
+    #[serde(default)]
+    non_release: bool,
+    /// Seconds since the unix epoch used instead of the current time in reproducible builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_date_epoch: Option<i64>,
+    /// Git url rewrite rules passed to the git processes started during the build
+    #[serde(skip)]
+    git_url_rewrites: Vec<UrlRewrite>,
+    /// The versions file as read when resolving the options
+    #[serde(skip, default = "VersionedThirdPartyLibraries::new")]
+    libraries_versions: VersionedThirdPartyLibraries,
+    #[serde(skip)]
+    workspace_directory: Option<PathBuf>,
     executables: Vec<Executable>,
 }
 
 impl ResolvedOptions {
-    pub fn new(options: BuilderOptions) -> Self {
+    pub fn new(options: BuilderOptions) -> Result<Self> {
+        let libraries_versions = options.libraries_versions()?;
+        Self::validate_custom_libraries(&libraries_versions)?;
+        Self::validate_library_paths(&options, &libraries_versions)?;
+        Self::validate_license_policies(&libraries_versions)?;
+        Self::validate_pinned_libraries(&libraries_versions)?;
+        Self::resolve_with_versions(options, libraries_versions)
+    }
+
+    /// Resolve the options without validating them, for commands like `clean` that only need the paths
+    pub fn resolve(options: BuilderOptions) -> Result<Self> {
+        let libraries_versions = options.libraries_versions()?;
+        Self::resolve_with_versions(options, libraries_versions)
+    }
+
+    fn resolve_with_versions(
+        options: BuilderOptions,
+        libraries_versions: VersionedThirdPartyLibraries,
+    ) -> Result<Self> {
+        let workspace_directory = options.workspace_directory()?;
         let target_dir: PathBuf = options.target_dir().map_or_else(
             || {
-                options
-                    .workspace_directory()
+                workspace_directory
+                    .as_ref()
                     .map_or(PathBuf::from(DEFAULT_BUILD_DIR), |workspace| {
                         workspace.join(DEFAULT_BUILD_DIR)
                     })
@@ -92,12 +148,13 @@ impl ResolvedOptions {
             .executable_name()
             .map_or_else(|| app_name.clone(), |name| name.to_owned());
 
-        let version = options.version().map_or_else(
-            || Version::new(VersionBump::Patch),
-            |version| {
-                Version::parse(version).expect(&format!("Could not parse version {}", version))
-            },
-        );
+        let version = match options.version() {
+            None => Version::new(VersionBump::Patch),
+            Some(version) => Version::parse(version).ok().context(|| {
+                Error::invalid_options(format!("Could not parse version {}", version))
+                    .hint("Pass the version in the X.Y.Z or vX.Y.Z format")
+            })?,
+        };
 
         let icons = options.icons().map_or(vec![], |icons| {
             icons
@@ -106,15 +163,15 @@ impl ResolvedOptions {
                 .collect::<Vec<PathBuf>>()
         });
 
-        let library_target: LibraryTarget =
-            LibraryTarget::from_str(target.to_string().as_str()).unwrap();
-        let libraries_versions = options.libraries_versions();
-        let libraries = options.libraries().map_or(vec![], |libraries| {
-            libraries
-                .iter()
-                .map(|each| each.as_library(library_target, &libraries_versions))
-                .collect::<Vec<Box<dyn Library>>>()
-        });
+        let libraries = Self::resolve_libraries(&options, &libraries_versions, target)?;
+        let local_libraries = Self::resolve_local_libraries(&options, &libraries_versions, target)?;
+        let non_release = !local_libraries.is_empty();
+        let pinned_libraries =
+            Self::resolve_pinned_libraries(&options, &libraries_versions, target)?;
+        let library_versions =
+            Self::resolve_library_versions(&options, &libraries_versions, target, &libraries)?;
+        let source_date_epoch = options.source_date_epoch(workspace_directory.as_deref())?;
+        let git_url_rewrites = options.git_url_rewrites(&libraries_versions)?;
 
         let executables = options
             .executables()
@@ -122,10 +179,10 @@ impl ResolvedOptions {
                 values.clone()
             });
 
-        Self {
+        Ok(Self {
             builder_flags: options,
             builder_info: BuilderInfo::new(),
-            app_build_info: AppInfo::new(),
+            app_build_info: AppInfo::new(source_date_epoch),
             target_dir,
             target,
             app_name,
@@ -134,8 +191,345 @@ impl ResolvedOptions {
             version,
             icons,
             libraries,
+            library_versions,
+            local_libraries,
+            pinned_libraries,
+            non_release,
+            source_date_epoch,
+            git_url_rewrites,
+            libraries_versions,
+            workspace_directory,
             executables,
+        })
+    }
+
+    /// A declared library must not shadow a built-in one, since both would share
+    /// the sources and build directories, and must export at most one compiled library
+    fn validate_custom_libraries(libraries_versions: &VersionedThirdPartyLibraries) -> Result<()> {
+        for library in libraries_versions.custom_libraries() {
+            if let Some(built_in) = ThirdPartyLibrary::value_variants()
+                .iter()
+                .find(|built_in| built_in.to_string().eq_ignore_ascii_case(&library.name))
+            {
+                return Error::invalid_options(format!(
+                    "The declared library {} has the same name as the built-in {} library",
+                    &library.name, built_in
+                ))
+                .hint(format!(
+                    "Rename the declared library, or select {} with --libraries instead",
+                    built_in
+                ))
+                .into();
+            }
+            if library.exported.len() > 1 {
+                return Error::invalid_options(format!(
+                    "The declared library {} exports {}, but only one compiled library is bundled per library",
+                    &library.name,
+                    library.exported.join(", ")
+                ))
+                .hint("Declare each compiled library as a separate library")
+                .into();
+            }
+        }
+        Ok(())
+    }
+
+    /// Every library built from a local checkout must be selected and must support it
+    fn validate_library_paths(
+        options: &BuilderOptions,
+        libraries_versions: &VersionedThirdPartyLibraries,
+    ) -> Result<()> {
+        let selected_libraries = options.libraries().cloned().unwrap_or_default();
+        let library_paths = options.library_paths(libraries_versions);
+
+        let cmake_libraries = selected_libraries
+            .iter()
+            .filter(|library| {
+                library_paths.contains_key(&library.to_string())
+                    && !library.can_be_built_from_local_checkout()
+            })
+            .map(|library| library.to_string())
+            .collect::<Vec<String>>();
+        if !cmake_libraries.is_empty() {
+            let all_cmake_libraries = ThirdPartyLibrary::value_variants()
+                .iter()
+                .filter(|library| !library.can_be_built_from_local_checkout())
+                .map(|library| library.to_string())
+                .collect::<Vec<String>>();
+            return Error::invalid_options(format!(
+                "Can not build {} from a local checkout, they are built with cmake",
+                cmake_libraries.join(", ")
+            ))
+            .hint(format!(
+                "Only the libraries compiled with cargo can be built from a local checkout, not {}. Declare them as custom cmake libraries in the versions file instead",
+                all_cmake_libraries.join(", ")
+            ))
+            .into();
+        }
+
+        for (name, path) in library_paths {
+            if !selected_libraries
+                .iter()
+                .any(|library| library.to_string() == name)
+                && !libraries_versions
+                    .custom_libraries()
+                    .iter()
+                    .any(|library| library.name == name)
+            {
+                return Error::invalid_options(format!(
+                    "Can not build {} from {}, the library is not included in the build",
+                    name,
+                    path.display()
+                ))
+                .hint("Select the library with --libraries or declare it in the versions file")
+                .into();
+            }
+
+            if !path.is_dir() {
+                return Error::invalid_options(format!(
+                    "The local checkout of {} at {} does not exist",
+                    name,
+                    path.display()
+                ))
+                .into();
+            }
+        }
+        Ok(())
+    }
+
+    /// A pinned commit must be a hex prefix of a commit hash long enough to tell commits apart
+    fn validate_pinned_libraries(libraries_versions: &VersionedThirdPartyLibraries) -> Result<()> {
+        let pinned_libraries = libraries_versions
+            .pinned_versions()
+            .map(|(library, pinned)| (library.to_string(), pinned.clone()))
+            .chain(
+                libraries_versions
+                    .custom_libraries()
+                    .iter()
+                    .filter_map(|library| {
+                        library
+                            .pinned()
+                            .map(|pinned| (library.name.clone(), pinned))
+                    }),
+            );
+        for (library, pinned) in pinned_libraries {
+            if let Some(commit) = &pinned.commit {
+                if commit.len() < 7 || !commit.chars().all(|each| each.is_ascii_hexdigit()) {
+                    return Error::invalid_options(format!(
+                        "The commit {} pinned for {} is not a commit hash of at least 7 characters",
+                        commit, library
+                    ))
+                    .hint("Pin the full commit hash, as printed by `git rev-parse HEAD`")
+                    .into();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every exception from a license policy must say why the component is shipped anyway
+    fn validate_license_policies(libraries_versions: &VersionedThirdPartyLibraries) -> Result<()> {
+        for policy in libraries_versions.license_policies() {
+            for exception in &policy.exceptions {
+                if exception.justification.trim().is_empty() {
+                    return Error::invalid_options(format!(
+                        "The license policy exception for {} has no justification",
+                        &exception.component
+                    ))
+                    .hint("Explain in the justification why the component may be shipped")
+                    .into();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The target of the library builders, built-in libraries can not be compiled for every target
+    fn library_target(target: Target) -> Result<LibraryTarget> {
+        LibraryTarget::try_from(target.to_string().as_str()).context(|| {
+            Error::invalid_options(format!(
+                "Libraries can not be compiled for {}",
+                target.to_string()
+            ))
+        })
+    }
+
+    /// Versions of the selected libraries given in the versions file, by library names
+    fn resolve_library_versions(
+        options: &BuilderOptions,
+        libraries_versions: &VersionedThirdPartyLibraries,
+        target: Target,
+        libraries: &[Box<dyn Library>],
+    ) -> Result<BTreeMap<String, Option<String>>> {
+        let mut versions = options
+            .libraries()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|library| {
+                libraries_versions.get_version_of(library).map(|version| {
+                    let name = library
+                        .as_library(Self::library_target(target)?, libraries_versions)?
+                        .name()
+                        .to_string();
+                    Ok((name, Some(version.to_string())))
+                })
+            })
+            .collect::<Result<BTreeMap<String, Option<String>>>>()?;
+
+        for library in libraries_versions.custom_libraries() {
+            if let CustomLibraryLocation::Git { tag: Some(tag), .. } = &library.location {
+                versions.insert(library.name.clone(), Some(tag.clone()));
+            }
+        }
+
+        for library in libraries {
+            versions.entry(library.name().to_string()).or_insert(None);
         }
+        Ok(versions)
+    }
+
+    fn resolve_libraries(
+        options: &BuilderOptions,
+        libraries_versions: &VersionedThirdPartyLibraries,
+        target: Target,
+    ) -> Result<Vec<Box<dyn Library>>> {
+        let library_paths = options.library_paths(libraries_versions);
+        let mut libraries = options
+            .libraries()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .map(|each| {
+                let library_target = Self::library_target(target)?;
+                match library_paths.get(&each.to_string()).and_then(|path| {
+                    each.as_local_library(library_target, libraries_versions, path)
+                }) {
+                    Some(library) => Ok(library),
+                    None => each.as_library(library_target, libraries_versions),
+                }
+            })
+            .collect::<Result<Vec<Box<dyn Library>>>>()?;
+        libraries.extend(libraries_versions.custom_libraries().iter().map(|each| {
+            match library_paths.get(&each.name) {
+                Some(path) => each.with_path(path).as_library(),
+                None => each.as_library(),
+            }
+        }));
+        Ok(libraries)
+    }
+
+    /// Map names of the libraries built from local checkouts to their paths
+    fn resolve_local_libraries(
+        options: &BuilderOptions,
+        libraries_versions: &VersionedThirdPartyLibraries,
+        target: Target,
+    ) -> Result<BTreeMap<String, PathBuf>> {
+        options
+            .library_paths(libraries_versions)
+            .into_iter()
+            .map(|(name, path)| {
+                let library_name = match name.parse::<ThirdPartyLibrary>() {
+                    Ok(library) => library
+                        .as_library(Self::library_target(target)?, libraries_versions)?
+                        .name()
+                        .to_string(),
+                    Err(_) => name,
+                };
+                Ok((library_name, path))
+            })
+            .collect()
+    }
+
+    /// Map names of the selected and declared libraries with a pinned commit or checksums to the pins.
+    /// Libraries built from local checkouts are not verified
+    fn resolve_pinned_libraries(
+        options: &BuilderOptions,
+        libraries_versions: &VersionedThirdPartyLibraries,
+        target: Target,
+    ) -> Result<BTreeMap<String, PinnedVersion>> {
+        let library_paths = options.library_paths(libraries_versions);
+        let mut pinned_libraries = options
+            .libraries()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|library| !library_paths.contains_key(&library.to_string()))
+            .filter_map(|library| {
+                libraries_versions.pinned_version_of(library).map(|pinned| {
+                    let name = library
+                        .as_library(Self::library_target(target)?, libraries_versions)?
+                        .name()
+                        .to_string();
+                    Ok((name, pinned.clone()))
+                })
+            })
+            .collect::<Result<BTreeMap<String, PinnedVersion>>>()?;
+
+        for library in libraries_versions.custom_libraries() {
+            if library_paths.contains_key(&library.name) {
+                continue;
+            }
+            if let Some(pinned) = library.pinned() {
+                pinned_libraries.insert(library.name.clone(), pinned);
+            }
+        }
+        Ok(pinned_libraries)
+    }
+
+    /// All third party libraries known to the builder, whether selected or not
+    pub fn known_libraries(&self) -> Result<Vec<Box<dyn Library>>> {
+        let library_target = Self::library_target(self.target)?;
+        let libraries_versions = &self.libraries_versions;
+        ThirdPartyLibrary::value_variants()
+            .iter()
+            .map(|each| each.as_library(library_target, libraries_versions))
+            .chain(
+                libraries_versions
+                    .custom_libraries()
+                    .iter()
+                    .map(|each| Ok(each.as_library())),
+            )
+            .collect()
+    }
+
+    /// Names of all known libraries by the names they are selected with,
+    /// for example `skia` for Skia, and by their own names for the libraries declared in the versions file
+    pub fn known_library_names(&self) -> Result<BTreeMap<String, String>> {
+        let library_target = Self::library_target(self.target)?;
+        let libraries_versions = &self.libraries_versions;
+        ThirdPartyLibrary::value_variants()
+            .iter()
+            .map(|each| {
+                Ok((
+                    each.to_string(),
+                    each.as_library(library_target, libraries_versions)?
+                        .name()
+                        .to_string(),
+                ))
+            })
+            .chain(
+                libraries_versions
+                    .custom_libraries()
+                    .iter()
+                    .map(|each| Ok((each.name.clone(), each.name.clone()))),
+            )
+            .collect()
+    }
+
+    /// Return a copy of the options that compiles the same app for another target
+    pub fn with_target(&self, target: Target) -> Result<Self> {
+        let mut options = self.clone();
+        options.target = target;
+        options.libraries =
+            Self::resolve_libraries(&self.builder_flags, &self.libraries_versions, target)?;
+        options.library_versions = Self::resolve_library_versions(
+            &self.builder_flags,
+            &self.libraries_versions,
+            target,
+            &options.libraries,
+        )?;
+        Ok(options)
     }
 
     pub fn target(&self) -> &Target {
@@ -187,6 +581,44 @@ impl ResolvedOptions {
         self.builder_flags.include_debug_symbols()
     }
 
+    pub fn symbols_archive(&self) -> bool {
+        self.builder_flags.symbols_archive()
+    }
+
+    pub fn offline(&self) -> bool {
+        self.builder_flags.offline()
+    }
+
+    pub fn strict_licenses(&self) -> bool {
+        self.builder_flags.strict_licenses()
+    }
+
+    pub fn reproducible(&self) -> bool {
+        self.builder_flags.reproducible()
+    }
+
+    pub fn fresh(&self) -> bool {
+        self.builder_flags.fresh()
+    }
+
+    pub fn source_date_epoch(&self) -> Option<i64> {
+        self.source_date_epoch
+    }
+
+    pub fn git_url_rewrites(&self) -> &[UrlRewrite] {
+        &self.git_url_rewrites
+    }
+
+    /// License policies from the versions file that apply to the target
+    pub fn license_policies(&self) -> Vec<LicensePolicy> {
+        self.libraries_versions
+            .license_policies()
+            .iter()
+            .filter(|policy| policy.applies_to(&self.target))
+            .cloned()
+            .collect()
+    }
+
     pub fn icons(&self) -> &Vec<PathBuf> {
         &self.icons
     }
@@ -207,6 +639,118 @@ impl ResolvedOptions {
         &self.libraries
     }
 
+    /// A local checkout the library is built from instead of its release
+    pub fn local_library_path(&self, library_name: &str) -> Option<&Path> {
+        self.local_libraries
+            .get(library_name)
+            .map(|path| path.as_path())
+    }
+
+    pub fn is_non_release(&self) -> bool {
+        self.non_release
+    }
+
+    /// Versions of the selected libraries by their names, None for the latest release
+    pub fn library_versions(&self) -> &BTreeMap<String, Option<String>> {
+        &self.library_versions
+    }
+
+    /// A commit and checksums the sources and the compiled library must match
+    pub fn pinned_library(&self, library_name: &str) -> Option<&PinnedVersion> {
+        self.pinned_libraries.get(library_name)
+    }
+
+    /// True if the library is compiled from a directory on the local disk
+    /// and does not need to be downloaded
+    pub fn is_built_from_local_path(&self, library_name: &str) -> bool {
+        self.local_library_path(library_name).is_some()
+            || library_name == test_library().name()
+            || self
+                .libraries_versions
+                .custom_libraries()
+                .iter()
+                .any(|library| {
+                    library.name == library_name
+                        && matches!(library.location, CustomLibraryLocation::Path(_))
+                })
+    }
+
+    /// The versions file with the versions of libraries that are not pinned
+    /// replaced by the ones returned for their names, for example tags of their checkouts
+    pub fn pinned_libraries_versions(
+        &self,
+        version_of: impl Fn(&str) -> Option<String>,
+    ) -> Result<VersionedThirdPartyLibraries> {
+        let mut libraries_versions = self.libraries_versions.clone();
+
+        for library in self.builder_flags.libraries().cloned().unwrap_or_default() {
+            if library == ThirdPartyLibrary::TestLibrary
+                || libraries_versions.get_version_of(library).is_some()
+            {
+                continue;
+            }
+            let name = library
+                .as_library(Self::library_target(self.target)?, &libraries_versions)?
+                .name()
+                .to_string();
+            if let Some(version) = version_of(&name) {
+                libraries_versions.set_version_of(library, version);
+            }
+        }
+
+        let unpinned_custom_libraries = libraries_versions
+            .custom_libraries()
+            .iter()
+            .filter(|library| {
+                matches!(
+                    library.location,
+                    CustomLibraryLocation::Git { tag: None, .. }
+                )
+            })
+            .map(|library| library.name.clone())
+            .collect::<Vec<String>>();
+        for name in unpinned_custom_libraries {
+            if let Some(version) = version_of(&name) {
+                libraries_versions.set_tag_of_custom_library(&name, version);
+            }
+        }
+
+        Ok(libraries_versions)
+    }
+
+    /// Selected libraries that would be downloaded at their latest release
+    /// because the versions file does not pin their version
+    pub fn libraries_without_versions(&self) -> Vec<String> {
+        let libraries_versions = &self.libraries_versions;
+        let library_paths = self.builder_flags.library_paths(libraries_versions);
+
+        let mut libraries = self
+            .builder_flags
+            .libraries()
+            .map_or(vec![], |libraries| libraries.clone())
+            .into_iter()
+            .filter(|library| *library != ThirdPartyLibrary::TestLibrary)
+            .filter(|library| !library_paths.contains_key(&library.to_string()))
+            .filter(|library| libraries_versions.get_version_of(*library).is_none())
+            .map(|library| library.to_string())
+            .collect::<Vec<String>>();
+
+        libraries.extend(
+            libraries_versions
+                .custom_libraries()
+                .iter()
+                .filter(|library| !library_paths.contains_key(&library.name))
+                .filter(|library| {
+                    matches!(
+                        library.location,
+                        CustomLibraryLocation::Git { tag: None, .. }
+                    )
+                })
+                .map(|library| library.name.clone()),
+        );
+        libraries
+    }
+
     pub fn executables(&self) -> &Vec<Executable> {
         &self.executables
     }
@@ -216,7 +760,19 @@ impl ResolvedOptions {
     }
 
     pub fn workspace_directory(&self) -> Option<PathBuf> {
-        self.builder_flags.workspace_directory()
+        self.workspace_directory.clone()
+    }
+
+    pub fn image(&self) -> Option<&Path> {
+        self.builder_flags.image()
+    }
+
+    pub fn resources(&self) -> &[PathBuf] {
+        self.builder_flags.resources()
+    }
+
+    pub fn android(&self) -> &AndroidOptions {
+        self.builder_flags.android()
     }
 }
 
@@ -238,6 +794,14 @@ impl Clone for ResolvedOptions {
                 .iter()
                 .map(|library| library.clone_library())
                 .collect(),
+            library_versions: self.library_versions.clone(),
+            local_libraries: self.local_libraries.clone(),
+            pinned_libraries: self.pinned_libraries.clone(),
+            non_release: self.non_release,
+            source_date_epoch: self.source_date_epoch,
+            git_url_rewrites: self.git_url_rewrites.clone(),
+            libraries_versions: self.libraries_versions.clone(),
+            workspace_directory: self.workspace_directory.clone(),
             executables: self.executables.clone(),
         }
     }
@@ -0,0 +1,24 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::BuilderOptions;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct VendorOptions {
+    #[clap(flatten)]
+    builder_options: BuilderOptions,
+    /// A directory to put the workspace, the sources of the libraries and all dependencies in
+    #[clap(parse(from_os_str))]
+    directory: PathBuf,
+}
+
+impl VendorOptions {
+    pub fn builder_options(&self) -> &BuilderOptions {
+        &self.builder_options
+    }
+
+    pub fn directory(&self) -> &Path {
+        self.directory.as_path()
+    }
+}
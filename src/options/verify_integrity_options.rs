@@ -0,0 +1,16 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+#[derive(Args, Clone, Debug)]
+pub struct VerifyIntegrityOptions {
+    /// A bundle directory or the app directory within it
+    #[clap(parse(from_os_str))]
+    bundle: PathBuf,
+}
+
+impl VerifyIntegrityOptions {
+    pub fn bundle(&self) -> &Path {
+        self.bundle.as_path()
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::{human_size, size_of};
+use crate::events::{millis, Event, Phase, Recording};
+use crate::{BundleOptions, Error, Result};
+
+/// How long every phase of a build took and how large the produced files are
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildReport {
+    pub app_name: String,
+    pub target: String,
+    pub release: bool,
+    pub succeeded: bool,
+    pub duration_ms: u64,
+    pub phases: Vec<PhaseTiming>,
+    pub files: Vec<FileSize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    pub subject: String,
+    pub duration_ms: u64,
+    pub succeeded: bool,
+    /// True for libraries that were already compiled and were not rebuilt
+    #[serde(default)]
+    pub cached: bool,
+}
+
+impl PhaseTiming {
+    /// Phases are compared between reports by their name and subject
+    pub fn key(&self) -> String {
+        format!("{} {}", phase_name(self.phase), &self.subject)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSize {
+    /// A name that identifies the file across builds, for example `skia/libSkia.so`
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl BuildReport {
+    pub fn new(options: &BundleOptions, recording: &Recording, succeeded: bool) -> Self {
+        let mut phases: Vec<PhaseTiming> = vec![];
+        let mut files: Vec<FileSize> = vec![];
+
+        for event in &recording.events {
+            match event {
+                Event::PhaseFinished {
+                    phase,
+                    subject,
+                    duration_ms,
+                } => phases.push(PhaseTiming {
+                    phase: *phase,
+                    subject: subject.clone(),
+                    duration_ms: *duration_ms,
+                    succeeded: true,
+                    cached: false,
+                }),
+                Event::PhaseFailed {
+                    phase,
+                    subject,
+                    duration_ms,
+                    ..
+                } => phases.push(PhaseTiming {
+                    phase: *phase,
+                    subject: subject.clone(),
+                    duration_ms: *duration_ms,
+                    succeeded: false,
+                    cached: false,
+                }),
+                Event::LibraryCacheHit { library, path } => {
+                    if let Some(timing) = phases.iter_mut().rev().find(|timing| {
+                        timing.phase == Phase::CompileLibrary && &timing.subject == library
+                    }) {
+                        timing.cached = true;
+                    }
+                    files.push(FileSize::new(library, path));
+                }
+                Event::ArtifactProduced { subject, path, .. } => {
+                    files.push(FileSize::new(subject, path))
+                }
+                Event::ArchiveCreated { path, size } => files.push(FileSize {
+                    name: file_name(path),
+                    path: path.clone(),
+                    size: *size,
+                }),
+                _ => {}
+            }
+        }
+
+        let ran_bundle = phases.iter().any(|timing| timing.phase == Phase::Bundle);
+        let bundle_location = options.bundle_location();
+        if ran_bundle && bundle_location.exists() {
+            files.push(FileSize::new("bundle", &bundle_location));
+        }
+
+        Self {
+            app_name: options.app_name().to_string(),
+            target: options.target().to_string(),
+            release: options.release(),
+            succeeded,
+            duration_ms: millis(recording.started.elapsed()),
+            phases,
+            files,
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|error| {
+            Error::new(format!(
+                "Could not read the build report {}",
+                path.display()
+            ))
+            .from(error)
+        })?;
+        serde_json::from_str(&content).map_err(|error| {
+            Error::new(format!("{} is not a valid build report", path.display()))
+                .from(error)
+                .into()
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self)?).map_err(|error| {
+            Error::new(format!(
+                "Could not write the build report {}",
+                path.display()
+            ))
+            .from(error)
+            .into()
+        })
+    }
+
+    pub fn print_summary(&self) {
+        let subject_width = self
+            .phases
+            .iter()
+            .map(|timing| timing.subject.len())
+            .chain(self.files.iter().map(|file| file.name.len()))
+            .max()
+            .unwrap_or(0)
+            .max(7);
+
+        println!();
+        println!(
+            "{:<18} {:<width$} {:>10}",
+            "Phase",
+            "Subject",
+            "Duration",
+            width = subject_width
+        );
+        for timing in &self.phases {
+            let note = if !timing.succeeded {
+                " (failed)"
+            } else if timing.cached {
+                " (cached)"
+            } else {
+                ""
+            };
+            println!(
+                "{:<18} {:<width$} {:>10}{}",
+                phase_name(timing.phase),
+                &timing.subject,
+                seconds(timing.duration_ms as i64),
+                note,
+                width = subject_width
+            );
+        }
+        println!(
+            "{:<18} {:<width$} {:>10}",
+            "total",
+            "",
+            seconds(self.duration_ms as i64),
+            width = subject_width
+        );
+
+        if !self.files.is_empty() {
+            println!();
+            println!(
+                "{:<width$} {:>10}",
+                "File",
+                "Size",
+                width = subject_width + 19
+            );
+            for file in &self.files {
+                println!(
+                    "{:<width$} {:>10}",
+                    &file.name,
+                    human_size(file.size),
+                    width = subject_width + 19
+                );
+            }
+        }
+    }
+}
+
+impl FileSize {
+    fn new(subject: &str, path: &Path) -> Self {
+        Self {
+            name: format!("{}/{}", subject, file_name(path)),
+            path: path.to_path_buf(),
+            size: size_of(path),
+        }
+    }
+}
+
+pub fn phase_name(phase: Phase) -> String {
+    serde_json::to_value(phase)
+        .ok()
+        .and_then(|value| value.as_str().map(|name| name.to_string()))
+        .unwrap_or_else(|| format!("{:?}", phase))
+}
+
+pub fn seconds(duration_ms: i64) -> String {
+    format!("{:.2}s", duration_ms as f64 / 1000.0)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
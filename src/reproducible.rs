@@ -0,0 +1,82 @@
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::{BundleOptions, Context, Error, Result};
+
+/// Set the modification time of every file and directory in the bundle to the source date epoch,
+/// and their permissions to 755 for directories and executables and 644 for other files
+pub fn normalize_bundle(options: &BundleOptions) -> Result<()> {
+    normalize_path(options, &options.bundle_location())
+}
+
+/// Normalize a file, or a directory with everything in it, written after the bundle was normalized
+pub fn normalize_path(options: &BundleOptions, path: &Path) -> Result<()> {
+    normalize_to(path, options.source_date_epoch().unwrap_or(0))
+}
+
+/// Normalize a file, or a directory with everything in it, to a given source date epoch
+pub fn normalize_to(path: &Path, source_date_epoch: i64) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(source_date_epoch.max(0) as u64);
+    normalize(path, modified)
+}
+
+fn normalize(path: &Path, modified: SystemTime) -> Result<()> {
+    let metadata = path
+        .symlink_metadata()
+        .context(|| Error::io(format!("Could not read the metadata of {}", path.display())))?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+
+    if metadata.is_dir() {
+        for entry in path
+            .read_dir()
+            .context(|| Error::io(format!("Could not read {}", path.display())))?
+        {
+            normalize(&entry?.path(), modified)?;
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = if metadata.is_dir() || metadata.permissions().mode() & 0o111 != 0 {
+            0o755
+        } else {
+            0o644
+        };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).context(|| {
+            Error::io(format!(
+                "Could not set the permissions of {}",
+                path.display()
+            ))
+        })?;
+    }
+
+    // directories can not be opened as files on windows
+    if metadata.is_file() || cfg!(unix) {
+        open_to_set_times(path)
+            .and_then(|file| file.set_modified(modified))
+            .context(|| {
+                Error::io(format!(
+                    "Could not set the modification time of {}",
+                    path.display()
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+/// Windows needs write access to change the times of a file, while on unix
+/// it is enough to own it, which also works for directories
+fn open_to_set_times(path: &Path) -> std::io::Result<File> {
+    if cfg!(windows) {
+        OpenOptions::new().write(true).open(path)
+    } else {
+        File::open(path)
+    }
+}
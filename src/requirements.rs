@@ -0,0 +1,208 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::Serialize;
+
+use crate::Target;
+
+#[derive(Debug, Clone)]
+pub enum RequirementKind {
+    /// An executable that must be in the PATH
+    Tool(String),
+    /// Any of the environment variables must be set
+    EnvironmentVariable(Vec<String>),
+    /// A rust standard library for the target must be installed
+    RustTarget(Target),
+    /// A tool of the MSVC toolchain for the target
+    MsvcTool(Target, String),
+}
+
+/// An external tool or environment variable needed to build or bundle for a target
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    kind: RequirementKind,
+    reason: String,
+    hint: String,
+    blocker: bool,
+}
+
+impl Requirement {
+    pub fn tool(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            hint: format!("Install `{}` and make sure it is in the PATH", &name),
+            kind: RequirementKind::Tool(name),
+            reason: reason.into(),
+            blocker: true,
+        }
+    }
+
+    pub fn environment_variable(names: &[&str], reason: impl Into<String>) -> Self {
+        Self {
+            hint: format!("Set {}", names.join(" or ")),
+            kind: RequirementKind::EnvironmentVariable(
+                names.iter().map(|name| name.to_string()).collect(),
+            ),
+            reason: reason.into(),
+            blocker: true,
+        }
+    }
+
+    pub fn rust_target(target: Target) -> Self {
+        Self {
+            hint: format!("Run `rustup target add {}`", target.to_string()),
+            kind: RequirementKind::RustTarget(target),
+            reason: "to compile for the target".to_string(),
+            blocker: true,
+        }
+    }
+
+    pub fn msvc_tool(target: Target, tool: impl Into<String>, reason: impl Into<String>) -> Self {
+        let tool = tool.into();
+        Self {
+            hint: format!(
+                "Install Visual Studio Build Tools with the \"Desktop development with C++\" workload that provides {}",
+                &tool
+            ),
+            kind: RequirementKind::MsvcTool(target, tool),
+            reason: reason.into(),
+            blocker: true,
+        }
+    }
+
+    pub fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = hint.into();
+        self
+    }
+
+    /// Missing optional requirements are reported as warnings and do not block the build
+    pub fn optional(mut self) -> Self {
+        self.blocker = false;
+        self
+    }
+
+    pub fn name(&self) -> String {
+        match &self.kind {
+            RequirementKind::Tool(name) => name.clone(),
+            RequirementKind::EnvironmentVariable(names) => names.join(" | "),
+            RequirementKind::RustTarget(target) => format!("rust-std-{}", target.to_string()),
+            RequirementKind::MsvcTool(_, tool) => tool.clone(),
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        self.reason.as_str()
+    }
+
+    pub fn check(&self) -> RequirementCheck {
+        let found = match &self.kind {
+            RequirementKind::Tool(name) => which::which(name).ok().map(|path| {
+                let version = tool_version(Command::new(&path));
+                (Some(path), version)
+            }),
+            RequirementKind::EnvironmentVariable(names) => names.iter().find_map(|name| {
+                std::env::var_os(name)
+                    .map(|value| (Some(PathBuf::from(value)), Some(format!("{} is set", name))))
+            }),
+            RequirementKind::RustTarget(target) => {
+                if installed_rust_targets().contains(&target.to_string()) {
+                    Some((None, None))
+                } else {
+                    None
+                }
+            }
+            RequirementKind::MsvcTool(target, tool) => {
+                cc::windows_registry::find(target.to_string().as_str(), tool)
+                    .map(|command| (Some(PathBuf::from(command.get_program())), None))
+            }
+        };
+
+        let status = match (&found, self.blocker) {
+            (Some(_), _) => RequirementStatus::Found,
+            (None, true) => RequirementStatus::Missing,
+            (None, false) => RequirementStatus::Warning,
+        };
+
+        RequirementCheck {
+            name: self.name(),
+            reason: self.reason.clone(),
+            status,
+            location: found
+                .as_ref()
+                .and_then(|(location, _)| location.as_ref())
+                .map(|location| location.display().to_string()),
+            version: found.and_then(|(_, version)| version),
+            hint: if status == RequirementStatus::Found {
+                None
+            } else {
+                Some(self.hint.clone())
+            },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequirementStatus {
+    Found,
+    Warning,
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequirementCheck {
+    pub name: String,
+    pub reason: String,
+    pub status: RequirementStatus,
+    pub location: Option<String>,
+    pub version: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl RequirementCheck {
+    pub fn is_blocker(&self) -> bool {
+        self.status == RequirementStatus::Missing
+    }
+}
+
+/// Requirements shared by all bundlers: a rust toolchain able to compile for the target
+pub fn common_requirements(target: &Target) -> Vec<Requirement> {
+    vec![
+        Requirement::tool("cargo", "to compile the vm-client")
+            .hint("Install rust from https://rustup.rs"),
+        Requirement::tool("rustc", "to compile the vm-client")
+            .hint("Install rust from https://rustup.rs"),
+        Requirement::tool("rustup", "to check the installed rust targets")
+            .hint("Install rust from https://rustup.rs")
+            .optional(),
+        Requirement::rust_target(*target),
+        Requirement::tool("git", "to download the sources of third party libraries"),
+    ]
+}
+
+fn tool_version(mut command: Command) -> Option<String> {
+    let output = command.arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+fn installed_rust_targets() -> Vec<String> {
+    Command::new("rustup")
+        .arg("target")
+        .arg("list")
+        .arg("--installed")
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
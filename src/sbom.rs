@@ -0,0 +1,470 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use shared_library_builder::Library;
+
+use crate::bundlers::{create_directory, Bundler, CYCLONEDX_FILE, SPDX_FILE};
+use crate::checksums;
+use crate::crates::Crate;
+use crate::environment::tool_environment;
+use crate::events::{Event, Phase};
+use crate::libraries::library_repositories;
+use crate::{crates, events, BundleOptions, Context, Error, Result};
+
+/// A third party library as it was checked out for the build
+#[derive(Debug, Clone)]
+pub struct LibraryComponent {
+    pub name: String,
+    pub version: Option<String>,
+    pub commit: Option<String>,
+    pub source_url: Option<String>,
+    pub sources_directory: PathBuf,
+}
+
+impl LibraryComponent {
+    fn reference(&self) -> String {
+        format!("library:{}", &self.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BundledFile {
+    /// A path relative to the bundle location
+    path: String,
+    sha1: String,
+    sha256: String,
+}
+
+/// Describe the selected libraries with the versions and commits of their sources
+pub fn library_components(options: &BundleOptions) -> Vec<LibraryComponent> {
+    let versions = options.library_versions();
+    options
+        .libraries()
+        .iter()
+        .map(|library| {
+            let name = library.name().to_string();
+            let sources_directory = options
+                .local_library_path(&name)
+                .map(|path| path.to_path_buf())
+                .unwrap_or_else(|| {
+                    options
+                        .third_party_libraries_sources_directory()
+                        .join(&name)
+                });
+            let repository = library_repositories(&sources_directory).into_iter().next();
+            if repository.is_none() && sources_directory.exists() {
+                events::emit(Event::Warning {
+                    message: format!(
+                        "Could not find a git checkout of {} in {}, its commit is not recorded",
+                        &name,
+                        sources_directory.display()
+                    ),
+                });
+            }
+            let git = |arguments: &[&str]| {
+                repository
+                    .as_ref()
+                    .and_then(|repository| git(repository, arguments))
+            };
+            let version = versions
+                .get(&name)
+                .cloned()
+                .flatten()
+                .or_else(|| git(&["describe", "--tags", "--exact-match"]));
+            LibraryComponent {
+                version,
+                commit: git(&["rev-parse", "HEAD"]),
+                source_url: git(&["remote", "get-url", "origin"]),
+                sources_directory,
+                name,
+            }
+        })
+        .collect()
+}
+
+/// The crates the selected executables are compiled from for the target of the build
+pub fn executable_crates(options: &BundleOptions) -> Result<Vec<Crate>> {
+    let workspace = match options.workspace_directory() {
+        None => return Ok(vec![]),
+        Some(workspace) => workspace,
+    };
+    let packages = options
+        .executables()
+        .iter()
+        .map(|executable| executable.cargo_package_name())
+        .collect::<Vec<&str>>();
+    crates::dependencies_of(
+        &workspace,
+        &packages,
+        options.features(),
+        &options.target().to_string(),
+        tool_environment(options),
+    )
+}
+
+/// Write the software bill of materials in the CycloneDX and SPDX formats
+/// next to build-info.json and into the resources of the bundle
+pub fn write_sbom(bundler: &dyn Bundler, options: &BundleOptions) -> Result<()> {
+    let libraries = library_components(options);
+    let crates = executable_crates(options)?;
+    let files = bundled_files(bundler, options)?;
+
+    let timestamp = options
+        .source_date_epoch()
+        .and_then(|epoch| Utc.timestamp_opt(epoch, 0).single())
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let serial_number = serial_number(options, &timestamp);
+
+    let cyclonedx = cyclonedx(
+        options,
+        &libraries,
+        &crates,
+        &files,
+        &timestamp,
+        &serial_number,
+    );
+    let spdx = spdx(
+        options,
+        &libraries,
+        &crates,
+        &files,
+        &timestamp,
+        &serial_number,
+    );
+
+    let mut directories = vec![options.compilation_location()];
+    // on Android the resources are the assets, packaged after the SBOM is written
+    let resources_directory = bundler.bundled_resources_directory(options);
+    create_directory(&resources_directory)?;
+    directories.push(resources_directory);
+
+    for directory in directories {
+        write_json(&directory.join(CYCLONEDX_FILE), &cyclonedx)?;
+        write_json(&directory.join(SPDX_FILE), &spdx)?;
+    }
+    Ok(())
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(value)?)
+        .context(|| Error::io(format!("Could not write {}", path.display())))?;
+    events::emit(Event::ArtifactProduced {
+        phase: Phase::Sbom,
+        subject: "sbom".to_string(),
+        path: path.to_path_buf(),
+    });
+    Ok(())
+}
+
+/// All files of the bundled app except for the SBOM itself and symbolic links,
+/// without the archives and other bundles that share the bundle location
+fn bundled_files(bundler: &dyn Bundler, options: &BundleOptions) -> Result<Vec<BundledFile>> {
+    let bundle_location = options.bundle_location();
+    let app_directory = bundler.bundled_app_directory(options);
+    if !app_directory.is_dir() {
+        return Ok(vec![]);
+    }
+
+    checksums::files_in_directory(&app_directory)?
+        .into_iter()
+        .filter(|file| {
+            let name = file.file_name().and_then(|name| name.to_str());
+            name != Some(CYCLONEDX_FILE) && name != Some(SPDX_FILE)
+        })
+        .filter(|file| {
+            !file
+                .symlink_metadata()
+                .map_or(false, |metadata| metadata.file_type().is_symlink())
+        })
+        .map(|file| {
+            Ok(BundledFile {
+                path: checksums::relative_path(&bundle_location, &file),
+                sha1: checksums::sha1_of_file(&file)?,
+                sha256: checksums::sha256_of_file(&file)?,
+            })
+        })
+        .collect()
+}
+
+fn cyclonedx(
+    options: &BundleOptions,
+    libraries: &[LibraryComponent],
+    crates: &[Crate],
+    files: &[BundledFile],
+    timestamp: &str,
+    serial_number: &str,
+) -> Value {
+    let mut components = vec![];
+    for library in libraries {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": library.reference(),
+            "name": &library.name,
+        });
+        if let Some(version) = &library.version {
+            component["version"] = json!(version);
+        }
+        if let Some(url) = &library.source_url {
+            component["externalReferences"] = json!([{ "type": "vcs", "url": url }]);
+        }
+        if let Some(commit) = &library.commit {
+            component["pedigree"] = json!({ "commits": [{ "uid": commit }] });
+        }
+        components.push(component);
+    }
+
+    for each in crates {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": &each.id,
+            "name": &each.name,
+            "version": &each.version,
+            "purl": each.purl(),
+        });
+        if let Some(license) = each.license_expression() {
+            component["licenses"] = json!([{ "expression": license }]);
+        }
+        if let Some(repository) = &each.repository {
+            component["externalReferences"] = json!([{ "type": "vcs", "url": repository }]);
+        }
+        components.push(component);
+    }
+
+    for file in files {
+        components.push(json!({
+            "type": "file",
+            "bom-ref": format!("file:{}", &file.path),
+            "name": &file.path,
+            "hashes": [
+                { "alg": "SHA-1", "content": &file.sha1 },
+                { "alg": "SHA-256", "content": &file.sha256 },
+            ],
+        }));
+    }
+
+    let mut app_dependencies = libraries
+        .iter()
+        .map(|library| library.reference())
+        .collect::<Vec<String>>();
+    app_dependencies.extend(
+        crates
+            .iter()
+            .filter(|each| each.source.is_none())
+            .map(|each| each.id.clone()),
+    );
+
+    let mut dependencies = vec![json!({ "ref": "app", "dependsOn": app_dependencies })];
+    dependencies.extend(
+        crates
+            .iter()
+            .map(|each| json!({ "ref": &each.id, "dependsOn": &each.dependencies })),
+    );
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", serial_number),
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp,
+            "tools": [{
+                "vendor": "feenk gmbh",
+                "name": "gtoolkit-vm-builder",
+                "version": env!("CARGO_PKG_VERSION"),
+            }],
+            "component": {
+                "type": "application",
+                "bom-ref": "app",
+                "name": options.app_name(),
+                "version": options.version().to_string(),
+                "properties": [{ "name": "target", "value": options.target().to_string() }],
+            },
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+fn spdx(
+    options: &BundleOptions,
+    libraries: &[LibraryComponent],
+    crates: &[Crate],
+    files: &[BundledFile],
+    timestamp: &str,
+    serial_number: &str,
+) -> Value {
+    let app_id = "SPDXRef-App";
+    let mut packages = vec![json!({
+        "SPDXID": app_id,
+        "name": options.app_name(),
+        "versionInfo": options.version().to_string(),
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+    })];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": app_id,
+    })];
+
+    for library in libraries {
+        let id = spdx_id("Library", &library.name);
+        let download_location = match (&library.source_url, &library.commit) {
+            (Some(url), Some(commit)) => format!("git+{}@{}", url, commit),
+            (Some(url), None) => format!("git+{}", url),
+            _ => "NOASSERTION".to_string(),
+        };
+        packages.push(json!({
+            "SPDXID": &id,
+            "name": &library.name,
+            "versionInfo": library.version.as_deref().unwrap_or("NOASSERTION"),
+            "downloadLocation": download_location,
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+        }));
+        relationships.push(json!({
+            "spdxElementId": app_id,
+            "relationshipType": "DEPENDS_ON",
+            "relatedSpdxElement": id,
+        }));
+    }
+
+    for each in crates {
+        let id = spdx_id("Crate", &format!("{}-{}", &each.name, &each.version));
+        packages.push(json!({
+            "SPDXID": &id,
+            "name": &each.name,
+            "versionInfo": &each.version,
+            "downloadLocation": each.repository.as_deref().unwrap_or("NOASSERTION"),
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": each.license_expression().unwrap_or_else(|| "NOASSERTION".to_string()),
+            "copyrightText": "NOASSERTION",
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": each.purl(),
+            }],
+        }));
+        if each.source.is_none() {
+            relationships.push(json!({
+                "spdxElementId": app_id,
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": &id,
+            }));
+        }
+        for dependency in crates
+            .iter()
+            .filter(|dependency| each.dependencies.contains(&dependency.id))
+        {
+            relationships.push(json!({
+                "spdxElementId": &id,
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_id("Crate", &format!("{}-{}", &dependency.name, &dependency.version)),
+            }));
+        }
+    }
+
+    let files = files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            let id = format!("SPDXRef-File-{}", index);
+            relationships.push(json!({
+                "spdxElementId": app_id,
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": &id,
+            }));
+            json!({
+                "SPDXID": id,
+                "fileName": format!("./{}", &file.path),
+                // SPDX requires a SHA1 checksum of every file
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": &file.sha1 },
+                    { "algorithm": "SHA256", "checksumValue": &file.sha256 },
+                ],
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", options.app_name(), options.version()),
+        "documentNamespace": format!(
+            "https://feenk.com/spdx/{}-{}-{}",
+            options.app_name(),
+            options.version(),
+            serial_number
+        ),
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [format!("Tool: gtoolkit-vm-builder-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "files": files,
+        "relationships": relationships,
+    })
+}
+
+/// SPDX identifiers may only contain letters, numbers, `.` and `-`
+fn spdx_id(kind: &str, name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() || character == '.' || character == '-' {
+                character
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("SPDXRef-{}-{}", kind, name)
+}
+
+/// A unique identifier of the SBOM in the format of a UUID
+fn serial_number(options: &BundleOptions, timestamp: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(options.app_name().as_bytes());
+    hasher.update(options.version().to_string().as_bytes());
+    hasher.update(options.target().to_string().as_bytes());
+    hasher.update(timestamp.as_bytes());
+    let hex = checksums::to_hex(&hasher.finalize()[..16]);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Run git in a checkout found by [`library_repositories`]
+fn git(repository: &Path, arguments: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(repository)
+        .args(arguments)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.is_empty() {
+        None
+    } else {
+        Some(output)
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::archives::zip_timestamp;
+use crate::bundlers::elf::ElfFile;
+use crate::bundlers::linux::LinuxBundler;
+use crate::bundlers::mac::MacBundler;
+use crate::bundlers::windows::WindowsBundler;
+use crate::bundlers::Bundler;
+use crate::checksums::{files_in_directory, relative_path, to_hex};
+use crate::events::Event;
+use crate::{events, BundleOptions, Context, Error, Result};
+
+/// A name of the index of the debug symbols at the root of the symbols archive
+pub const SYMBOLS_INDEX_FILE: &str = "index.json";
+
+const MACH_O_MAGIC_32: u32 = 0xfeedface;
+const MACH_O_MAGIC_64: u32 = 0xfeedfacf;
+const MACH_O_FAT_MAGIC: u32 = 0xcafebabe;
+const MACH_O_LOAD_COMMAND_UUID: u32 = 0x1b;
+const PE_DEBUG_DIRECTORY_INDEX: usize = 6;
+const PE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+const CODEVIEW_PDB70_SIGNATURE: &[u8] = b"RSDS";
+
+/// How a debugger or crash analysis matches a binary with its debug symbols
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "id", rename_all = "kebab-case")]
+pub enum SymbolsId {
+    /// A GNU build-id of an ELF binary as lowercase hex
+    BuildId(String),
+    /// An `LC_UUID` of a Mach-O binary, one per architecture of a universal binary
+    MachOUuid(String),
+    /// A GUID and age of the PDB referenced by a PE binary, in the format of symbol servers
+    #[serde(rename = "pdb")]
+    PdbGuidAge(String),
+}
+
+/// A bundled binary and its debug symbols, by their paths within the bundle and the archive
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolsIndexEntry {
+    pub binary: String,
+    #[serde(flatten)]
+    pub id: SymbolsId,
+    pub symbols: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SymbolsIndex {
+    app_name: String,
+    version: String,
+    target: String,
+    entries: Vec<SymbolsIndexEntry>,
+}
+
+/// Archive the debug symbols that were separated from the bundle together with an index of the binaries they belong to
+pub fn write_symbols_archive(bundler: &dyn Bundler, options: &BundleOptions) -> Result<()> {
+    if !options.symbols_archive() {
+        return Ok(());
+    }
+    let symbols_directory = options.symbols_directory();
+    let app_directory = bundler.bundled_app_directory(options);
+    let symbols = if symbols_directory.is_dir() {
+        files_in_directory(&symbols_directory)?
+    } else {
+        vec![]
+    };
+
+    let index = SymbolsIndex {
+        app_name: options.app_name().to_string(),
+        version: options.version().to_string(),
+        target: options.target().to_string(),
+        entries: symbols_index(
+            &app_directory,
+            &symbols_directory,
+            &bundler.bundled_binary_names(options)?,
+        )?,
+    };
+
+    let archive = options.symbols_archive_path();
+    let file = File::create(&archive)
+        .context(|| Error::io(format!("Could not create {}", archive.display())))?;
+    let mut zip = ZipWriter::new(file);
+    let file_options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(zip_timestamp(options.source_date_epoch()))
+        .unix_permissions(0o644);
+
+    zip.start_file(SYMBOLS_INDEX_FILE, file_options)?;
+    zip.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+    for each in &symbols {
+        let size = each.metadata()?.len();
+        zip.start_file(
+            relative_path(&symbols_directory, each),
+            file_options.large_file(size > u32::MAX as u64),
+        )?;
+        let mut symbols_file =
+            File::open(each).context(|| Error::io(format!("Could not open {}", each.display())))?;
+        std::io::copy(&mut symbols_file, &mut zip)?;
+    }
+    zip.finish()?;
+
+    events::emit(Event::archive_created(&archive));
+    Ok(())
+}
+
+/// Find the identifiers of the bundled executables and libraries with the given file names
+/// whose debug symbols are in the symbols directory.
+/// Binaries that can not be read or parsed are treated as having no identifier
+pub fn symbols_index(
+    app_directory: &Path,
+    symbols_directory: &Path,
+    binary_names: &BTreeSet<OsString>,
+) -> Result<Vec<SymbolsIndexEntry>> {
+    if !app_directory.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let binaries = files_in_directory(app_directory)?
+        .into_iter()
+        .filter(|file| {
+            file.file_name()
+                .map_or(false, |name| binary_names.contains(name))
+        });
+
+    let mut entries = vec![];
+    for binary in binaries {
+        let binary_path = relative_path(app_directory, &binary);
+        // the symbols of a binary are at the same path within the symbols directory as they would be in the bundle
+        let symbols_of = |bundled_symbols: PathBuf| {
+            Some(symbols_directory.join(relative_path(app_directory, bundled_symbols)))
+                .filter(|symbols| symbols.exists())
+                .map(|symbols| relative_path(symbols_directory, symbols))
+        };
+
+        if let Some(mut elf) = ElfFile::open(&binary).ok().flatten() {
+            let build_id = elf.build_id().ok().flatten();
+            let symbols = symbols_of(LinuxBundler::debug_file(
+                app_directory,
+                &binary,
+                build_id.as_deref(),
+            ));
+            if let (Some(build_id), Some(symbols)) = (build_id, symbols) {
+                entries.push(SymbolsIndexEntry {
+                    binary: binary_path,
+                    id: SymbolsId::BuildId(build_id),
+                    symbols,
+                });
+            }
+            continue;
+        }
+
+        let uuids = mach_o_uuids(&binary).unwrap_or_default();
+        if !uuids.is_empty() {
+            if let Some(symbols) = MacBundler::debug_symbol_file(&binary)
+                .ok()
+                .and_then(&symbols_of)
+            {
+                for uuid in uuids {
+                    entries.push(SymbolsIndexEntry {
+                        binary: binary_path.clone(),
+                        id: SymbolsId::MachOUuid(uuid),
+                        symbols: symbols.clone(),
+                    });
+                }
+            }
+            continue;
+        }
+
+        if let Some((guid_age, pdb_name)) = pdb_guid_age(&binary).ok().flatten() {
+            let symbols = pdb_name
+                .and_then(|pdb_name| symbols_of(binary.with_file_name(pdb_name)))
+                .or_else(|| symbols_of(WindowsBundler::debug_symbol_file(&binary)));
+            if let Some(symbols) = symbols {
+                entries.push(SymbolsIndexEntry {
+                    binary: binary_path,
+                    id: SymbolsId::PdbGuidAge(guid_age),
+                    symbols,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// The UUIDs of a Mach-O binary, uppercase and separated with dashes as printed by `dwarfdump --uuid`
+fn mach_o_uuids(binary: &Path) -> Result<Vec<String>> {
+    let mut file =
+        File::open(binary).context(|| Error::io(format!("Could not open {}", binary.display())))?;
+    let magic = match read_at(&mut file, 0, 8) {
+        Ok(header) => header,
+        Err(_) => return Ok(vec![]),
+    };
+
+    // universal binaries use big endian headers, java class files share the magic but have a large version there
+    let architectures = u32::from_be_bytes([magic[4], magic[5], magic[6], magic[7]]);
+    if u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]) == MACH_O_FAT_MAGIC
+        && architectures < 32
+    {
+        let headers = read_at(&mut file, 8, architectures as u64 * 20)?;
+        let mut uuids = vec![];
+        for architecture in headers.chunks(20) {
+            let offset = u32::from_be_bytes([
+                architecture[8],
+                architecture[9],
+                architecture[10],
+                architecture[11],
+            ]);
+            uuids.extend(thin_mach_o_uuid(&mut file, offset as u64)?);
+        }
+        return Ok(uuids);
+    }
+
+    Ok(thin_mach_o_uuid(&mut file, 0)?.into_iter().collect())
+}
+
+fn thin_mach_o_uuid(file: &mut File, offset: u64) -> Result<Option<String>> {
+    let header = match read_at(file, offset, 32) {
+        Ok(header) => header,
+        Err(_) => return Ok(None),
+    };
+    let header_size = match u32_le(&header, 0) {
+        MACH_O_MAGIC_64 => 32,
+        MACH_O_MAGIC_32 => 28,
+        _ => return Ok(None),
+    };
+    let commands_count = u32_le(&header, 16);
+    let commands_size = u32_le(&header, 20);
+    let commands = read_at(file, offset + header_size, commands_size as u64)?;
+
+    let mut position = 0;
+    for _ in 0..commands_count {
+        if position + 8 > commands.len() {
+            break;
+        }
+        let command = u32_le(&commands, position);
+        let command_size = u32_le(&commands, position + 4) as usize;
+        if command == MACH_O_LOAD_COMMAND_UUID && position + 24 <= commands.len() {
+            let uuid = to_hex(&commands[position + 8..position + 24]).to_uppercase();
+            return Ok(Some(format!(
+                "{}-{}-{}-{}-{}",
+                &uuid[0..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..32]
+            )));
+        }
+        if command_size == 0 {
+            break;
+        }
+        position += command_size;
+    }
+    Ok(None)
+}
+
+/// The GUID and age of the PDB referenced from the CodeView debug directory of a PE binary,
+/// formatted as the key of a symbol server, together with the file name of the PDB
+fn pdb_guid_age(binary: &Path) -> Result<Option<(String, Option<String>)>> {
+    let mut file =
+        File::open(binary).context(|| Error::io(format!("Could not open {}", binary.display())))?;
+    let dos_header = match read_at(&mut file, 0, 64) {
+        Ok(header) if header.starts_with(b"MZ") => header,
+        _ => return Ok(None),
+    };
+    let pe_offset = u32_le(&dos_header, 0x3c) as u64;
+    let coff_header = match read_at(&mut file, pe_offset, 24) {
+        Ok(header) if header.starts_with(b"PE\0\0") => header,
+        _ => return Ok(None),
+    };
+    let sections_count = u16_le(&coff_header, 6) as u64;
+    let optional_header_size = u16_le(&coff_header, 20) as u64;
+    let optional_header = read_at(&mut file, pe_offset + 24, optional_header_size)?;
+    if optional_header.len() < 2 {
+        return Ok(None);
+    }
+    let data_directories = match u16_le(&optional_header, 0) {
+        0x10b => 96,
+        0x20b => 112,
+        _ => return Ok(None),
+    };
+    let debug_directory = data_directories + PE_DEBUG_DIRECTORY_INDEX * 8;
+    if debug_directory + 8 > optional_header.len() {
+        return Ok(None);
+    }
+    let debug_address = u32_le(&optional_header, debug_directory);
+    let debug_size = u32_le(&optional_header, debug_directory + 4);
+    if debug_address == 0 || debug_size == 0 {
+        return Ok(None);
+    }
+
+    // find the file offset of the debug directory in the section that contains its address
+    let sections = read_at(
+        &mut file,
+        pe_offset + 24 + optional_header_size,
+        sections_count * 40,
+    )?;
+    let debug_offset = sections.chunks(40).find_map(|section| {
+        let virtual_size = u32_le(section, 8);
+        let virtual_address = u32_le(section, 12);
+        let raw_offset = u32_le(section, 20);
+        let section_end = virtual_address.checked_add(virtual_size)?;
+        if debug_address < virtual_address || debug_address >= section_end {
+            return None;
+        }
+        debug_address
+            .checked_sub(virtual_address)?
+            .checked_add(raw_offset)
+            .map(|offset| offset as u64)
+    });
+    let debug_offset = match debug_offset {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+
+    let entries = read_at(&mut file, debug_offset, debug_size as u64)?;
+    for entry in entries.chunks_exact(28) {
+        if u32_le(entry, 12) != PE_DEBUG_TYPE_CODEVIEW {
+            continue;
+        }
+        let record = read_at(
+            &mut file,
+            u32_le(entry, 24) as u64,
+            u32_le(entry, 16) as u64,
+        )?;
+        if record.len() < 24 || !record.starts_with(CODEVIEW_PDB70_SIGNATURE) {
+            continue;
+        }
+        let guid = &record[4..20];
+        let age = u32_le(&record, 20);
+        let key = format!(
+            "{:08X}{:04X}{:04X}{}{:X}",
+            u32_le(guid, 0),
+            u16_le(guid, 4),
+            u16_le(guid, 6),
+            to_hex(&guid[8..16]).to_uppercase(),
+            age
+        );
+        let pdb_path = &record[24..];
+        let pdb_path = &pdb_path[..pdb_path
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(pdb_path.len())];
+        // the path is where the PDB was written when linking, possibly on another platform
+        let pdb_name = String::from_utf8_lossy(pdb_path)
+            .rsplit(|character| character == '\\' || character == '/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string());
+        return Ok(Some((key, pdb_name)));
+    }
+    Ok(None)
+}
+
+/// Read the given number of bytes, failing instead of allocating more than the file contains
+fn read_at(file: &mut File, offset: u64, size: u64) -> std::io::Result<Vec<u8>> {
+    let length = file.metadata()?.len();
+    if offset.checked_add(size).map_or(true, |end| end > length) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "the data ends past the end of the file",
+        ));
+    }
+    let mut data = vec![0u8; size as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn u16_le(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: [u8; 16] = [
+        0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+
+    fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// A PE32+ file with one section that holds a CodeView debug directory entry and its PDB 7.0 record
+    fn pe_file(pdb_path: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; 0x300];
+        data[..2].copy_from_slice(b"MZ");
+        put_u32(&mut data, 0x3c, 0x40);
+
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        put_u16(&mut data, 0x46, 1);
+        put_u16(&mut data, 0x54, 240);
+
+        let optional_header = 0x58;
+        put_u16(&mut data, optional_header, 0x20b);
+        let debug_directory = optional_header + 112 + PE_DEBUG_DIRECTORY_INDEX * 8;
+        put_u32(&mut data, debug_directory, 0x1000);
+        put_u32(&mut data, debug_directory + 4, 28);
+
+        let section = optional_header + 240;
+        put_u32(&mut data, section + 8, 0x100);
+        put_u32(&mut data, section + 12, 0x1000);
+        put_u32(&mut data, section + 20, 0x200);
+
+        let mut record = CODEVIEW_PDB70_SIGNATURE.to_vec();
+        record.extend_from_slice(&GUID);
+        record.extend_from_slice(&2u32.to_le_bytes());
+        record.extend_from_slice(pdb_path);
+        record.push(0);
+
+        put_u32(&mut data, 0x200 + 12, PE_DEBUG_TYPE_CODEVIEW);
+        put_u32(&mut data, 0x200 + 16, record.len() as u32);
+        put_u32(&mut data, 0x200 + 24, 0x240);
+        data[0x240..0x240 + record.len()].copy_from_slice(&record);
+        data
+    }
+
+    fn test_file(name: &str, content: &[u8]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "gtoolkit-vm-builder-symbols-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join(name);
+        std::fs::write(&file, content).unwrap();
+        file
+    }
+
+    #[test]
+    fn pdb_guid_age_and_name() {
+        let file = test_file("with-pdb.exe", &pe_file(b"C:\\build\\release\\vm.pdb"));
+        assert_eq!(
+            pdb_guid_age(&file).unwrap(),
+            Some((
+                "00112233445566778899AABBCCDDEEFF2".to_string(),
+                Some("vm.pdb".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn pdb_name_of_unix_path() {
+        let file = test_file("with-unix-pdb.exe", &pe_file(b"/build/release/vm.pdb"));
+        let (_, pdb_name) = pdb_guid_age(&file).unwrap().unwrap();
+        assert_eq!(pdb_name, Some("vm.pdb".to_string()));
+    }
+
+    #[test]
+    fn pdb_of_binary_without_debug_directory() {
+        let mut data = pe_file(b"vm.pdb");
+        put_u32(&mut data, 0x58 + 112 + PE_DEBUG_DIRECTORY_INDEX * 8, 0);
+        let file = test_file("without-debug-directory.exe", &data);
+        assert_eq!(pdb_guid_age(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn pdb_of_non_pe_file() {
+        let file = test_file("not-pe.exe", b"#!/bin/sh\necho hello\n");
+        assert_eq!(pdb_guid_age(&file).unwrap(), None);
+    }
+}